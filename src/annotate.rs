@@ -0,0 +1,202 @@
+//! Adding computed values to track points as extensions.
+
+use crate::JoinConfig;
+use crate::geo::{haversine_distance, initial_bearing};
+use crate::point::{format_number, TrackPoint};
+use crate::schema;
+use crate::summary::TrackSummary;
+use crate::tree::Element;
+
+/// Add the extensions asked for in the config to each point of a `<trk>` element.
+pub(crate) fn annotate_track(trk: &mut Element, config: &JoinConfig) {
+    if config.speed_extension.is_none() && !config.distance_extension && !config.bearing_extension
+    {
+        return;
+    }
+    // Distance along the track to the last point.
+    let mut distance = 0.;
+    for seg in trk.elements_mut().filter(|el| el.name() == b"trkseg") {
+        let points = seg.elements()
+            .filter(|el| el.name() == b"trkpt")
+            .map(TrackPoint::from_element)
+            .collect::<Vec<_>>();
+        let speeds = match config.speed_extension {
+            Some(_) => speeds(&points),
+            None => vec![],
+        };
+        let bearings = if config.bearing_extension { bearings(&points) } else { vec![] };
+        if !config.distance_across_segments {
+            distance = 0.;
+        }
+        let mut prev = None;
+        let pts = seg.elements_mut().filter(|el| el.name() == b"trkpt");
+        for (i, pt) in pts.enumerate() {
+            if let (Some(name), Some(&Some(speed))) = (&config.speed_extension, speeds.get(i)) {
+                set_extension(pt, name, &format_number(speed, 2));
+            }
+            if let (true, Some(point)) = (config.distance_extension, points[i]) {
+                if let Some(prev) = prev {
+                    distance += haversine_distance(&prev, &point);
+                }
+                prev = Some(point);
+                set_extension(pt, "dist", &format_number(distance, 1));
+            }
+            if let Some(&Some(bearing)) = bearings.get(i) {
+                set_extension(pt, "bearing", &format_number(bearing, 1));
+            }
+        }
+    }
+}
+
+/// The speed at each point in m/s, going by the distance and time to the next point that has a
+/// time, or from the previous one for the last. None for points without a time, or without a
+/// neighbor at a different time.
+fn speeds(points: &[Option<TrackPoint>]) -> Vec<Option<f64>> {
+    let timed = points.iter()
+        .enumerate()
+        .filter_map(|(i, p)| p.filter(|p| p.time.is_some()).map(|p| (i, p)))
+        .collect::<Vec<_>>();
+    let mut speeds = vec![None; points.len()];
+    for (j, &(i, point)) in timed.iter().enumerate() {
+        let (a, b) = match (timed.get(j + 1), j.checked_sub(1).map(|k| timed[k])) {
+            (Some(&(_, next)), _) => (point, next),
+            (None, Some((_, prev))) => (prev, point),
+            (None, None) => continue,
+        };
+        let seconds = (b.time.unwrap().0 - a.time.unwrap().0) as f64 / 1000.;
+        if seconds > 0. {
+            speeds[i] = Some(haversine_distance(&a, &b) / seconds);
+        }
+    }
+    speeds
+}
+
+/// The compass bearing in degrees from each point to the next one, or from the previous one for
+/// the last. It's 0 between points in the same place. None for points with missing or invalid
+/// coordinates, or without any neighbors.
+fn bearings(points: &[Option<TrackPoint>]) -> Vec<Option<f64>> {
+    let valid = points.iter()
+        .enumerate()
+        .filter_map(|(i, p)| p.map(|p| (i, p)))
+        .collect::<Vec<_>>();
+    let mut bearings = vec![None; points.len()];
+    for (j, &(i, point)) in valid.iter().enumerate() {
+        let (a, b) = match (valid.get(j + 1), j.checked_sub(1).map(|k| valid[k])) {
+            (Some(&(_, next)), _) => (point, next),
+            (None, Some((_, prev))) => (prev, point),
+            (None, None) => continue,
+        };
+        let same_place = (a.lat, a.lon) == (b.lat, b.lon);
+        bearings[i] = Some(if same_place { 0. } else { initial_bearing(&a, &b) });
+    }
+    bearings
+}
+
+/// Add a `<summary>` of a `<trk>` element to its extensions, replacing any it already has. The
+/// duration is left out if the track has no times.
+pub(crate) fn add_summary(trk: &mut Element) {
+    let summary = TrackSummary::of(trk);
+    let mut el = Element::new("summary");
+    el.insert_child(Element::with_text("points", &summary.points.to_string()), &[]);
+    el.insert_child(Element::with_text("distance_m", &format_number(summary.distance, 1)), &[]);
+    if let Some((start, end)) = summary.time_range {
+        let seconds = (end.0 - start.0) as f64 / 1000.;
+        el.insert_child(Element::with_text("duration_s", &format_number(seconds, 3)), &[]);
+    }
+    match trk.child_mut(b"extensions") {
+        Some(extensions) if extensions.child(b"summary").is_some() => {
+            extensions.replace_children(b"summary", vec![el]);
+        }
+        Some(extensions) => extensions.insert_child(el, &[]),
+        None => {
+            let mut extensions = Element::new("extensions");
+            extensions.insert_child(el, &[]);
+            trk.insert_child(extensions, schema::TRK);
+        }
+    }
+}
+
+/// Set the text of an element inside a point's `<extensions>`, adding either of them as needed.
+fn set_extension(pt: &mut Element, name: &str, value: &str) {
+    match pt.child_mut(b"extensions") {
+        Some(extensions) => extensions.set_child_text(name, value, &[]),
+        None => {
+            let mut extensions = Element::new("extensions");
+            extensions.insert_child(Element::with_text(name, value), &[]);
+            pt.insert_child(extensions, schema::POINT);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Document;
+
+    /// The text of the named extension of each point.
+    fn annotate(gpx: &str, config: &JoinConfig, name: &[u8]) -> Vec<Option<String>> {
+        let mut doc = Document::read(gpx.as_bytes()).unwrap();
+        let trk = doc.root.child_mut(b"trk").unwrap();
+        annotate_track(trk, config);
+        trk.track_points()
+            .map(|pt| pt.child(b"extensions").and_then(|e| e.child(name)).map(|e| e.text()))
+            .collect()
+    }
+
+    #[test]
+    fn speed() {
+        // 0.001 degrees of latitude is about 111.2 meters.
+        let gpx = r#"<gpx><trk><trkseg>
+            <trkpt lat="47.000" lon="-121.0"><time>2021-08-27T10:00:00Z</time></trkpt>
+            <trkpt lat="47.001" lon="-121.0"><time>2021-08-27T10:00:10Z</time>
+                <extensions><other/></extensions></trkpt>
+            <trkpt lat="47.002" lon="-121.0"/>
+            <trkpt lat="47.004" lon="-121.0"><time>2021-08-27T10:00:30Z</time></trkpt>
+            <trkpt lat="47.005" lon="-121.0"><time>2021-08-27T10:00:50Z</time></trkpt>
+        </trkseg><trkseg>
+            <trkpt lat="47.000" lon="-121.0"><time>2021-08-27T10:00:00Z</time></trkpt>
+        </trkseg></trk></gpx>"#;
+        let config = JoinConfig { speed_extension: Some("speed".to_owned()), ..Default::default() };
+        let speed = |s: &str| Some(s.to_owned());
+        assert_eq!(annotate(gpx, &config, b"speed"),
+            [speed("11.12"), speed("16.68"), None, speed("5.56"), speed("5.56"), None]);
+    }
+
+    #[test]
+    fn distance() {
+        let gpx = r#"<gpx><trk><trkseg>
+            <trkpt lat="47.000" lon="-121.0"/>
+            <trkpt lat="47.001" lon="-121.0"/>
+            <trkpt lat="bogus" lon="-121.0"/>
+            <trkpt lat="47.003" lon="-121.0"/>
+        </trkseg><trkseg>
+            <trkpt lat="47.004" lon="-121.0"/>
+            <trkpt lat="47.005" lon="-121.0"/>
+        </trkseg></trk></gpx>"#;
+        let dist = |s: &str| Some(s.to_owned());
+        let config = JoinConfig { distance_extension: true, ..Default::default() };
+        assert_eq!(annotate(gpx, &config, b"dist"),
+            [dist("0"), dist("111.2"), None, dist("333.6"), dist("0"), dist("111.2")]);
+        let config = JoinConfig { distance_across_segments: true, ..config };
+        assert_eq!(annotate(gpx, &config, b"dist"),
+            [dist("0"), dist("111.2"), None, dist("333.6"), dist("333.6"), dist("444.8")]);
+    }
+
+    #[test]
+    fn bearing() {
+        let gpx = r#"<gpx><trk><trkseg>
+            <trkpt lat="47.000" lon="-121.000"/>
+            <trkpt lat="47.001" lon="-121.000"/>
+            <trkpt lat="47.001" lon="-121.000"/>
+            <trkpt lat="bogus" lon="-121.000"/>
+            <trkpt lat="47.001" lon="-120.999"/>
+            <trkpt lat="47.000" lon="-120.999"/>
+        </trkseg><trkseg>
+            <trkpt lat="47.000" lon="-121.000"/>
+        </trkseg></trk></gpx>"#;
+        let config = JoinConfig { bearing_extension: true, ..Default::default() };
+        let b = |s: &str| Some(s.to_owned());
+        assert_eq!(annotate(gpx, &config, b"bearing"),
+            [b("0"), b("0"), b("90"), None, b("180"), b("180"), None]);
+    }
+}
@@ -0,0 +1,116 @@
+use crate::geo::haversine_distance;
+use crate::point::TrackPoint;
+use crate::tree::{Document, Element};
+use std::io::BufRead;
+
+/// A geographic area to keep points within.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Area {
+    /// Everywhere within `radius` meters of a center point.
+    Circle { lat: f64, lon: f64, radius: f64 },
+    BoundingBox(BoundingBox),
+}
+
+/// A range of latitudes and longitudes, inclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lat: f64,
+    pub min_lon: f64,
+    pub max_lat: f64,
+    pub max_lon: f64,
+}
+
+impl BoundingBox {
+    /// Grow the box, if there is one yet, to include a point.
+    pub(crate) fn extend(bounds: &mut Option<BoundingBox>, p: &TrackPoint) {
+        *bounds = Some(match *bounds {
+            None => BoundingBox { min_lat: p.lat, min_lon: p.lon, max_lat: p.lat, max_lon: p.lon },
+            Some(b) => BoundingBox {
+                min_lat: b.min_lat.min(p.lat),
+                min_lon: b.min_lon.min(p.lon),
+                max_lat: b.max_lat.max(p.lat),
+                max_lon: b.max_lon.max(p.lon),
+            },
+        });
+    }
+
+    fn contains(&self, p: &TrackPoint) -> bool {
+        (self.min_lat ..= self.max_lat).contains(&p.lat)
+            && (self.min_lon ..= self.max_lon).contains(&p.lon)
+    }
+}
+
+impl Area {
+    /// The bounding box of all the waypoints, route points, and track points in a GPX document.
+    pub fn bounding_box_of<R: BufRead>(source: R) -> anyhow::Result<Area> {
+        fn visit(el: &Element, bounds: &mut Option<BoundingBox>) {
+            for child in el.elements() {
+                if matches!(child.name(), b"wpt" | b"rtept" | b"trkpt") {
+                    if let Some(p) = TrackPoint::from_element(child) {
+                        BoundingBox::extend(bounds, &p);
+                    }
+                } else {
+                    visit(child, bounds);
+                }
+            }
+        }
+
+        let doc = Document::read(source)?;
+        let mut bounds = None;
+        visit(&doc.root, &mut bounds);
+        match bounds {
+            Some(bounds) => Ok(Area::BoundingBox(bounds)),
+            None => anyhow::bail!("no points to take the bounding box of"),
+        }
+    }
+
+    fn contains(&self, point: &TrackPoint) -> bool {
+        match *self {
+            Area::Circle { lat, lon, radius } => {
+                let center = TrackPoint { lat, lon, ele: None, time: None };
+                haversine_distance(&center, point) <= radius
+            }
+            Area::BoundingBox(ref bounds) => bounds.contains(point),
+        }
+    }
+
+    fn contains_element(&self, el: &Element) -> bool {
+        TrackPoint::from_element(el).is_some_and(|point| self.contains(&point))
+    }
+}
+
+/// Remove track points outside the area from a top-level element. Returns false if the whole
+/// element should be removed, which is the case for waypoints outside it.
+///
+/// Segments left empty are kept.
+pub(crate) fn filter_element(el: &mut Element, area: &Area) -> bool {
+    match el.name() {
+        b"wpt" => area.contains_element(el),
+        b"trk" => {
+            for seg in el.elements_mut().filter(|el| el.name() == b"trkseg") {
+                seg.retain_elements(|pt| pt.name() != b"trkpt" || area.contains_element(pt));
+            }
+            true
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounding_box() {
+        let gpx = r#"<gpx>
+    <wpt lat="47.5" lon="-121.5"/>
+    <rte><rtept lat="47.6" lon="-121.7"/></rte>
+    <trk><trkseg><trkpt lat="47.4" lon="-121.6"/><trkpt lat="bogus" lon="0"/></trkseg></trk>
+</gpx>"#;
+        assert_eq!(Area::bounding_box_of(gpx.as_bytes()).unwrap(),
+            Area::BoundingBox(BoundingBox {
+                min_lat: 47.4, min_lon: -121.7, max_lat: 47.6, max_lon: -121.5,
+            }));
+        assert!(Area::bounding_box_of("<gpx><trk/></gpx>".as_bytes()).is_err());
+    }
+}
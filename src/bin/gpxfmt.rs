@@ -0,0 +1,92 @@
+use anyhow::{anyhow, bail, Context as _};
+use gpxjoin::pretty_print;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+const DEFAULT_INDENT: usize = 2;
+
+fn print_help() {
+    eprintln!("gpxfmt v{} (c) 2021 {}",
+        env!("CARGO_PKG_VERSION"),
+        env!("CARGO_PKG_AUTHORS"));
+    eprintln!("usage: {} [options] <file.gpx>",
+        std::env::args().next().unwrap());
+    eprintln!("Rewrites a GPX file with consistent indentation.\n\
+        Writes result to standard output.\n\
+        \n\
+        options:\n  \
+        -o FILE, --output=FILE\n                        \
+        write to FILE instead of standard output\n  \
+        --indent=SPACES       indent each level by SPACES spaces (default: {})",
+        DEFAULT_INDENT);
+}
+
+struct Args {
+    path: PathBuf,
+    output: Option<PathBuf>,
+    indent: usize,
+}
+
+/// Parse the arguments, or return None if help was asked for.
+fn parse_args(argv: impl Iterator<Item = OsString>) -> anyhow::Result<Option<Args>> {
+    let mut path = None;
+    let mut output = None;
+    let mut indent = DEFAULT_INDENT;
+    let mut argv = argv.skip(1);
+    while let Some(arg) = argv.next() {
+        let s = match arg.to_str() {
+            Some(s) if s.starts_with('-') && s != "-" => s,
+            _ => {
+                if path.replace(PathBuf::from(arg)).is_some() {
+                    bail!("gpxfmt takes a single file");
+                }
+                continue;
+            }
+        };
+        // Options can take a value either as "--name=value" or as "--name value".
+        let (flag, mut inline_value) = match s.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_owned())),
+            _ => (s, None),
+        };
+        let mut value = || -> anyhow::Result<String> {
+            match inline_value.take().or_else(|| argv.next().and_then(|v| v.into_string().ok())) {
+                Some(value) => Ok(value),
+                None => bail!("{} requires a value", flag),
+            }
+        };
+        match flag {
+            "-h" | "--help" | "-V" | "--version" => return Ok(None),
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            "--indent" => {
+                indent = value()?.parse()
+                    .map_err(|_| anyhow!("--indent must be a number of spaces"))?;
+            }
+            _ => bail!("unknown option {:?}", s),
+        }
+    }
+    let path = path.ok_or_else(|| anyhow!("need a file to format"))?;
+    Ok(Some(Args { path, output, indent }))
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = match parse_args(std::env::args_os())? {
+        Some(args) => args,
+        None => {
+            print_help();
+            std::process::exit(1);
+        }
+    };
+    let source = BufReader::new(File::open(&args.path)
+        .with_context(|| format!("failed to open {:?}", args.path))?);
+    let dest: Box<dyn Write> = match args.output {
+        Some(ref path) => Box::new(File::create(path)
+            .with_context(|| format!("failed to create {:?}", path))?),
+        None => Box::new(io::stdout()),
+    };
+    let mut dest = BufWriter::new(dest);
+    pretty_print(source, &mut dest, args.indent)
+        .with_context(|| format!("failed to format {:?}", args.path))?;
+    Ok(dest.flush()?)
+}
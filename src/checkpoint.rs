@@ -0,0 +1,84 @@
+use anyhow::{anyhow, Context as _};
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// How far a join got, so that it can be picked up from there if it's interrupted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// How many of the sources have been joined.
+    pub sources_done: usize,
+    /// How many bytes had been written to the output once they were.
+    pub bytes_written: u64,
+}
+
+/// Written as the two numbers on one line, for keeping in a state file.
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.sources_done, self.bytes_written)
+    }
+}
+
+impl FromStr for Checkpoint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Checkpoint> {
+        let mut fields = s.split_whitespace();
+        let (sources_done, bytes_written) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(sources), Some(bytes), None) => (sources, bytes),
+            _ => return Err(anyhow!("invalid checkpoint {:?}", s)),
+        };
+        Ok(Checkpoint {
+            sources_done: sources_done.parse().context("invalid checkpoint source count")?,
+            bytes_written: bytes_written.parse().context("invalid checkpoint byte count")?,
+        })
+    }
+}
+
+/// Counts the bytes written through it, and can be told to throw them away instead, for going
+/// back over output that was already written before resuming.
+pub(crate) struct ProgressWriter<W> {
+    inner: W,
+    pub written: u64,
+    pub discard: bool,
+}
+
+impl<W: Write> ProgressWriter<W> {
+    pub fn new(inner: W, resume: Option<Checkpoint>) -> Self {
+        ProgressWriter {
+            inner,
+            written: resume.map_or(0, |c| c.bytes_written),
+            discard: resume.is_some(),
+        }
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.discard {
+            return Ok(buf.len());
+        }
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let checkpoint = Checkpoint { sources_done: 3, bytes_written: 12345 };
+        assert_eq!(checkpoint.to_string().parse::<Checkpoint>().unwrap(), checkpoint);
+        assert_eq!("3 12345\n".parse::<Checkpoint>().unwrap(), checkpoint);
+        assert!("3".parse::<Checkpoint>().is_err());
+        assert!("3 12345 6".parse::<Checkpoint>().is_err());
+        assert!("-3 12345".parse::<Checkpoint>().is_err());
+    }
+}
@@ -0,0 +1,38 @@
+use crate::tree::{Element, Node};
+
+/// Conversions between waypoints and route points.
+///
+/// Waypoints and route points have the same attributes and children in the GPX schema, so
+/// converting between them is just a matter of renaming elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointConversion {
+    /// Turn each `<wpt>` into a `<rte>` holding a single `<rtept>`.
+    WptToRtept,
+    /// Turn each `<rtept>` in a route into a standalone `<wpt>`. The routes themselves are
+    /// removed.
+    RteptToWpt,
+}
+
+/// Convert a top-level element. It turns into any number of elements.
+pub(crate) fn convert_points(mut el: Element, conversion: PointConversion) -> Vec<Element> {
+    match (conversion, el.name()) {
+        (PointConversion::WptToRtept, b"wpt") => {
+            el.rename("rtept");
+            let mut rte = Element::new("rte");
+            rte.children.push(Node::Element(el));
+            vec![rte]
+        }
+        (PointConversion::RteptToWpt, b"rte") => {
+            el.children.into_iter()
+                .filter_map(|node| match node {
+                    Node::Element(mut pt) if pt.name() == b"rtept" => {
+                        pt.rename("wpt");
+                        Some(pt)
+                    }
+                    _ => None,
+                })
+                .collect()
+        }
+        _ => vec![el],
+    }
+}
@@ -0,0 +1,61 @@
+//! Cleaning up the `creator` attribute of GPX files, which apps fill in inconsistently.
+
+/// Canonical names for apps, by their name once cleaned up and lowercased.
+const CREATORS: &[(&str, &str)] = &[
+    ("garmin connect", "Garmin Connect"),
+    ("garmin desktop app", "Garmin Desktop App"),
+    ("komoot", "komoot"),
+    ("osmand", "OsmAnd"),
+    ("osmand+", "OsmAnd"),
+    ("osmand~", "OsmAnd"),
+    ("ride with gps", "Ride with GPS"),
+    ("ridewithgps", "Ride with GPS"),
+    ("strava", "Strava"),
+    ("stravagpx", "Strava"),
+    ("strava gpx", "Strava"),
+    ("wahoo", "Wahoo"),
+    ("wahoo fitness", "Wahoo"),
+];
+
+/// Clean up a creator: underscores become spaces, runs of spaces become one, and version numbers
+/// (like `v7.23.1` or `4.0`) are left out. The result is then replaced with the canonical name of
+/// the app, if it's one of the known ones. A creator that's nothing but a version is kept.
+pub(crate) fn normalize_creator(creator: &str) -> String {
+    let cleaned = creator.split(|c: char| c.is_whitespace() || c == '_')
+        .filter(|word| !word.is_empty() && !is_version(word))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if cleaned.is_empty() {
+        return creator.trim().to_owned();
+    }
+    let key = cleaned.to_lowercase();
+    match CREATORS.iter().find(|(name, _)| *name == key) {
+        Some((_, canonical)) => canonical.to_string(),
+        None => cleaned,
+    }
+}
+
+fn is_version(word: &str) -> bool {
+    let number = word.strip_prefix(|c| c == 'v' || c == 'V').unwrap_or(word);
+    number.starts_with(|c: char| c.is_ascii_digit())
+        && number.chars().all(|c| c.is_ascii_digit() || c == '.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize() {
+        for creator in ["Garmin Connect", "Garmin_Connect", "Garmin Connect v7.23.1",
+            "  garmin  connect 7 "]
+        {
+            assert_eq!(normalize_creator(creator), "Garmin Connect");
+        }
+        assert_eq!(normalize_creator("OsmAnd~ 4.0.8"), "OsmAnd");
+        assert_eq!(normalize_creator("StravaGPX"), "Strava");
+        assert_eq!(normalize_creator("My_Tracker  V2 (beta)"), "My Tracker (beta)");
+        assert_eq!(normalize_creator("Vista 3"), "Vista");
+        assert_eq!(normalize_creator(" 2.0"), "2.0");
+    }
+}
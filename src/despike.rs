@@ -0,0 +1,75 @@
+use crate::tree::Element;
+
+/// Remove track points whose elevation jumps from the previous point's by more than `max_change`
+/// meters and then comes back again at the next point, like barometric altimeter glitches do.
+///
+/// Looking ahead one point keeps steep but real changes: if the next point stays near the new
+/// elevation, the point is kept. Points without an elevation are kept and don't count as
+/// neighbors.
+pub(crate) fn remove_spikes(trk: &mut Element, max_change: f64) {
+    let trk_name = trk.track_name();
+    for seg in trk.elements_mut().filter(|el| el.name() == b"trkseg") {
+        let elevations = seg.elements()
+            .filter(|el| el.name() == b"trkpt")
+            .map(elevation)
+            .collect::<Vec<_>>();
+        let mut prev: Option<f64> = None;
+        let mut i = 0;
+        seg.retain_elements(|pt| {
+            if pt.name() != b"trkpt" {
+                return true;
+            }
+            i += 1;
+            let ele = match elevations[i - 1] {
+                Some(ele) => ele,
+                None => return true,
+            };
+            let next = elevations[i ..].iter().flatten().next();
+            if let (Some(prev), Some(next)) = (prev, next) {
+                if (ele - prev).abs() > max_change && (next - prev).abs() <= max_change {
+                    info!("removed elevation spike of {}m in track {:?}", ele - prev,
+                        trk_name.as_deref().unwrap_or_default());
+                    return false;
+                }
+            }
+            prev = Some(ele);
+            true
+        });
+    }
+}
+
+/// The elevation of a point, if it has a valid one.
+pub(crate) fn elevation(pt: &Element) -> Option<f64> {
+    pt.child(b"ele")
+        .and_then(|ele| ele.text().trim().parse::<f64>().ok())
+        .filter(|ele| ele.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Document;
+
+    fn despike(elevations: &[&str], max_change: f64) -> Vec<String> {
+        let points = elevations.iter()
+            .map(|ele| format!(r#"<trkpt lat="47" lon="-121"><ele>{}</ele></trkpt>"#, ele))
+            .collect::<String>();
+        let gpx = format!("<gpx><trk><trkseg>{}</trkseg></trk></gpx>", points);
+        let mut doc = Document::read(gpx.as_bytes()).unwrap();
+        let trk = doc.root.child_mut(b"trk").unwrap();
+        remove_spikes(trk, max_change);
+        trk.track_points().map(|pt| pt.child(b"ele").unwrap().text()).collect()
+    }
+
+    #[test]
+    fn spike() {
+        assert_eq!(despike(&["100", "101", "601", "102", "100"], 50.),
+            ["100", "101", "102", "100"]);
+        assert_eq!(despike(&["100", "101", "-900", "bogus", "102"], 50.),
+            ["100", "101", "bogus", "102"]);
+        // Steep, but not a spike.
+        assert_eq!(despike(&["100", "200", "300", "300"], 50.), ["100", "200", "300", "300"]);
+        // Nothing to compare the last point with.
+        assert_eq!(despike(&["100", "100", "600"], 50.), ["100", "100", "600"]);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{JoinConfig, Timestamp};
+use crate::tree::Document;
+use std::fmt;
+use std::io::BufRead;
+
+/// How far apart, in milliseconds, the start or end times of two tracks with the same name can be
+/// before they're considered different.
+const TIME_TOLERANCE: i64 = 60_000;
+
+/// How a track in a subsequent file compares to the first file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackChange {
+    /// There's no track by this name in the first file.
+    Added,
+    /// The first file has a track by this name, but covering a different time range.
+    Changed,
+    /// The first file has a track by this name covering the same time range.
+    Unchanged,
+}
+
+impl fmt::Display for TrackChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            TrackChange::Added => "added",
+            TrackChange::Changed => "changed",
+            TrackChange::Unchanged => "unchanged",
+        })
+    }
+}
+
+/// A track in one of the subsequent files, compared to the first file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackDiff {
+    /// Index of the file the track is in.
+    pub source: usize,
+    pub name: Option<String>,
+    pub change: TrackChange,
+    /// Time range of the track.
+    pub start: Option<Timestamp>,
+    pub end: Option<Timestamp>,
+}
+
+/// Compare the tracks in each source after the first one against the tracks in the first one.
+///
+/// Tracks are matched up by name, case-insensitively unless `config.case_sensitive_names` is set.
+/// Tracks without a name always count as added.
+pub fn diff_tracks<R: BufRead>(sources: &mut [R], config: &JoinConfig)
+    -> anyhow::Result<Vec<TrackDiff>>
+{
+    let key = |name: String| if config.case_sensitive_names { name } else { name.to_lowercase() };
+    let mut base = vec![];
+    let mut result = vec![];
+    for (source, reader) in sources.iter_mut().enumerate() {
+        let doc = Document::read(reader)?;
+        for trk in doc.root.elements().filter(|el| el.name() == b"trk") {
+            let name = trk.track_name();
+            let (start, end) = (trk.track_start_time(), trk.track_end_time());
+            if source == 0 {
+                if let Some(name) = name {
+                    base.push((key(name), start, end));
+                }
+                continue;
+            }
+            let same_time = |a: Option<Timestamp>, b: Option<Timestamp>| match (a, b) {
+                (Some(a), Some(b)) => (a.0 - b.0).abs() <= TIME_TOLERANCE,
+                (a, b) => a == b,
+            };
+            let change = match name.clone().and_then(|name| {
+                let name = key(name);
+                base.iter().find(|(base_name, ..)| *base_name == name)
+            }) {
+                None => TrackChange::Added,
+                Some(&(_, base_start, base_end))
+                    if same_time(start, base_start) && same_time(end, base_end) =>
+                {
+                    TrackChange::Unchanged
+                }
+                Some(_) => TrackChange::Changed,
+            };
+            result.push(TrackDiff { source, name, change, start, end });
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(name: &str, start: &str, end: &str) -> String {
+        format!("<trk><name>{}</name><trkseg>\
+            <trkpt lat=\"1\" lon=\"1\"><time>{}</time></trkpt>\
+            <trkpt lat=\"1\" lon=\"1\"><time>{}</time></trkpt>\
+            </trkseg></trk>", name, start, end)
+    }
+
+    #[test]
+    fn diff() {
+        let base = format!("<gpx>{}{}</gpx>",
+            track("Morning", "2021-08-27T08:00:00Z", "2021-08-27T09:00:00Z"),
+            track("Evening", "2021-08-27T18:00:00Z", "2021-08-27T19:00:00Z"));
+        let new = format!("<gpx>{}{}{}<trk/></gpx>",
+            track("morning", "2021-08-27T08:00:30Z", "2021-08-27T09:00:00Z"),
+            track("Evening", "2021-08-27T18:00:00Z", "2021-08-27T20:00:00Z"),
+            track("Night", "2021-08-27T22:00:00Z", "2021-08-27T23:00:00Z"));
+        let mut sources = [base.as_bytes(), new.as_bytes()];
+        let changes = diff_tracks(&mut sources, &JoinConfig::default()).unwrap()
+            .into_iter()
+            .map(|d| (d.name, d.change))
+            .collect::<Vec<_>>();
+        assert_eq!(changes, vec![
+            (Some("morning".to_owned()), TrackChange::Unchanged),
+            (Some("Evening".to_owned()), TrackChange::Changed),
+            (Some("Night".to_owned()), TrackChange::Added),
+            (None, TrackChange::Added),
+        ]);
+    }
+}
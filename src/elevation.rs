@@ -0,0 +1,77 @@
+use crate::despike::elevation;
+use crate::point::format_number;
+use crate::schema;
+use crate::tree::Element;
+
+/// Give track points that have no `<ele>` one, interpolated linearly between the nearest points
+/// before and after them in the segment that do have an elevation. The points in between are
+/// assumed to be evenly spaced.
+///
+/// Points without an elevation at the start or end of a segment are left as they are, as are
+/// points whose `<ele>` isn't a number.
+pub(crate) fn interpolate_elevation(trk: &mut Element) {
+    for seg in trk.elements_mut().filter(|el| el.name() == b"trkseg") {
+        // The last point with an elevation, and the points without one since then.
+        let mut prev = None;
+        let mut pending = vec![];
+        for pt in seg.elements_mut().filter(|el| el.name() == b"trkpt") {
+            if pt.child(b"ele").is_none() {
+                if prev.is_some() {
+                    pending.push(pt);
+                }
+                continue;
+            }
+            let ele = match elevation(pt) {
+                Some(ele) => ele,
+                None => {
+                    // Not something to interpolate from, so start over after it.
+                    prev = None;
+                    pending.clear();
+                    continue;
+                }
+            };
+            if let Some(prev) = prev {
+                let steps = (pending.len() + 1) as f64;
+                for (i, missing) in pending.drain(..).enumerate() {
+                    let value = prev + (ele - prev) * (i + 1) as f64 / steps;
+                    missing.insert_child(Element::with_text("ele", &format_number(value, 2)),
+                        schema::POINT);
+                }
+            }
+            prev = Some(ele);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Document;
+
+    #[test]
+    fn interpolate() {
+        let gpx = r#"<gpx><trk><trkseg>
+            <trkpt lat="47" lon="-121"/>
+            <trkpt lat="47" lon="-121"><ele>100</ele></trkpt>
+            <trkpt lat="47" lon="-121"><time>2021-08-27T10:00:00Z</time></trkpt>
+            <trkpt lat="47" lon="-121"/>
+            <trkpt lat="47" lon="-121"><ele>103</ele></trkpt>
+            <trkpt lat="47" lon="-121"/>
+            <trkpt lat="47" lon="-121"><ele>bogus</ele></trkpt>
+            <trkpt lat="47" lon="-121"/>
+            <trkpt lat="47" lon="-121"><ele>50</ele></trkpt>
+            <trkpt lat="47" lon="-121"/>
+        </trkseg></trk></gpx>"#;
+        let mut doc = Document::read(gpx.as_bytes()).unwrap();
+        let trk = doc.root.child_mut(b"trk").unwrap();
+        interpolate_elevation(trk);
+        let elevations = trk.track_points()
+            .map(|pt| pt.child(b"ele").map(|e| e.text()))
+            .collect::<Vec<_>>();
+        let ele = |s: &str| Some(s.to_owned());
+        assert_eq!(elevations, [None, ele("100"), ele("101"), ele("102"), ele("103"), None,
+            ele("bogus"), None, ele("50"), None]);
+        // The <ele> goes before the <time>.
+        assert_eq!(trk.track_points().nth(2).unwrap().elements().next().unwrap().name(), b"ele");
+    }
+}
@@ -0,0 +1,45 @@
+use crate::tree::{Document, Element};
+use anyhow::Context as _;
+use std::io::Read;
+
+/// Content to add to the `<extensions>` element of the output's root, which is added if the
+/// first file doesn't have one.
+#[derive(Debug, Clone)]
+pub struct Extensions(pub(crate) Element);
+
+impl Extensions {
+    /// Read an XML fragment: any number of elements, with no XML declaration. Only the elements
+    /// are kept, not any comments or text between them.
+    pub fn read<R: Read>(mut source: R) -> anyhow::Result<Extensions> {
+        let mut fragment = String::new();
+        source.read_to_string(&mut fragment)?;
+        let xml = format!("<extensions>{}</extensions>", fragment.trim());
+        let doc = Document::read(xml.as_bytes()).context("not a valid XML fragment")?;
+        if !doc.epilog.is_empty() {
+            anyhow::bail!("not a valid XML fragment");
+        }
+        Ok(Extensions(doc.root))
+    }
+
+    /// Add the content to the end of an existing `<extensions>` element.
+    pub(crate) fn append_to(&self, el: &mut Element) {
+        for child in self.0.elements() {
+            el.insert_child(child.clone(), &[]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read() {
+        let ext = Extensions::read("\n<a:x>1</a:x>\n<!-- c --><y/>\n".as_bytes()).unwrap();
+        assert_eq!(ext.0.elements().map(|el| el.name().to_vec()).collect::<Vec<_>>(),
+            [b"a:x".to_vec(), b"y".to_vec()]);
+        assert!(Extensions::read("<x>".as_bytes()).is_err());
+        assert!(Extensions::read("<x></y>".as_bytes()).is_err());
+        assert!(Extensions::read("</extensions><x/>".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+//! Pluggable filtering of the XML events of the files being joined.
+
+use anyhow::bail;
+use quick_xml::events::Event;
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// What to do with an event. Only start tags, self-closing tags, text, and comments can be
+/// dropped or replaced; doing so to any other event is an error, since it would leave the output
+/// malformed.
+#[derive(Debug, Clone)]
+pub enum FilterAction {
+    /// Pass the event on unchanged.
+    Keep,
+    /// Leave out the event, or if it's a start tag, the whole element it starts, up to and
+    /// including its end tag.
+    Drop,
+    /// Use this event instead, which goes on to any later filters. It has to be the same kind of
+    /// event, and a start tag has to keep its name, so that it still matches its end tag.
+    Replace(Event<'static>),
+}
+
+/// A user-defined filter of the XML events of the files being joined, as they're read, before
+/// anything else looks at them.
+///
+/// ```
+/// use gpxjoin::{FilterAction, GpxEventFilter, JoinConfig};
+/// use quick_xml::events::Event;
+///
+/// /// Leaves out the times of track points.
+/// struct StripTime;
+///
+/// impl GpxEventFilter for StripTime {
+///     fn filter(&mut self, event: &Event, path: &[Vec<u8>]) -> FilterAction {
+///         match event {
+///             Event::Start(start) if start.name() == b"time"
+///                 && path.last().is_some_and(|el| el == b"trkpt") => FilterAction::Drop,
+///             _ => FilterAction::Keep,
+///         }
+///     }
+/// }
+///
+/// let filters: Vec<Box<dyn GpxEventFilter>> = vec![Box::new(StripTime)];
+/// let config = JoinConfig { event_filters: filters.into(), ..Default::default() };
+/// let gpx = "<gpx><trk><trkseg><trkpt lat=\"1\" lon=\"2\"><time>2021-08-27T10:00:00Z</time>\
+///     </trkpt></trkseg></trk></gpx>";
+/// let mut out = vec![];
+/// gpxjoin::join_gpx(&mut [gpx.as_bytes()], &mut out, &config).unwrap();
+/// assert_eq!(String::from_utf8(out).unwrap(),
+///     "<gpx><trk><trkseg><trkpt lat=\"1\" lon=\"2\"></trkpt></trkseg></trk></gpx>");
+/// ```
+pub trait GpxEventFilter: Send + Sync {
+    /// Decide what to do with an event, given the names of the elements it's inside, from the
+    /// root element down. The path of a start tag doesn't include the element it starts.
+    fn filter(&mut self, event: &Event, path: &[Vec<u8>]) -> FilterAction;
+}
+
+/// Event filters, run in order. Cloning a config shares its filters instead of copying them.
+#[derive(Clone, Default)]
+pub struct EventFilters(Vec<Arc<Mutex<Box<dyn GpxEventFilter>>>>);
+
+impl EventFilters {
+    pub fn push(&mut self, filter: Box<dyn GpxEventFilter>) {
+        self.0.push(Arc::new(Mutex::new(filter)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<Box<dyn GpxEventFilter>>> for EventFilters {
+    fn from(filters: Vec<Box<dyn GpxEventFilter>>) -> Self {
+        EventFilters(filters.into_iter().map(|f| Arc::new(Mutex::new(f))).collect())
+    }
+}
+
+impl fmt::Debug for EventFilters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EventFilters({} filters)", self.0.len())
+    }
+}
+
+/// Event filters being run on one file, keeping track of any element being left out.
+pub(crate) struct FilterState<'a> {
+    filters: &'a EventFilters,
+    /// How deep inside a left-out element the events are, or 0 if they're not in one.
+    dropping: usize,
+}
+
+impl<'a> FilterState<'a> {
+    pub(crate) fn new(filters: &'a EventFilters) -> Self {
+        FilterState { filters, dropping: 0 }
+    }
+
+    /// Run the filters on an event, returning the event to use, or None if it's left out. It's an
+    /// error for a filter to drop or replace an event it can't.
+    pub(crate) fn apply<'e>(&mut self, mut evt: Event<'e>, path: &[Vec<u8>])
+        -> anyhow::Result<Option<Event<'e>>>
+    {
+        if self.dropping > 0 {
+            match evt {
+                Event::Start(_) => self.dropping += 1,
+                Event::End(_) => self.dropping -= 1,
+                _ => (),
+            }
+            return Ok(None);
+        }
+        for filter in &self.filters.0 {
+            let mut filter = filter.lock().unwrap_or_else(PoisonError::into_inner);
+            let action = filter.filter(&evt, path);
+            let changeable = matches!(evt, Event::Start(_) | Event::Empty(_) | Event::Text(_)
+                | Event::CData(_) | Event::Comment(_));
+            match action {
+                FilterAction::Keep => (),
+                FilterAction::Drop if !changeable => {
+                    bail!("an event filter can't drop {}", describe(&evt));
+                }
+                FilterAction::Drop => {
+                    if let Event::Start(_) = evt {
+                        self.dropping = 1;
+                    }
+                    return Ok(None);
+                }
+                FilterAction::Replace(_) if !changeable => {
+                    bail!("an event filter can't replace {}", describe(&evt));
+                }
+                FilterAction::Replace(new) => {
+                    if mem::discriminant(&new) != mem::discriminant(&evt) {
+                        bail!("an event filter can't replace {} with {}", describe(&evt),
+                            describe(&new));
+                    }
+                    let renamed = match (&evt, &new) {
+                        (Event::Start(old), Event::Start(new)) => old.name() != new.name(),
+                        _ => false,
+                    };
+                    if renamed {
+                        bail!("an event filter can't replace {} with {}, as its end tag wouldn't \
+                            match", describe(&evt), describe(&new));
+                    }
+                    evt = new;
+                }
+            }
+        }
+        Ok(Some(evt))
+    }
+}
+
+/// What kind of event this is, for error messages.
+fn describe(evt: &Event) -> String {
+    match evt {
+        Event::Start(start) => format!("a <{}> start tag", String::from_utf8_lossy(start.name())),
+        Event::End(end) => format!("a </{}> end tag", String::from_utf8_lossy(end.name())),
+        Event::Empty(empty) => format!("a <{}/> tag", String::from_utf8_lossy(empty.name())),
+        Event::Text(_) => "text".to_owned(),
+        Event::CData(_) => "a CDATA section".to_owned(),
+        Event::Comment(_) => "a comment".to_owned(),
+        Event::Decl(_) => "the XML declaration".to_owned(),
+        Event::PI(_) => "a processing instruction".to_owned(),
+        Event::DocType(_) => "a doctype".to_owned(),
+        Event::Eof => "the end of the file".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use quick_xml::events::{BytesEnd, BytesStart, BytesText};
+    use quick_xml::Writer;
+
+    /// A filter deciding by a function of the event.
+    struct Rule(fn(&Event) -> FilterAction);
+
+    impl GpxEventFilter for Rule {
+        fn filter(&mut self, event: &Event, _path: &[Vec<u8>]) -> FilterAction {
+            (self.0)(event)
+        }
+    }
+
+    fn start(name: &str) -> Event<'static> {
+        Event::Start(BytesStart::owned_name(name))
+    }
+
+    fn end(name: &str) -> Event<'static> {
+        Event::End(BytesEnd::owned(name.as_bytes().to_vec()))
+    }
+
+    fn text(text: &str) -> Event<'static> {
+        Event::Text(BytesText::from_escaped(text.as_bytes().to_vec()))
+    }
+
+    fn named(evt: &Event, name: &str) -> bool {
+        match evt {
+            Event::Start(el) | Event::Empty(el) => el.name() == name.as_bytes(),
+            Event::End(el) => el.name() == name.as_bytes(),
+            _ => false,
+        }
+    }
+
+    /// Run some filters on some events, writing out what's left.
+    fn run(rules: Vec<Rule>, events: Vec<Event<'static>>) -> anyhow::Result<String> {
+        let filters = rules.into_iter()
+            .map(|rule| Box::new(rule) as Box<dyn GpxEventFilter>)
+            .collect::<Vec<_>>()
+            .into();
+        let mut state = FilterState::new(&filters);
+        let mut out = Writer::new(vec![]);
+        for evt in events {
+            if let Some(evt) = state.apply(evt, &[])? {
+                out.write_event(evt)?;
+            }
+        }
+        Ok(String::from_utf8(out.into_inner())?)
+    }
+
+    fn trkpt() -> Vec<Event<'static>> {
+        vec![start("trkpt"), start("ele"), text("10"), end("ele"), start("time"),
+            text("2021-08-27T10:00:00Z"), end("time"), end("trkpt")]
+    }
+
+    #[test]
+    fn drop() {
+        let drop_time = Rule(|evt| if named(evt, "time") && matches!(evt, Event::Start(_)) {
+            FilterAction::Drop
+        } else {
+            FilterAction::Keep
+        });
+        assert_eq!(run(vec![drop_time], trkpt()).unwrap(), "<trkpt><ele>10</ele></trkpt>");
+        let drop_text = Rule(|evt| match evt {
+            Event::Text(_) => FilterAction::Drop,
+            _ => FilterAction::Keep,
+        });
+        assert_eq!(run(vec![drop_text], trkpt()).unwrap(),
+            "<trkpt><ele></ele><time></time></trkpt>");
+    }
+
+    #[test]
+    fn nested_drop() {
+        // Everything inside a dropped element goes with it, including elements of the same name,
+        // and its end tag doesn't get to the filters at all.
+        let drop_outer = Rule(|evt| match evt {
+            Event::Start(el) if el.name() == b"trkpt" && el.attributes().next().is_none() => {
+                FilterAction::Drop
+            }
+            Event::End(_) => panic!("saw an end tag"),
+            _ => FilterAction::Keep,
+        });
+        let mut events = vec![start("trkpt")];
+        events.push(Event::Start(BytesStart::owned(&b"trkpt lat=\"1\""[..], 5)));
+        events.extend(trkpt());
+        events.extend(vec![end("trkpt"), end("trkpt")]);
+        events.push(Event::Empty(BytesStart::owned_name("trkseg")));
+        assert_eq!(run(vec![drop_outer], events).unwrap(), "<trkseg/>");
+    }
+
+    #[test]
+    fn replace() {
+        let replace_ele = Rule(|evt| match evt {
+            Event::Text(t) if &**t == b"10" => FilterAction::Replace(text("11")),
+            _ => FilterAction::Keep,
+        });
+        // Later filters see the replacement.
+        let replace_11 = Rule(|evt| match evt {
+            Event::Text(t) if &**t == b"11" => FilterAction::Replace(text("12")),
+            _ => FilterAction::Keep,
+        });
+        assert_eq!(run(vec![replace_ele, replace_11], trkpt()).unwrap(),
+            "<trkpt><ele>12</ele><time>2021-08-27T10:00:00Z</time></trkpt>");
+        // A start tag can change its attributes, but not its name.
+        let attrs = Rule(|evt| if named(evt, "trkpt") && matches!(evt, Event::Start(_)) {
+            FilterAction::Replace(Event::Start(BytesStart::owned(&b"trkpt lat=\"1\""[..], 5)))
+        } else {
+            FilterAction::Keep
+        });
+        assert!(run(vec![attrs], trkpt()).unwrap().starts_with("<trkpt lat=\"1\"><ele>"));
+        let rename = Rule(|evt| if named(evt, "trkpt") && matches!(evt, Event::Start(_)) {
+            FilterAction::Replace(start("rtept"))
+        } else {
+            FilterAction::Keep
+        });
+        assert_eq!(run(vec![rename], trkpt()).unwrap_err().to_string(),
+            "an event filter can't replace a <trkpt> start tag with a <rtept> start tag, as its \
+            end tag wouldn't match");
+    }
+
+    #[test]
+    fn invalid() {
+        let drop_end = Rule(|evt| if named(evt, "trkpt") && matches!(evt, Event::End(_)) {
+            FilterAction::Drop
+        } else {
+            FilterAction::Keep
+        });
+        assert_eq!(run(vec![drop_end], trkpt()).unwrap_err().to_string(),
+            "an event filter can't drop a </trkpt> end tag");
+        let replace_end = Rule(|evt| if named(evt, "ele") && matches!(evt, Event::End(_)) {
+            FilterAction::Replace(end("ele"))
+        } else {
+            FilterAction::Keep
+        });
+        assert_eq!(run(vec![replace_end], trkpt()).unwrap_err().to_string(),
+            "an event filter can't replace a </ele> end tag");
+        // An element can't be made self-closing, which would leave its end tag unmatched.
+        let empty = Rule(|evt| match evt {
+            Event::Start(el) if el.name() == b"ele" => {
+                FilterAction::Replace(Event::Empty(BytesStart::owned_name("ele")))
+            }
+            _ => FilterAction::Keep,
+        });
+        assert_eq!(run(vec![empty], trkpt()).unwrap_err().to_string(),
+            "an event filter can't replace a <ele> start tag with a <ele/> tag");
+    }
+}
@@ -0,0 +1,162 @@
+//! Encoding of track points as a FIT activity file, the binary format Garmin devices record.
+//!
+//! Only the little that's needed is written: a `file_id` message, then a `record` message for
+//! each track point, with its time, position, and altitude.
+
+use crate::Timestamp;
+use crate::point::TrackPoint;
+use crate::tree::Document;
+use std::io::{BufRead, Write};
+
+/// FIT timestamps count seconds from 1989-12-31T00:00:00Z, which is this many seconds after the
+/// Unix epoch.
+const FIT_EPOCH: i64 = 631_065_600;
+
+/// The FIT SDK version the file claims to follow, times 100.
+const PROFILE_VERSION: u16 = 2100;
+
+const FILE_ID: u16 = 0;
+const RECORD: u16 = 20;
+
+// Base types of fields.
+const ENUM: u8 = 0x00;
+const SINT32: u8 = 0x85;
+const UINT16: u8 = 0x84;
+const UINT32: u8 = 0x86;
+
+// The values meaning a field has no data.
+const NO_UINT16: u16 = 0xFFFF;
+const NO_SINT32: i32 = 0x7FFF_FFFF;
+const NO_UINT32: u32 = 0xFFFF_FFFF;
+
+/// Convert the track points of a GPX document to a FIT activity file.
+pub fn write_fit<R: BufRead, W: Write>(source: R, mut dest: W) -> anyhow::Result<()> {
+    let doc = Document::read(source)?;
+    let points = doc.root.elements()
+        .filter(|el| el.name() == b"trk")
+        .flat_map(|trk| trk.track_points())
+        .filter_map(TrackPoint::from_element)
+        .collect::<Vec<_>>();
+
+    let mut data = vec![];
+    // file_id, as local message 0: type (4, an activity), manufacturer (255, development), and
+    // time_created.
+    definition(&mut data, 0, FILE_ID, &[(0, 1, ENUM), (1, 2, UINT16), (4, 4, UINT32)]);
+    data.push(0);
+    data.push(4);
+    data.extend_from_slice(&255u16.to_le_bytes());
+    let created = points.iter().find_map(|p| p.time);
+    data.extend_from_slice(&fit_time(created).to_le_bytes());
+
+    // record, as local message 1: timestamp, position_lat, position_long, and altitude.
+    definition(&mut data, 1, RECORD, &[(253, 4, UINT32), (0, 4, SINT32), (1, 4, SINT32),
+        (2, 2, UINT16)]);
+    for point in &points {
+        data.push(1);
+        data.extend_from_slice(&fit_time(point.time).to_le_bytes());
+        data.extend_from_slice(&semicircles(point.lat).to_le_bytes());
+        data.extend_from_slice(&semicircles(point.lon).to_le_bytes());
+        data.extend_from_slice(&altitude(point.ele).to_le_bytes());
+    }
+
+    let mut header = vec![14, 0x20];
+    header.extend_from_slice(&PROFILE_VERSION.to_le_bytes());
+    header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    header.extend_from_slice(b".FIT");
+    let header_crc = crc(0, &header);
+    header.extend_from_slice(&header_crc.to_le_bytes());
+    let file_crc = crc(crc(0, &header), &data);
+
+    dest.write_all(&header)?;
+    dest.write_all(&data)?;
+    dest.write_all(&file_crc.to_le_bytes())?;
+    Ok(())
+}
+
+/// Append a definition message, giving the number, size in bytes, and base type of each field
+/// of the messages of a local type.
+fn definition(buf: &mut Vec<u8>, local: u8, global: u16, fields: &[(u8, u8, u8)]) {
+    buf.push(0x40 | local);
+    // Reserved, then little-endian.
+    buf.extend_from_slice(&[0, 0]);
+    buf.extend_from_slice(&global.to_le_bytes());
+    buf.push(fields.len() as u8);
+    for &(number, size, base_type) in fields {
+        buf.extend_from_slice(&[number, size, base_type]);
+    }
+}
+
+fn fit_time(time: Option<Timestamp>) -> u32 {
+    match time.map(|t| t.0.div_euclid(1000) - FIT_EPOCH) {
+        Some(seconds) if (0 .. i64::from(NO_UINT32)).contains(&seconds) => seconds as u32,
+        _ => NO_UINT32,
+    }
+}
+
+/// Degrees as semicircles, where 2^31 of them make 180 degrees.
+fn semicircles(degrees: f64) -> i32 {
+    let value = (degrees * (1u64 << 31) as f64 / 180.).round();
+    if value >= f64::from(i32::MIN) && value < f64::from(NO_SINT32) {
+        value as i32
+    } else {
+        NO_SINT32
+    }
+}
+
+/// Meters as FIT altitudes, which are in fifths of a meter, from 500 meters below sea level.
+fn altitude(ele: Option<f64>) -> u16 {
+    match ele.map(|ele| ((ele + 500.) * 5.).round()) {
+        Some(value) if value >= 0. && value < f64::from(NO_UINT16) => value as u16,
+        _ => NO_UINT16,
+    }
+}
+
+/// The CRC-16 FIT files use, continuing from a CRC of some earlier bytes.
+fn crc(mut crc: u16, bytes: &[u8]) -> u16 {
+    const TABLE: [u16; 16] = [
+        0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401,
+        0xA001, 0x6C00, 0x7800, 0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+    ];
+    for &byte in bytes {
+        for nibble in [byte & 0xF, byte >> 4] {
+            let tmp = TABLE[usize::from(crc & 0xF)];
+            crc = (crc >> 4) ^ tmp ^ TABLE[usize::from(nibble)];
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn check_crc() {
+        assert_eq!(crc(0, b"123456789"), 0xBB3D);
+        assert_eq!(crc(crc(0, b"1234"), b"56789"), 0xBB3D);
+    }
+
+    #[test]
+    fn encode() {
+        let gpx = r#"<gpx><trk><trkseg>
+            <trkpt lat="45" lon="-90"><ele>100</ele><time>1989-12-31T00:01:00Z</time></trkpt>
+            <trkpt lat="0" lon="0"/>
+        </trkseg></trk></gpx>"#;
+        let mut out = vec![];
+        write_fit(gpx.as_bytes(), &mut out).unwrap();
+        let data = [
+            0x40, 0, 0, 0, 0, 3, 0, 1, 0x00, 1, 2, 0x84, 4, 4, 0x86, // file_id definition
+            0, 4, 255, 0, 60, 0, 0, 0, // file_id
+            0x41, 0, 0, 20, 0, 4, 253, 4, 0x86, 0, 4, 0x85, 1, 4, 0x85, 2, 2, 0x84, // record def.
+            1, 60, 0, 0, 0, 0, 0, 0, 0x20, 0, 0, 0, 0xC0, 0xB8, 0x0B, // 45, -90, 100m
+            1, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, // no time or ele
+        ];
+        assert_eq!(&out[.. 12], &[14, 0x20, 0x34, 0x08, data.len() as u8, 0, 0, 0, b'.', b'F',
+            b'I', b'T']);
+        assert_eq!(&out[12 .. 14], &crc(0, &out[.. 12]).to_le_bytes());
+        assert_eq!(&out[14 .. out.len() - 2], &data[..]);
+        // A file's CRC is right when the CRC of the whole file, including it, is 0.
+        assert_eq!(crc(0, &out), 0);
+    }
+}
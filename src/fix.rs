@@ -0,0 +1,132 @@
+use crate::point::TrackPoint;
+use crate::tree::Element;
+
+/// Repair common defects in an element and everything inside it: swapped latitude and longitude,
+/// line breaks inside timestamps, and consecutive duplicate points.
+///
+/// Each repair is logged at the info level.
+pub(crate) fn fix_element(el: &mut Element) {
+    match el.name() {
+        b"trkpt" | b"rtept" | b"wpt" => fix_coordinates(el),
+        b"time" => {
+            fix_time(el);
+            return;
+        }
+        _ => (),
+    }
+    for child in el.elements_mut() {
+        fix_element(child);
+    }
+    if matches!(el.name(), b"trkseg" | b"rte") {
+        remove_duplicate_points(el);
+    }
+}
+
+fn fix_coordinates(el: &mut Element) {
+    if let Some((lat, lon)) = swap_coordinates(el) {
+        info!("fix: swapped coordinates: lat={:?} lon={:?} -> lat={:?} lon={:?}",
+            lat, lon, lon, lat);
+    }
+}
+
+/// Swap the `lat` and `lon` of the points in an element and everything inside it where the
+/// latitude is out of range but would be fine as a longitude and vice versa, with a warning.
+/// Returns how many were swapped.
+pub(crate) fn enforce_coordinate_order(el: &mut Element) -> usize {
+    let mut swapped = 0;
+    swap_all(el, &mut swapped);
+    if swapped > 0 {
+        warn!("swapped the latitude and longitude of {} points in {}", swapped, describe(el));
+    }
+    swapped
+}
+
+/// A top-level element, for messages: its track name if it's a track, or else its tag.
+pub(crate) fn describe(el: &Element) -> String {
+    match el.name() {
+        b"trk" => describe_track(el.track_name()),
+        name => format!("<{}>", String::from_utf8_lossy(name)),
+    }
+}
+
+/// A track with the given name, for messages.
+pub(crate) fn describe_track(name: Option<String>) -> String {
+    match name {
+        Some(name) => format!("track {:?}", name),
+        None => "<trk>".to_owned(),
+    }
+}
+
+/// The points in an element and everything inside it with a latitude or longitude out of range,
+/// described like `<trkpt lat="91.5" lon="-121.0">`. Coordinates that aren't numbers at all are
+/// left to validation to complain about.
+pub(crate) fn out_of_range_points(el: &Element) -> Vec<String> {
+    let mut points = vec![];
+    find_out_of_range(el, &mut points);
+    points
+}
+
+fn find_out_of_range(el: &Element, points: &mut Vec<String>) {
+    if matches!(el.name(), b"trkpt" | b"rtept" | b"wpt") {
+        let (lat, lon) = (el.attr(b"lat").unwrap_or_default(), el.attr(b"lon").unwrap_or_default());
+        let beyond = |value: &str, limit: f64| {
+            value.trim().parse::<f64>().is_ok_and(|v| !(-limit ..= limit).contains(&v))
+        };
+        if beyond(&lat, 90.) || beyond(&lon, 180.) {
+            points.push(format!("<{} lat={:?} lon={:?}>", String::from_utf8_lossy(el.name()), lat,
+                lon));
+        }
+    }
+    for child in el.elements() {
+        find_out_of_range(child, points);
+    }
+}
+
+fn swap_all(el: &mut Element, swapped: &mut usize) {
+    if matches!(el.name(), b"trkpt" | b"rtept" | b"wpt") && swap_coordinates(el).is_some() {
+        *swapped += 1;
+    }
+    for child in el.elements_mut() {
+        swap_all(child, swapped);
+    }
+}
+
+/// Swap the point's `lat` and `lon` if its latitude is out of range and its longitude isn't,
+/// returning the original ones.
+fn swap_coordinates(el: &mut Element) -> Option<(String, String)> {
+    let (lat, lon) = (el.attr(b"lat")?, el.attr(b"lon")?);
+    match (lat.trim().parse::<f64>(), lon.trim().parse::<f64>()) {
+        (Ok(lat_val), Ok(lon_val)) if lat_val.abs() > 90. && lon_val.abs() <= 90. => (),
+        _ => return None,
+    }
+    el.set_attr(b"lat", &lon);
+    el.set_attr(b"lon", &lat);
+    Some((lat, lon))
+}
+
+fn fix_time(el: &mut Element) {
+    let text = el.text();
+    if !text.contains(&['\r', '\n'][..]) {
+        return;
+    }
+    let fixed = text.replace(&['\r', '\n'][..], "");
+    let fixed = fixed.trim();
+    info!("fix: line break in timestamp: {:?} -> {:?}", text, fixed);
+    el.set_text(fixed);
+}
+
+fn remove_duplicate_points(el: &mut Element) {
+    let mut prev = None;
+    el.retain_elements(|child| {
+        if !matches!(child.name(), b"trkpt" | b"rtept") {
+            return true;
+        }
+        let point = TrackPoint::from_element(child);
+        if let Some(p) = point.filter(|_| point == prev) {
+            info!("fix: removed duplicate point: lat={} lon={}", p.lat, p.lon);
+            return false;
+        }
+        prev = point;
+        true
+    });
+}
@@ -0,0 +1,132 @@
+use crate::point::TrackPoint;
+use crate::schema;
+use crate::strategy::SourceTrack;
+use crate::tree::Element;
+
+/// How many seconds of a gap each interpolated point stands for.
+const SECONDS_PER_POINT: f64 = 30.;
+
+/// The most points to interpolate in one gap.
+const MAX_POINTS: usize = 10;
+
+/// Add a segment bridging each gap of at most `max_gap` seconds between consecutive segments of
+/// a `<trk>` element.
+pub(crate) fn fill_segment_gaps(trk: &mut Element, max_gap: f64) {
+    let segments = trk.elements().filter(|el| el.name() == b"trkseg").collect::<Vec<_>>();
+    let mut filled = vec![];
+    let mut bridged = false;
+    for (i, seg) in segments.iter().enumerate() {
+        filled.push((*seg).clone());
+        let next = match segments.get(i + 1) {
+            Some(next) => next,
+            None => break,
+        };
+        if let Some(bridge) = bridge(seg, next, max_gap) {
+            filled.push(bridge);
+            bridged = true;
+        }
+    }
+    if bridged {
+        trk.replace_children(b"trkseg", filled);
+    }
+}
+
+/// Add a segment to the end of each track bridging a gap of at most `max_gap` seconds to the
+/// next track.
+pub(crate) fn fill_track_gaps(tracks: &mut [SourceTrack], max_gap: f64) {
+    for i in 1 .. tracks.len() {
+        let segments = |trk: &Element| {
+            trk.elements().filter(|el| el.name() == b"trkseg").cloned().collect::<Vec<_>>()
+        };
+        let (before, after) = (segments(&tracks[i - 1].1), segments(&tracks[i].1));
+        if let (Some(last), Some(first)) = (before.last(), after.first()) {
+            if let Some(bridge) = bridge(last, first, max_gap) {
+                tracks[i - 1].1.insert_child(bridge, schema::TRK);
+            }
+        }
+    }
+}
+
+/// A segment from the last timed point of one segment to the first timed point of another,
+/// with points interpolated between them, if they're at most `max_gap` seconds apart.
+fn bridge(before: &Element, after: &Element, max_gap: f64) -> Option<Element> {
+    let timed = |seg: &Element| {
+        seg.elements()
+            .filter(|el| el.name() == b"trkpt")
+            .filter_map(TrackPoint::from_element)
+            .filter(|p| p.time.is_some())
+            .collect::<Vec<_>>()
+    };
+    let a = *timed(before).last()?;
+    let b = *timed(after).first()?;
+    let seconds = (b.time.unwrap().0 - a.time.unwrap().0) as f64 / 1000.;
+    if seconds <= 0. || seconds > max_gap {
+        return None;
+    }
+    let count = ((seconds / SECONDS_PER_POINT).ceil() as usize).clamp(1, MAX_POINTS);
+    let mut seg = Element::new("trkseg");
+    seg.insert_child(a.to_element("trkpt"), &[]);
+    for i in 1 ..= count {
+        seg.insert_child(a.interpolate(&b, i as f64 / (count + 1) as f64).to_element("trkpt"), &[]);
+    }
+    seg.insert_child(b.to_element("trkpt"), &[]);
+    Some(seg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Document;
+
+    fn times(trk: &Element) -> Vec<Vec<String>> {
+        trk.elements().filter(|el| el.name() == b"trkseg").map(|seg| {
+            seg.elements()
+                .filter_map(TrackPoint::from_element)
+                .filter_map(|p| p.time)
+                .map(|t| t.to_string()[11 .. 19].to_owned())
+                .collect()
+        }).collect()
+    }
+
+    #[test]
+    fn segment_gaps() {
+        let gpx = r#"<gpx><trk><trkseg>
+            <trkpt lat="47.000" lon="-121.0"><time>2021-08-27T10:00:00Z</time></trkpt>
+        </trkseg><trkseg>
+            <trkpt lat="47.001" lon="-121.0"><time>2021-08-27T10:01:30Z</time></trkpt>
+        </trkseg><trkseg>
+            <trkpt lat="47.002" lon="-121.0"><time>2021-08-27T10:30:00Z</time></trkpt>
+        </trkseg><trkseg>
+            <trkpt lat="47.003" lon="-121.0"/>
+        </trkseg></trk></gpx>"#;
+        let mut doc = Document::read(gpx.as_bytes()).unwrap();
+        let trk = doc.root.child_mut(b"trk").unwrap();
+        fill_segment_gaps(trk, 300.);
+        // 90 seconds is bridged with three points; 28.5 minutes is too long, and the last
+        // segment has no times.
+        let t = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(times(trk), [
+            t(&["10:00:00"]),
+            t(&["10:00:00", "10:00:22", "10:00:45", "10:01:07", "10:01:30"]),
+            t(&["10:01:30"]),
+            t(&["10:30:00"]),
+            t(&[]),
+        ]);
+    }
+
+    #[test]
+    fn track_gaps() {
+        let gpx = r#"<gpx><trk><trkseg>
+            <trkpt lat="47.000" lon="-121.0"><time>2021-08-27T10:00:00Z</time></trkpt>
+        </trkseg></trk><trk><trkseg>
+            <trkpt lat="47.001" lon="-121.0"><time>2021-08-27T10:00:10Z</time></trkpt>
+        </trkseg></trk></gpx>"#;
+        let doc = Document::read(gpx.as_bytes()).unwrap();
+        let mut tracks = doc.root.elements().map(|trk| (0, trk.clone())).collect::<Vec<_>>();
+        fill_track_gaps(&mut tracks, 60.);
+        let t = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(times(&tracks[0].1),
+            [t(&["10:00:00"]), t(&["10:00:00", "10:00:05", "10:00:10"])]);
+        assert_eq!(times(&tracks[1].1), [t(&["10:00:10"])]);
+    }
+}
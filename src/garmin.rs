@@ -0,0 +1,53 @@
+use crate::schema;
+use crate::tree::Element;
+
+const GPXX_NAMESPACE: &str = "http://www.garmin.com/xmlschemas/GpxExtensions/v3";
+
+/// Garmin display colors for Strava activity types. Strava writes these as `Run`, `Ride`, and so
+/// on in some exports, and as `running`, `cycling`, and so on in others.
+const COLORS: &[(&[&str], &str)] = &[
+    (&["run", "running", "virtualrun"], "Red"),
+    (&["ride", "cycling", "virtualride", "ebikeride", "mountainbikeride"], "Blue"),
+    (&["hike", "hiking"], "DarkGreen"),
+    (&["walk", "walking"], "Green"),
+    (&["swim", "swimming"], "Cyan"),
+    (&["alpineski", "backcountryski", "nordicski", "snowboard"], "Magenta"),
+    (&["kayaking", "canoeing", "rowing"], "DarkCyan"),
+];
+
+/// Give a track with a Strava activity `<type>` the matching Garmin `<gpxx:DisplayColor>`, unless
+/// it already has one. Types without a matching color are left alone.
+pub(crate) fn strava_to_garmin(trk: &mut Element) {
+    let activity = match trk.child(b"type") {
+        Some(t) => t.text().trim().to_lowercase(),
+        None => return,
+    };
+    let color = match COLORS.iter().find(|(types, _)| types.contains(&activity.as_str())) {
+        Some(&(_, color)) => color,
+        None => return,
+    };
+    if trk.child(b"extensions")
+        .and_then(|ext| ext.child(b"gpxx:TrackExtension"))
+        .is_some_and(|ext| ext.child(b"gpxx:DisplayColor").is_some())
+    {
+        return;
+    }
+
+    info!("setting the display color of a {:?} track to {}", activity, color);
+    let mut extension = Element::new("gpxx:TrackExtension");
+    extension.set_attr(b"xmlns:gpxx", GPXX_NAMESPACE);
+    extension.insert_child(Element::with_text("gpxx:DisplayColor", color), &[]);
+    match trk.child_mut(b"extensions") {
+        Some(extensions) => match extensions.child_mut(b"gpxx:TrackExtension") {
+            Some(existing) => {
+                existing.insert_child(Element::with_text("gpxx:DisplayColor", color), &[]);
+            }
+            None => extensions.insert_child(extension, &[]),
+        },
+        None => {
+            let mut extensions = Element::new("extensions");
+            extensions.insert_child(extension, &[]);
+            trk.insert_child(extensions, schema::TRK);
+        }
+    }
+}
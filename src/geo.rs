@@ -0,0 +1,62 @@
+use crate::point::TrackPoint;
+
+/// Mean radius of the Earth, in meters.
+const EARTH_RADIUS: f64 = 6_371_000.;
+
+/// The great-circle distance between two points in meters, by the Haversine formula.
+pub(crate) fn haversine_distance(a: &TrackPoint, b: &TrackPoint) -> f64 {
+    let (lat1, lat2) = (a.lat.to_radians(), b.lat.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (b.lon - a.lon).to_radians();
+    let h = (dlat / 2.).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.).sin().powi(2);
+    2. * EARTH_RADIUS * h.sqrt().min(1.).asin()
+}
+
+/// The area in square meters of the triangle between three points, by Heron's formula from the
+/// great-circle distances between them. Close enough for the small triangles between neighboring
+/// track points.
+pub(crate) fn triangle_area(a: &TrackPoint, b: &TrackPoint, c: &TrackPoint) -> f64 {
+    let (x, y, z) = (haversine_distance(a, b), haversine_distance(b, c), haversine_distance(c, a));
+    let s = (x + y + z) / 2.;
+    (s * (s - x) * (s - y) * (s - z)).max(0.).sqrt()
+}
+
+/// The initial compass bearing in degrees, from 0 up to 360, of the great circle from one point
+/// to another.
+pub(crate) fn initial_bearing(a: &TrackPoint, b: &TrackPoint) -> f64 {
+    let (lat1, lat2) = (a.lat.to_radians(), b.lat.to_radians());
+    let dlon = (b.lon - a.lon).to_radians();
+    let y = dlon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(lat: f64, lon: f64) -> TrackPoint {
+        TrackPoint { lat, lon, ele: None, time: None }
+    }
+
+    #[test]
+    fn haversine() {
+        assert_eq!(haversine_distance(&point(47., -121.), &point(47., -121.)), 0.);
+        // One degree of latitude is about 111.2 km.
+        let d = haversine_distance(&point(47., -121.), &point(48., -121.));
+        assert!((d - 111_195.).abs() < 1., "{}", d);
+        // Seattle to London.
+        let d = haversine_distance(&point(47.6062, -122.3321), &point(51.5074, -0.1278));
+        assert!((d - 7_700_000.).abs() < 5_000., "{}", d);
+    }
+
+    #[test]
+    fn bearing() {
+        assert_eq!(initial_bearing(&point(47., -121.), &point(48., -121.)), 0.);
+        assert_eq!(initial_bearing(&point(47., -121.), &point(46., -121.)), 180.);
+        let east = initial_bearing(&point(0., 10.), &point(0., 11.));
+        assert!((east - 90.).abs() < 1e-9, "{}", east);
+        let west = initial_bearing(&point(0., 10.), &point(0., 9.));
+        assert!((west - 270.).abs() < 1e-9, "{}", west);
+    }
+}
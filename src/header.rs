@@ -0,0 +1,86 @@
+use crate::tree::read_event;
+use quick_xml::{Reader, Writer};
+use quick_xml::events::{BytesEnd, BytesText, Event};
+use std::io::{BufRead, Write};
+
+/// Copy a GPX file up to its first track, then close the root element, making an empty-track
+/// document that can be used as a template.
+///
+/// Everything before the first `<trk>` is kept: the XML declaration, the `<gpx>` start tag with
+/// its attributes, the metadata, waypoints, and routes.
+pub fn write_header<R: BufRead, W: Write>(source: R, dest: W) -> anyhow::Result<()> {
+    let mut reader = Reader::from_reader(source);
+    let mut writer = Writer::new(dest);
+    let mut buf = vec![];
+    let mut depth = 0;
+    // Whitespace is held back until it's known whether a track follows it.
+    let mut space = None;
+    loop {
+        let evt = read_event(&mut reader, &mut buf)?;
+        match evt {
+            Event::Eof => break,
+            Event::Start(ref start) | Event::Empty(ref start)
+                if depth == 1 && start.name() == b"trk" =>
+            {
+                let newline = space.take().is_some_and(|s: Vec<u8>| s.contains(&b'\n'));
+                if newline {
+                    writer.write_event(Event::Text(BytesText::from_escaped(&b"\n"[..])))?;
+                }
+                writer.write_event(Event::End(BytesEnd::borrowed(b"gpx")))?;
+                if newline {
+                    writer.write_event(Event::Text(BytesText::from_escaped(&b"\n"[..])))?;
+                }
+                return Ok(());
+            }
+            Event::Text(ref text) if depth == 1 && text.iter().all(u8::is_ascii_whitespace) => {
+                if let Some(space) = space.replace(text.to_vec()) {
+                    writer.write_event(Event::Text(BytesText::from_escaped(space)))?;
+                }
+                buf.clear();
+                continue;
+            }
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => (),
+        }
+        if let Some(space) = space.take() {
+            writer.write_event(Event::Text(BytesText::from_escaped(space)))?;
+        }
+        writer.write_event(evt)?;
+        buf.clear();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn header(gpx: &str) -> String {
+        let mut out = vec![];
+        write_header(gpx.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn header_only() {
+        assert_eq!(header(r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1" creator="test">
+    <metadata><name>template</name></metadata>
+    <wpt lat="1" lon="2"/>
+    <trk><name>a</name></trk>
+    <trk><name>b</name></trk>
+    <extensions/>
+</gpx>
+"#), r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1" creator="test">
+    <metadata><name>template</name></metadata>
+    <wpt lat="1" lon="2"/>
+</gpx>
+"#);
+        // Without any tracks, the whole file is copied.
+        assert_eq!(header("<gpx><metadata/></gpx>"), "<gpx><metadata/></gpx>");
+        assert_eq!(header("<gpx><trk/></gpx>"), "<gpx></gpx>");
+    }
+}
@@ -0,0 +1,280 @@
+//! Indexes of the track point times in GPX files, for big files that would otherwise have to be
+//! read all the way through to find out what times they cover.
+//!
+//! # Format
+//!
+//! An index file is binary, with all numbers little-endian:
+//!
+//! * the magic bytes `GPXI`, then a format version byte, currently 2;
+//! * a flags byte: bit 0 is set if every track in the file has at least one point with a time;
+//! * the length of the indexed GPX file in bytes, as a u64, for telling when the index is stale;
+//! * the latest time of any track point, as an i64 of milliseconds since the Unix epoch, or
+//!   `i64::MIN` if no track point has a time;
+//! * the byte offset of the first `<trkpt>` tag in the file, and of the `</trkseg>` tag ending
+//!   the track segment it's in, as u64s, or `u64::MAX` if there's no track point (only in
+//!   version 2);
+//! * then, to the end of the file, pairs of a u64 byte offset of a `<trkpt>` tag in the GPX file
+//!   and an i64 of that point's time, in milliseconds since the Unix epoch. Every 1000th point
+//!   with a time is included, starting with the first, as well as the last.
+//!
+//! Version 1 indexes, without the offsets of the first segment, can still be read.
+
+use crate::Timestamp;
+use crate::tree;
+use anyhow::bail;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use std::convert::TryInto;
+use std::io::{self, BufRead, Read, Write};
+
+const MAGIC: &[u8] = b"GPXI";
+const VERSION: u8 = 2;
+
+/// How many points with times there are for each one in the index.
+const SAMPLE_INTERVAL: usize = 1000;
+
+/// An index of the track point times in a GPX file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    /// The length in bytes of the GPX file indexed.
+    pub file_len: u64,
+    /// Whether every track in the file has a point with a time.
+    pub all_tracks_timed: bool,
+    /// The latest time of any track point.
+    pub latest: Option<Timestamp>,
+    /// The byte offsets of the first `<trkpt>` tag in the file, and of the end tag of the segment
+    /// it's in.
+    pub first_segment: Option<(u64, u64)>,
+    /// Byte offsets of a sample of the `<trkpt>` tags in the file, with their times.
+    pub entries: Vec<(u64, Timestamp)>,
+}
+
+impl Index {
+    /// Index a GPX file.
+    pub fn build<R: BufRead>(source: R) -> anyhow::Result<Index> {
+        let mut reader = Reader::from_reader(source);
+        let mut buf = vec![];
+        let mut path: Vec<Vec<u8>> = vec![];
+        let mut index = Index {
+            file_len: 0,
+            all_tracks_timed: true,
+            latest: None,
+            first_segment: None,
+            entries: vec![],
+        };
+        // The offset of the first point, until its segment ends.
+        let mut first_point = None;
+        let mut timed_points = 0;
+        // The current track point's offset and time, if it has one.
+        let mut point = None;
+        let mut point_time = None;
+        let mut track_timed = false;
+        // The last point with a time, if it hasn't been put in the index.
+        let mut last = None;
+        loop {
+            let offset = reader.buffer_position() as u64;
+            match tree::read_event(&mut reader, &mut buf)? {
+                Event::Eof => break,
+                Event::Start(start) => {
+                    path.push(start.name().to_owned());
+                    let names = path.iter().map(|name| &name[..]).collect::<Vec<_>>();
+                    match names[..] {
+                        [_, b"trk"] => track_timed = false,
+                        [_, b"trk", b"trkseg", b"trkpt"] => {
+                            point = Some(offset);
+                            point_time = None;
+                            if index.first_segment.is_none() {
+                                first_point = first_point.or(Some(offset));
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+                Event::Empty(empty) if path.len() == 3 && path[1] == b"trk" && path[2] == b"trkseg"
+                    && empty.name() == b"trkpt" && index.first_segment.is_none() =>
+                {
+                    first_point = first_point.or(Some(offset));
+                }
+                Event::Empty(empty) if path.len() == 1 && empty.name() == b"trk" => {
+                    index.all_tracks_timed = false;
+                }
+                Event::Text(text) if point.is_some() && path.last().unwrap() == b"time" => {
+                    point_time = Timestamp::parse(reader.decode(&text.unescaped()?)?.trim());
+                }
+                Event::End(end) => {
+                    if path.pop().as_deref() != Some(end.name()) {
+                        bail!("unbalanced </{}> at byte {}", String::from_utf8_lossy(end.name()),
+                            offset);
+                    }
+                    let in_trk = path.len() == 1;
+                    if let (b"trkpt", Some(offset), Some(time)) = (end.name(), point, point_time) {
+                        track_timed = true;
+                        index.latest = index.latest.max(Some(time));
+                        if timed_points % SAMPLE_INTERVAL == 0 {
+                            index.entries.push((offset, time));
+                            last = None;
+                        } else {
+                            last = Some((offset, time));
+                        }
+                        timed_points += 1;
+                    }
+                    if end.name() == b"trkpt" {
+                        point = None;
+                    }
+                    if let (b"trkseg", 2, Some(first)) = (end.name(), path.len(), first_point) {
+                        index.first_segment = Some((first, offset));
+                        first_point = None;
+                    }
+                    if in_trk && end.name() == b"trk" && !track_timed {
+                        index.all_tracks_timed = false;
+                    }
+                }
+                _ => (),
+            }
+            buf.clear();
+        }
+        index.entries.extend(last);
+        index.file_len = reader.buffer_position() as u64;
+        Ok(index)
+    }
+
+    pub fn write<W: Write>(&self, mut dest: W) -> io::Result<()> {
+        dest.write_all(MAGIC)?;
+        dest.write_all(&[VERSION, self.all_tracks_timed as u8])?;
+        dest.write_all(&self.file_len.to_le_bytes())?;
+        dest.write_all(&self.latest.map_or(i64::MIN, |t| t.0).to_le_bytes())?;
+        let (first, end) = self.first_segment.unwrap_or((u64::MAX, u64::MAX));
+        dest.write_all(&first.to_le_bytes())?;
+        dest.write_all(&end.to_le_bytes())?;
+        for &(offset, time) in &self.entries {
+            dest.write_all(&offset.to_le_bytes())?;
+            dest.write_all(&time.0.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut source: R) -> anyhow::Result<Index> {
+        let mut data = vec![];
+        source.read_to_end(&mut data)?;
+        if data.len() < MAGIC.len() + 2 || !data.starts_with(MAGIC) {
+            bail!("not a gpxjoin index");
+        }
+        let version = data[MAGIC.len()];
+        let header_len = match version {
+            1 => MAGIC.len() + 2 + 16,
+            VERSION => MAGIC.len() + 2 + 32,
+            _ => bail!("unsupported index version {}", version),
+        };
+        if data.len() < header_len {
+            bail!("truncated index");
+        }
+        let number = |bytes: &[u8]| u64::from_le_bytes(bytes.try_into().unwrap());
+        let rest = &data[MAGIC.len() + 2 ..];
+        let latest = number(&rest[8 .. 16]) as i64;
+        let first_segment = match version {
+            1 => None,
+            _ => Some((number(&rest[16 .. 24]), number(&rest[24 .. 32])))
+                .filter(|&(first, _)| first != u64::MAX),
+        };
+        let entries = &data[header_len ..];
+        if entries.len() % 16 != 0 {
+            bail!("truncated index");
+        }
+        Ok(Index {
+            file_len: number(&rest[.. 8]),
+            all_tracks_timed: data[MAGIC.len() + 1] & 1 != 0,
+            latest: Some(Timestamp(latest)).filter(|_| latest != i64::MIN),
+            first_segment,
+            entries: entries.chunks(16)
+                .map(|entry| (number(&entry[.. 8]), Timestamp(number(&entry[8 ..]) as i64)))
+                .collect(),
+        })
+    }
+
+    /// The offset of the last indexed point before `time` in the file's first track segment, for
+    /// starting a scan for the points after it there, past the ones before it. Points aren't
+    /// necessarily in time order, so this is only a starting point. None if no indexed point is
+    /// early enough, or if the index is too old to say where the first segment ends.
+    pub fn seek_position(&self, time: Timestamp) -> Option<u64> {
+        let (_, end) = self.first_segment?;
+        self.entries.iter()
+            .take_while(|&&(offset, t)| t < time && offset < end)
+            .last()
+            .map(|&(offset, _)| offset)
+    }
+
+    /// Whether the file can be left out when only tracks with points at or after `since` are
+    /// wanted: no point is that late, and there are no tracks without times, which are kept.
+    pub fn all_before(&self, since: Timestamp) -> bool {
+        self.all_tracks_timed && self.latest.is_none_or(|latest| latest < since)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let points = (0 .. 2500)
+            .map(|i| format!("<trkpt lat=\"1\" lon=\"1\"><time>{}</time></trkpt>\n",
+                Timestamp(1_630_000_000_000 + i * 1000)))
+            .collect::<String>();
+        let gpx = format!("<gpx>\n<trk><trkseg>\n<trkpt lat=\"1\" lon=\"1\"/>\n{}</trkseg></trk>\n\
+            </gpx>\n", points);
+        let index = Index::build(gpx.as_bytes()).unwrap();
+        assert_eq!(index.file_len, gpx.len() as u64);
+        assert!(index.all_tracks_timed);
+        assert_eq!(index.latest, Some(Timestamp(1_630_000_000_000 + 2499 * 1000)));
+        let times = index.entries.iter().map(|&(_, t)| (t.0 - 1_630_000_000_000) / 1000)
+            .collect::<Vec<_>>();
+        assert_eq!(times, [0, 1000, 2000, 2499]);
+        for &(offset, time) in &index.entries {
+            let at = &gpx[offset as usize ..];
+            assert!(at.starts_with("<trkpt"));
+            assert!(at[.. at.find("</trkpt>").unwrap()].contains(&time.to_string()));
+        }
+
+        let mut data = vec![];
+        index.write(&mut data).unwrap();
+        assert_eq!(Index::read(&data[..]).unwrap(), index);
+        assert!(Index::read(&data[.. data.len() - 1]).is_err());
+
+        assert_eq!(index.first_segment, Some((gpx.find("<trkpt").unwrap() as u64,
+            gpx.find("</trkseg>").unwrap() as u64)));
+        assert_eq!(index.seek_position(Timestamp(1_630_000_000_000)), None);
+        assert_eq!(index.seek_position(Timestamp(1_630_000_000_000 + 1500 * 1000)),
+            Some(index.entries[1].0));
+        // Version 1 indexes don't say where the first segment ends, so they can't be seeked in.
+        let mut v1 = data.clone();
+        v1[MAGIC.len()] = 1;
+        v1.drain(MAGIC.len() + 2 + 16 .. MAGIC.len() + 2 + 32);
+        let v1 = Index::read(&v1[..]).unwrap();
+        assert_eq!(v1, Index { first_segment: None, ..index.clone() });
+        assert_eq!(v1.seek_position(Timestamp(1_630_000_000_000 + 1500 * 1000)), None);
+        assert!(index.all_before(Timestamp(1_630_000_000_000 + 2500 * 1000)));
+        assert!(!index.all_before(Timestamp(1_630_000_000_000 + 2499 * 1000)));
+
+        let untimed = Index::build("<gpx><trk/><trk><trkseg/></trk></gpx>".as_bytes()).unwrap();
+        assert!(!untimed.all_tracks_timed);
+        assert_eq!(untimed.first_segment, None);
+        assert!(!untimed.all_before(Timestamp(0)));
+    }
+
+    #[test]
+    fn first_segment() {
+        let gpx = "<gpx><trk><trkseg/></trk><trk><trkseg>\n<trkpt lat=\"1\" lon=\"1\"/>\n\
+            <trkpt lat=\"1\" lon=\"1\"><time>2021-08-27T10:00:00Z</time></trkpt>\n</trkseg>\
+            <trkseg><trkpt lat=\"1\" lon=\"1\"><time>2021-08-27T11:00:00Z</time></trkpt>\
+            </trkseg></trk></gpx>";
+        let index = Index::build(gpx.as_bytes()).unwrap();
+        let first = gpx.find("<trkpt").unwrap() as u64;
+        let end = gpx.find("</trkseg>").unwrap() as u64;
+        assert_eq!(index.first_segment, Some((first, end)));
+        // Only points in the first segment are seeked to.
+        let time = Timestamp::parse("2021-08-27T12:00:00Z").unwrap();
+        assert_eq!(index.seek_position(time), Some(index.entries[0].0));
+        assert!(index.entries[0].0 > first);
+        assert!(index.entries[1].0 > end);
+    }
+}
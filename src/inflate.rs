@@ -0,0 +1,279 @@
+//! Decompression of DEFLATE data (RFC 1951), the compression zip archives use.
+
+use anyhow::bail;
+
+/// The base lengths of the length codes 257 to 285, and how many extra bits each takes.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// The base distances of the distance codes, and how many extra bits each takes.
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049,
+    3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// The order the lengths of the code length codes are given in.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const MAX_BITS: usize = 15;
+
+/// Decompress some raw DEFLATE data, without a zlib or gzip header.
+pub(crate) fn inflate(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut bits = Bits { data, pos: 0, buf: 0, count: 0 };
+    let mut out = vec![];
+    loop {
+        let last = bits.take(1)? == 1;
+        match bits.take(2)? {
+            0 => stored_block(&mut bits, &mut out)?,
+            1 => {
+                let (lengths, distances) = fixed_codes();
+                compressed_block(&mut bits, &mut out, &lengths, &distances)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_codes(&mut bits)?;
+                compressed_block(&mut bits, &mut out, &lengths, &distances)?;
+            }
+            _ => bail!("invalid DEFLATE block type"),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/// Reads bits from the data, least significant first, as DEFLATE packs them.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn take(&mut self, n: u32) -> anyhow::Result<u32> {
+        while self.count < n {
+            let byte = match self.data.get(self.pos) {
+                Some(&byte) => byte,
+                None => bail!("DEFLATE data ends too soon"),
+            };
+            self.pos += 1;
+            self.buf |= u32::from(byte) << self.count;
+            self.count += 8;
+        }
+        let value = self.buf & ((1 << n) - 1);
+        self.buf >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Skip to the next byte boundary and take some whole bytes from there.
+    fn bytes(&mut self, n: usize) -> anyhow::Result<&[u8]> {
+        self.buf = 0;
+        self.count = 0;
+        match self.data.get(self.pos .. self.pos + n) {
+            Some(bytes) => {
+                self.pos += n;
+                Ok(bytes)
+            }
+            None => bail!("DEFLATE data ends too soon"),
+        }
+    }
+}
+
+/// A canonical Huffman code, by how many codes there are of each length, and the symbols they
+/// stand for in order of their codes.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// The code given by the length of each symbol's code, with 0 for symbols that aren't used.
+    fn new(lengths: &[u8]) -> anyhow::Result<Self> {
+        let mut counts = [0; MAX_BITS + 1];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+        // Each length can have at most twice the codes left over from the length before it.
+        let mut left = 1i32;
+        for &count in &counts[1 ..] {
+            left = left * 2 - i32::from(count);
+            if left < 0 {
+                bail!("invalid DEFLATE Huffman code");
+            }
+        }
+        let mut offsets = [0; MAX_BITS + 1];
+        for len in 1 .. MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                let offset = &mut offsets[usize::from(len)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    /// Read the next symbol, a bit at a time. The codes of each length are consecutive, so a code
+    /// is found once it's less than the first code of its length plus how many there are.
+    fn decode(&self, bits: &mut Bits) -> anyhow::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1 ..] {
+            code |= bits.take(1)? as i32;
+            let count = i32::from(count);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        bail!("invalid DEFLATE code")
+    }
+}
+
+fn stored_block(bits: &mut Bits, out: &mut Vec<u8>) -> anyhow::Result<()> {
+    let header = bits.bytes(4)?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let complement = u16::from_le_bytes([header[2], header[3]]);
+    if len != !complement {
+        bail!("invalid DEFLATE stored block length");
+    }
+    out.extend_from_slice(bits.bytes(usize::from(len))?);
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[.. 144].fill(8);
+    lengths[144 .. 256].fill(9);
+    lengths[256 .. 280].fill(7);
+    lengths[280 ..].fill(8);
+    let lengths = Huffman::new(&lengths).expect("the fixed DEFLATE codes are valid");
+    let distances = Huffman::new(&[5; 30]).expect("the fixed DEFLATE codes are valid");
+    (lengths, distances)
+}
+
+/// Read the codes a dynamic block gives at its start, which are themselves Huffman-coded.
+fn dynamic_codes(bits: &mut Bits) -> anyhow::Result<(Huffman, Huffman)> {
+    let literal_count = bits.take(5)? as usize + 257;
+    let distance_count = bits.take(5)? as usize + 1;
+    let code_length_count = bits.take(4)? as usize + 4;
+    let mut code_lengths = [0; 19];
+    for &i in &CODE_LENGTH_ORDER[.. code_length_count] {
+        code_lengths[i] = bits.take(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (len, repeat) = match code_lengths.decode(bits)? {
+            len @ 0 ..= 15 => (len as u8, 1),
+            16 => match lengths.last() {
+                Some(&prev) => (prev, 3 + bits.take(2)?),
+                None => bail!("invalid DEFLATE code lengths: a repeat with nothing to repeat"),
+            },
+            17 => (0, 3 + bits.take(3)?),
+            _ => (0, 11 + bits.take(7)?),
+        };
+        lengths.resize(lengths.len() + repeat as usize, len);
+    }
+    if lengths.len() > literal_count + distance_count {
+        bail!("invalid DEFLATE code lengths: too many of them");
+    }
+    if lengths[256] == 0 {
+        bail!("invalid DEFLATE code lengths: no code for the end of the block");
+    }
+    let distances = Huffman::new(&lengths[literal_count ..])?;
+    lengths.truncate(literal_count);
+    Ok((Huffman::new(&lengths)?, distances))
+}
+
+fn compressed_block(bits: &mut Bits, out: &mut Vec<u8>, lengths: &Huffman, distances: &Huffman)
+    -> anyhow::Result<()>
+{
+    loop {
+        let symbol = usize::from(lengths.decode(bits)?);
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        } else if symbol == 256 {
+            return Ok(());
+        }
+        // A length and distance back into what's been written, to copy from.
+        let i = symbol - 257;
+        if i >= LENGTH_BASE.len() {
+            bail!("invalid DEFLATE length code");
+        }
+        let len = usize::from(LENGTH_BASE[i]) + bits.take(u32::from(LENGTH_EXTRA[i]))? as usize;
+        let i = usize::from(distances.decode(bits)?);
+        if i >= DISTANCE_BASE.len() {
+            bail!("invalid DEFLATE distance code");
+        }
+        let distance = usize::from(DISTANCE_BASE[i])
+            + bits.take(u32::from(DISTANCE_EXTRA[i]))? as usize;
+        if distance > out.len() {
+            bail!("invalid DEFLATE distance, before the start of the data");
+        }
+        // The copy can overlap what it's adding, repeating the last `distance` bytes.
+        let start = out.len() - distance;
+        for i in start .. start + len {
+            out.push(out[i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn stored() {
+        // These are all made with zlib, this one at level 0.
+        let data = [0x01, 0x05, 0x00, 0xfa, 0xff, b'<', b'g', b'p', b'x', b'>'];
+        assert_eq!(inflate(&data).unwrap(), b"<gpx>");
+    }
+
+    #[test]
+    fn fixed() {
+        let data = [0xb3, 0x49, 0x2f, 0xa8, 0xb0, 0xb3, 0xd1, 0x07, 0x93, 0x08, 0x26, 0x00];
+        assert_eq!(inflate(&data).unwrap(), b"<gpx></gpx><gpx></gpx>");
+    }
+
+    #[test]
+    fn dynamic() {
+        let text = (0 .. 50)
+            .map(|i| format!("<trkpt lat=\"47.{:03}\" lon=\"-122.300\"/>\n", i * 7))
+            .collect::<String>();
+        let data = include_bytes!("../testdata/points.deflate");
+        assert_eq!(String::from_utf8(inflate(data).unwrap()).unwrap(), text);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(inflate(&[]).is_err());
+        // Block type 3.
+        assert!(inflate(&[0x07]).is_err());
+        // A stored block whose length doesn't match its complement.
+        assert!(inflate(&[0x01, 0x05, 0x00, 0xfa, 0xfe, b'<', b'g', b'p', b'x', b'>']).is_err());
+        // A fixed block copying from before the start.
+        assert!(inflate(&[0x03, 0x02, 0x00]).is_err());
+        // Cut off partway.
+        assert!(inflate(&[0xb3, 0x49, 0x2f, 0xa8]).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::Timestamp;
+use crate::point::{format_number, TrackPoint};
+use crate::tree::Element;
+
+/// How to smooth the noise out of track point coordinates with a Kalman filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Kalman {
+    /// How far, in meters, the position is expected to change each second (or from one point to
+    /// the next, between points without times). Lower values smooth more.
+    pub process_noise: f64,
+    /// How far off, in meters, the GPS's measurements typically are.
+    pub measurement_noise: f64,
+    /// Smooth the elevations too, with the same noise values.
+    pub elevation: bool,
+}
+
+impl Kalman {
+    pub const DEFAULT_PROCESS_NOISE: f64 = 3.;
+    pub const DEFAULT_MEASUREMENT_NOISE: f64 = 10.;
+}
+
+/// An estimate of one coordinate, with its variance in square meters. The value itself can be in
+/// any unit, since the filter only ever moves it part of the way toward a measurement.
+struct Estimate {
+    value: f64,
+    variance: f64,
+}
+
+impl Estimate {
+    /// Move the estimate toward a measurement with the given variance.
+    fn update(&mut self, measured: f64, noise: f64) -> f64 {
+        let gain = self.variance / (self.variance + noise);
+        self.value += gain * (measured - self.value);
+        self.variance *= 1. - gain;
+        self.value
+    }
+}
+
+/// Replace the coordinates of the points in each segment of a `<trk>` element with ones smoothed
+/// by a Kalman filter, going forward through the segment. Latitude and longitude are filtered
+/// separately, with the same noise.
+///
+/// Points with missing or invalid coordinates are left as they are.
+pub(crate) fn smooth_track(trk: &mut Element, kalman: Kalman) {
+    let process = kalman.process_noise.powi(2);
+    let noise = kalman.measurement_noise.powi(2);
+    for seg in trk.elements_mut().filter(|el| el.name() == b"trkseg") {
+        let mut position: Option<(Estimate, Estimate)> = None;
+        let mut elevation: Option<Estimate> = None;
+        let mut prev_time: Option<Timestamp> = None;
+        for pt in seg.elements_mut().filter(|el| el.name() == b"trkpt") {
+            let p = match TrackPoint::from_element(pt) {
+                Some(p) => p,
+                None => continue,
+            };
+            let seconds = match (prev_time, p.time) {
+                (Some(prev), Some(time)) => ((time.0 - prev.0) as f64 / 1000.).max(0.),
+                _ => 1.,
+            };
+            prev_time = p.time;
+            let start = |value| Estimate { value, variance: noise };
+            match position {
+                Some((ref mut lat, ref mut lon)) => {
+                    lat.variance += process * seconds;
+                    lon.variance += process * seconds;
+                    pt.set_attr(b"lat", &format_number(lat.update(p.lat, noise), 7));
+                    pt.set_attr(b"lon", &format_number(lon.update(p.lon, noise), 7));
+                }
+                None => position = Some((start(p.lat), start(p.lon))),
+            }
+            if !kalman.elevation {
+                continue;
+            }
+            if let Some(ref mut ele) = elevation {
+                ele.variance += process * seconds;
+            }
+            match (&mut elevation, p.ele) {
+                (Some(ele), Some(measured)) => {
+                    let value = format_number(ele.update(measured, noise), 2);
+                    pt.child_mut(b"ele").unwrap().set_text(&value);
+                }
+                (None, Some(measured)) => elevation = Some(start(measured)),
+                (_, None) => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Document;
+
+    #[test]
+    fn smooth() {
+        // Heading north, with the longitude jumping about 7 meters back and forth.
+        let points = (0 .. 20)
+            .map(|i| format!("<trkpt lat=\"{}\" lon=\"{}\"><ele>{}</ele></trkpt>",
+                47. + i as f64 * 0.0001, if i % 2 == 0 { "-121.0000" } else { "-121.0001" },
+                100 + i % 2 * 10))
+            .collect::<String>();
+        let gpx = format!("<gpx><trk><trkseg>{}<trkpt lat=\"x\" lon=\"1\"/></trkseg></trk>\
+            </gpx>", points);
+        let kalman = Kalman {
+            process_noise: Kalman::DEFAULT_PROCESS_NOISE,
+            measurement_noise: Kalman::DEFAULT_MEASUREMENT_NOISE,
+            elevation: false,
+        };
+        let mut doc = Document::read(gpx.as_bytes()).unwrap();
+        let trk = doc.root.child_mut(b"trk").unwrap();
+        smooth_track(trk, kalman);
+        let lons = trk.track_points()
+            .filter_map(|pt| TrackPoint::from_element(pt).map(|p| p.lon))
+            .collect::<Vec<_>>();
+        assert_eq!(lons.len(), 20);
+        assert_eq!(lons[0], -121.);
+        // After the first few points, the jumps are much smaller.
+        for pair in lons[5 ..].windows(2) {
+            assert!((pair[1] - pair[0]).abs() < 0.00003, "{:?}", lons);
+        }
+        // The point that isn't valid is kept, and the elevations aren't touched.
+        assert_eq!(trk.track_points().last().unwrap().attr(b"lat").unwrap(), "x");
+        assert_eq!(trk.track_points().nth(1).unwrap().child(b"ele").unwrap().text(), "110");
+
+        let mut doc = Document::read(gpx.as_bytes()).unwrap();
+        let trk = doc.root.child_mut(b"trk").unwrap();
+        smooth_track(trk, Kalman { elevation: true, ..kalman });
+        let elevations = trk.track_points()
+            .filter_map(|pt| pt.child(b"ele")?.text().parse::<f64>().ok())
+            .collect::<Vec<_>>();
+        assert_eq!(elevations[0], 100.);
+        assert!(elevations[5 ..].iter().all(|&ele| ele > 102. && ele < 108.), "{:?}", elevations);
+    }
+}
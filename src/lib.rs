@@ -0,0 +1,2707 @@
+use anyhow::bail;
+use quick_xml::{Reader, Writer};
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use std::io::{BufRead, Write};
+use std::time::Instant;
+use sha256::HashingReader;
+use strategy::SourceTrack;
+use tree::{Element, TreeBuilder};
+use filter::FilterState;
+use stats::TrackRecorder;
+use checkpoint::ProgressWriter;
+
+#[macro_use]
+pub mod log;
+
+mod annotate;
+mod area;
+mod checkpoint;
+mod convert;
+mod creators;
+mod despike;
+mod diff;
+mod elevation;
+mod extensions;
+mod filter;
+mod fix;
+#[cfg(feature = "fit-output")]
+mod fit;
+mod gaps;
+mod garmin;
+mod geo;
+mod header;
+mod index;
+mod inflate;
+mod kalman;
+mod metadata;
+mod nmea;
+mod point;
+mod pretty;
+mod profile;
+mod resample;
+#[cfg(feature = "prost")]
+mod protobuf;
+mod schema;
+mod sha256;
+mod simplify;
+mod snap;
+mod split;
+mod stationary;
+mod stats;
+mod strategy;
+mod summary;
+mod time;
+mod track;
+mod tracks;
+mod tree;
+mod validate;
+mod zip;
+
+pub use area::{Area, BoundingBox};
+pub use checkpoint::Checkpoint;
+pub use convert::PointConversion;
+pub use diff::{diff_tracks, TrackChange, TrackDiff};
+pub use extensions::Extensions;
+pub use filter::{EventFilters, FilterAction, GpxEventFilter};
+#[cfg(feature = "fit-output")]
+pub use fit::write_fit;
+pub use header::write_header;
+pub use index::Index;
+pub use kalman::Kalman;
+pub use metadata::{Copyright, Metadata};
+pub use nmea::write_nmea;
+pub use pretty::pretty_print;
+pub use profile::elevation_profile;
+pub use resample::Resample;
+#[cfg(feature = "prost")]
+pub use protobuf::write_protobuf;
+pub use simplify::SimplifyAlgorithm;
+pub use stationary::TrimStationary;
+pub use snap::snap_waypoints;
+pub use split::split_by_date;
+pub use stats::{FileStats, JoinStats, SegmentStats};
+pub use strategy::{DuplicateNames, JoinStrategy, MergeStrategy};
+pub use summary::{Summary, TrackSummary};
+pub use time::Timestamp;
+pub use tracks::{read_gpx_tracks, write_gpx_with_tracks, TrackXml};
+pub use validate::{check_well_formed, validate_gpx, ValidationError};
+pub use zip::{ZipArchive, ZipWriter};
+
+trait StartsWithExt<U> {
+    fn starts_with(&self, other: U) -> bool;
+}
+
+// Because the stdlib's slice::starts_with() doesn't work when the inner type is Vec<u8> and we're
+// comparing against &[u8]. Anything else that derefs to bytes works too, including Vec<u8>.
+impl<S: AsRef<[u8]>> StartsWithExt<&[S]> for Vec<Vec<u8>> {
+    fn starts_with(&self, other: &[S]) -> bool {
+        path_starts_with(self, other)
+    }
+}
+
+fn path_starts_with<S: AsRef<[u8]>>(path: &[Vec<u8>], pattern: &[S]) -> bool {
+    if path.len() < pattern.len() {
+        return false;
+    }
+    for (a, b) in path.iter().zip(pattern) {
+        if a != b.as_ref() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Whether a path of element names, from the root element down, starts with the given names: that
+/// is, whether it's the path of an element matching the pattern, or of something inside one.
+///
+/// This is how `join_gpx` decides which parts of a document an element belongs to, for custom
+/// filtering of the same kind.
+///
+/// ```
+/// let path = vec![b"gpx".to_vec(), b"trk".to_vec(), b"name".to_vec()];
+/// assert!(gpxjoin::path_matches(&path, &[b"gpx", b"trk"]));
+/// assert!(gpxjoin::path_matches(&path, &[b"gpx", b"trk", b"name"]));
+/// assert!(!gpxjoin::path_matches(&path, &[b"gpx", b"metadata"]));
+/// assert!(!gpxjoin::path_matches(&path[.. 1], &[b"gpx", b"trk"]));
+/// ```
+pub fn path_matches(path: &[Vec<u8>], pattern: &[&[u8]]) -> bool {
+    path_starts_with(path, pattern)
+}
+
+/// Options for how to join files.
+#[derive(Debug, Clone, Default)]
+pub struct JoinConfig {
+    /// Repair common defects in the input: swapped latitude and longitude, line breaks inside
+    /// timestamps, a byte order mark at the start of a file, and consecutive duplicate points.
+    pub fix: bool,
+
+    /// Swap the latitude and longitude of points where they're the wrong way around, with a
+    /// warning. This is one of the things `fix` does, on its own.
+    pub enforce_coordinate_order: bool,
+
+    /// Put the elements inside each track in the order the GPX 1.1 schema requires: name, cmt,
+    /// desc, src, link, number, type, extensions, then trkseg.
+    pub fix_trk_order: bool,
+
+    /// Fail if any point has a latitude or longitude out of range, after `fix` or
+    /// `enforce_coordinate_order` have had a chance to swap them, instead of warning about it.
+    pub verify_coordinates: bool,
+
+    /// Fail if any track point doesn't have a time.
+    pub require_time: bool,
+
+    /// How tracks from the different files are combined.
+    pub strategy: JoinStrategy,
+
+    /// Put each `<trkseg>` that's directly inside the root element, instead of in a `<trk>`, into
+    /// a new `<trk>`. Otherwise they're only kept from the first file, as they are.
+    pub wrap_in_trk: bool,
+
+    /// Keep only one of the tracks that have the same name.
+    pub merge_strategy: Option<MergeStrategy>,
+
+    /// Which `<name>` to keep in tracks that have more than one. They're all kept if this is None,
+    /// with a warning.
+    pub duplicate_names: Option<DuplicateNames>,
+
+    /// Combine all the tracks from each file into one, with the first track's name and other
+    /// details and all the tracks' segments.
+    pub flatten_tracks: bool,
+
+    /// Shorten track names to at most this many characters.
+    pub truncate_track_names: Option<usize>,
+
+    /// End the names shortened by `truncate_track_names` with "…".
+    pub truncate_ellipsis: bool,
+
+    /// Write the tracks in the opposite order, after the strategies have put them in theirs.
+    /// What's in each track stays in the same order.
+    pub reverse_tracks: bool,
+
+    /// Reverse the order of the points in each track segment. Their times aren't changed.
+    pub reverse_points: bool,
+
+    /// Recognize the `<gpx>` and `<trk>` elements even with a namespace prefix, like `<gpx:trk>`.
+    /// The other options only apply to elements without one.
+    pub ignore_namespace: bool,
+
+    /// Join all the segments of each track into one.
+    pub merge_segments: bool,
+
+    /// Leave out the points at the start of each track segment that are before this time, up to
+    /// the first one at or after it.
+    pub start_time: Option<Timestamp>,
+
+    /// Keep only this many points from the start of each track segment, before anything else is
+    /// done with them besides `start_time`, for a quick preview of the files.
+    pub sample_first: Option<usize>,
+
+    /// Compare track names case-sensitively when matching them up for
+    /// [`JoinStrategy::Replace`] and `merge_strategy`.
+    pub case_sensitive_names: bool,
+
+    /// Convert waypoints into routes or vice versa.
+    pub point_conversion: Option<PointConversion>,
+
+    /// Only keep tracks with a point at or after this time. Tracks without any times are kept.
+    pub since: Option<Timestamp>,
+
+    /// Only keep tracks with a point at or before this time. Tracks without any times are kept.
+    pub until: Option<Timestamp>,
+
+    /// Only keep track points and waypoints within this area.
+    pub area: Option<Area>,
+
+    /// Fill in the elevation of track points that don't have one, from the points around them.
+    pub interpolate_elevation: bool,
+
+    /// Remove track points whose elevation jumps from the previous point's by more than this many
+    /// meters, and then comes back at the next point.
+    pub max_elevation_change: Option<f64>,
+
+    /// Smooth out the noise in track point coordinates.
+    pub smooth_kalman: Option<Kalman>,
+
+    /// Remove the points in the middle of clusters logged while standing still.
+    pub trim_stationary: Option<TrimStationary>,
+
+    /// Bridge gaps of at most this many seconds between consecutive track segments, and between
+    /// consecutive tracks, with a new segment of interpolated points.
+    pub max_gap_interpolate: Option<f64>,
+
+    /// Reduce the number of points in each track segment.
+    pub simplify: Option<SimplifyAlgorithm>,
+
+    /// Replace the points of each track segment with evenly spaced ones.
+    pub resample: Option<Resample>,
+
+    /// Leave out track segments with fewer points than this, after everything else is done to
+    /// them.
+    pub min_points_per_segment: Option<usize>,
+
+    /// Add each track point's speed in m/s to its extensions, as an element with this name.
+    pub speed_extension: Option<String>,
+
+    /// Add the distance in meters along the track segment to each track point's extensions, as
+    /// `<dist>`.
+    pub distance_extension: bool,
+
+    /// With `distance_extension`, keep counting the distance from one segment to the next,
+    /// instead of starting again at zero. The gaps between segments aren't counted.
+    pub distance_across_segments: bool,
+
+    /// Add the compass bearing from each track point to the next to its extensions, as
+    /// `<bearing>`.
+    pub bearing_extension: bool,
+
+    /// Add a `<summary>` of each track to its extensions, with its number of points, its distance
+    /// in meters, and the seconds between its first and last times, as it's written out.
+    pub track_summaries: bool,
+
+    /// Set the `<cmt>` of every track to this, replacing any it already has.
+    pub track_comment: Option<String>,
+
+    /// Give tracks with a Strava activity type the matching Garmin display color.
+    pub strava_to_garmin: bool,
+
+    /// XML comments to add before the root element, in order.
+    pub comments: Vec<String>,
+
+    /// The names of the sources, in order, to put in a comment before each track saying which
+    /// source it came from.
+    pub track_source_comments: Option<Vec<String>>,
+
+    /// Declare whether the output is standalone in the XML declaration, adding one if the first
+    /// file doesn't have one. Otherwise the first file's declaration is kept as it is.
+    pub standalone: Option<bool>,
+
+    /// Leave out the XML declaration, like when the output is going into a larger document.
+    pub suppress_xml_declaration: bool,
+
+    /// Write the content of CDATA sections as escaped text instead, for tools that don't
+    /// understand them.
+    pub strip_cdata: bool,
+
+    /// Set the root element's `version` attribute to this, instead of keeping the first file's.
+    pub gpx_version: Option<String>,
+
+    /// Fail if a file's root element has a different namespace, and so a different GPX version,
+    /// than the first file's, instead of warning about it.
+    pub check_gpx_version: bool,
+
+    /// Add `xsi:schemaLocation` to the root element, pointing at the GPX 1.1 schema.
+    pub schema_location: bool,
+
+    /// Clean up the root element's `creator` attribute, leaving out version numbers and using
+    /// the canonical names of well-known apps.
+    pub normalize_creator: bool,
+
+    /// Declare this encoding in the XML declaration, adding one if the first file doesn't have
+    /// it. Only the declaration changes: the output is still UTF-8.
+    pub encoding: Option<String>,
+
+    /// Leave out the first file's `<metadata>` element.
+    pub no_metadata: bool,
+
+    /// Leave out all the tracks, so the output only has the first file's metadata, waypoints,
+    /// routes, and extensions.
+    pub no_tracks: bool,
+
+    /// Leave out the first file's waypoints.
+    pub no_waypoints: bool,
+
+    /// Leave out the first file's routes.
+    pub no_routes: bool,
+
+    /// User-defined filters of the XML events of all the files, run in order as they're read.
+    pub event_filters: EventFilters,
+
+    /// Use this in place of the first file's `<metadata>` element, or add it if the first file
+    /// doesn't have one.
+    pub metadata: Option<Metadata>,
+
+    /// Set the metadata's `<keywords>` to this, replacing any it already has.
+    pub metadata_keywords: Option<String>,
+
+    /// Set the metadata's `<copyright>` to this, replacing any it already has.
+    pub metadata_copyright: Option<Copyright>,
+
+    /// Add this to the end of the first file's root-level `<extensions>` element, or add one just
+    /// before the end of the root element if it doesn't have one.
+    pub gpx_extensions: Option<Extensions>,
+}
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+const GPX_SCHEMA_LOCATION: &str =
+    "http://www.topografix.com/GPX/1/1 http://www.topografix.com/GPX/1/1/gpx.xsd";
+
+/// A small but complete GPX 1.1 document, with metadata and one track of two points, for trying
+/// things out and as a baseline in tests.
+pub const EXAMPLE_GPX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="gpxjoin" xmlns="http://www.topografix.com/GPX/1/1"
+    xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+    xsi:schemaLocation="http://www.topografix.com/GPX/1/1
+        http://www.topografix.com/GPX/1/1/gpx.xsd">
+    <metadata>
+        <name>Example</name>
+        <time>2021-08-27T10:00:00Z</time>
+    </metadata>
+    <trk>
+        <name>Example track</name>
+        <trkseg>
+            <trkpt lat="47.543448" lon="-121.096462">
+                <ele>1008.6</ele>
+                <time>2021-08-27T10:00:00Z</time>
+            </trkpt>
+            <trkpt lat="47.543501" lon="-121.096385">
+                <ele>1009.2</ele>
+                <time>2021-08-27T10:00:05Z</time>
+            </trkpt>
+        </trkseg>
+    </trk>
+</gpx>
+"#;
+
+/// Whether the tracks need to be collected to put them in order or combine them, instead of
+/// being written as they're read.
+fn buffers_tracks(config: &JoinConfig) -> bool {
+    config.strategy != JoinStrategy::Append
+        || config.merge_strategy.is_some()
+        || config.flatten_tracks
+        || config.reverse_tracks
+        || config.max_gap_interpolate.is_some()
+}
+
+/// Whether tracks can be written out a point at a time as they're read, instead of each being
+/// built up into a tree first, because nothing needs to look at a whole track at once.
+fn streams_tracks(config: &JoinConfig) -> bool {
+    !(buffers_tracks(config)
+        || config.fix
+        || config.enforce_coordinate_order
+        || config.fix_trk_order
+        || config.duplicate_names.is_some()
+        || config.truncate_track_names.is_some()
+        || config.start_time.is_some()
+        || config.sample_first.is_some()
+        || config.merge_segments
+        || config.reverse_points
+        || config.since.is_some()
+        || config.until.is_some()
+        || config.strava_to_garmin
+        || config.track_comment.is_some()
+        || config.area.is_some()
+        || config.interpolate_elevation
+        || config.max_elevation_change.is_some()
+        || config.smooth_kalman.is_some()
+        || config.trim_stationary.is_some()
+        || config.simplify.is_some()
+        || config.resample.is_some()
+        || config.min_points_per_segment.is_some()
+        || config.speed_extension.is_some()
+        || config.distance_extension
+        || config.bearing_extension
+        || config.point_conversion.is_some()
+        || config.track_summaries
+        || config.require_time)
+}
+
+pub fn join_gpx<R: BufRead, W: Write>(sources: &mut [R], dest: W, config: &JoinConfig)
+    -> anyhow::Result<()>
+{
+    join_gpx_with_stats(sources, dest, config).map(|_| ())
+}
+
+/// Like [`join_gpx`], but also returns information about the tracks written.
+pub fn join_gpx_with_stats<R: BufRead, W: Write>(
+    sources: &mut [R],
+    dest: W,
+    config: &JoinConfig,
+) -> anyhow::Result<JoinStats> {
+    join_sources(sources, dest, config, None, None)
+}
+
+/// Like [`join_gpx_with_stats`], but calls `on_checkpoint` with how far it's got after each
+/// source is joined, once the output up to that point has been written and flushed.
+///
+/// Given a checkpoint from before, the join picks up from there: `dest` must be the output that
+/// had been written then, truncated to `bytes_written` and positioned at the end. The first
+/// source is read again to get what comes after its tracks, and the other sources already done
+/// are skipped. The stats include the first source's tracks, but not the others'.
+///
+/// Only [`JoinStrategy::Append`] without any of the options that need all the tracks at once,
+/// like `merge_strategy` or `reverse_tracks`, can be resumed, since the others write all the
+/// tracks at the end.
+pub fn join_gpx_resumable<R: BufRead, W: Write>(
+    sources: &mut [R],
+    dest: W,
+    config: &JoinConfig,
+    resume: Option<Checkpoint>,
+    mut on_checkpoint: impl FnMut(Checkpoint) -> anyhow::Result<()>,
+) -> anyhow::Result<JoinStats> {
+    if buffers_tracks(config) {
+        bail!("only appending tracks, without merging, reversing, flattening, or bridging gaps \
+            between them, can be checkpointed");
+    }
+    if let Some(checkpoint) = resume.filter(|c| c.sources_done > sources.len()) {
+        bail!("the checkpoint is after {} sources, but there are only {}",
+            checkpoint.sources_done, sources.len());
+    }
+    join_sources(sources, dest, config, resume, Some(&mut on_checkpoint))
+}
+
+/// The sources are read as a stream, and written out as they're read: the first file up to its
+/// `</gpx>`, then each later file's tracks, then the rest of the first file. Tracks are written a
+/// point at a time as they're read, unless [`streams_tracks`] says something needs to look at
+/// each of them as a whole, so by default the memory used doesn't depend on the size of the files
+/// or of the tracks in them. Other top-level elements are built up into a tree so they can be
+/// processed as a whole, as are tracks when they're not streamed, and the tracks are all held on
+/// to when [`buffers_tracks`] says they have to be.
+fn join_sources<R: BufRead, W: Write>(
+    sources: &mut [R],
+    dest: W,
+    config: &JoinConfig,
+    resume: Option<Checkpoint>,
+    mut on_checkpoint: Option<&mut dyn FnMut(Checkpoint) -> anyhow::Result<()>>,
+) -> anyhow::Result<JoinStats> {
+    if let Some(bad) = config.comments.iter().find(|c| c.contains("--") || c.ends_with('-')) {
+        bail!("XML comments can't contain \"--\" or end with \"-\": {:?}", bad);
+    }
+    if let Some(ref names) = config.track_source_comments {
+        if names.len() != sources.len() {
+            bail!("track source comments need a name for each of the {} sources, not {}",
+                sources.len(), names.len());
+        }
+        if let Some(bad) = names.iter().find(|name| name.contains("--")) {
+            bail!("XML comments can't contain \"--\", so a track source can't be named {:?}",
+                bad);
+        }
+    }
+    if config.suppress_xml_declaration && (config.encoding.is_some() || config.standalone.is_some())
+    {
+        bail!("an encoding or standalone declaration can't be made when leaving out the XML \
+            declaration");
+    }
+    if let Some(ref encoding) = config.encoding {
+        // The XML spec's EncName production.
+        let valid = encoding.starts_with(|c: char| c.is_ascii_alphabetic())
+            && encoding.chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+        if !valid {
+            bail!("invalid encoding name {:?}", encoding);
+        }
+    }
+    let started = Instant::now();
+    let mut first = None;
+    let mut buf = vec![];
+    let writer = Writer::new(ProgressWriter::new(dest, resume));
+    let mut out = Output::new(writer, config.track_source_comments.clone(),
+        config.track_summaries);
+    let done = resume.map_or(0, |c| c.sources_done);
+    // All the tracks are collected and written out at the end of the first file's tracks, if
+    // they can't just be copied through in order.
+    let mut buffered_tracks = if buffers_tracks(config) { Some(vec![]) } else { None };
+    let mut hashes = vec![[0; 32]; sources.len()];
+    // The default namespace of the first file's root element, which the output uses.
+    let mut namespace = None;
+    // Extensions still to be added, if the first file has no `<extensions>` to add them to.
+    let mut extensions = config.gpx_extensions.clone();
+    let streams = streams_tracks(config);
+    for (index, source) in sources.iter_mut().enumerate() {
+        if index >= done {
+            out.writer.inner().discard = false;
+        } else if index > 0 {
+            // Already joined before resuming.
+            continue;
+        }
+        let source_started = Instant::now();
+        let mut path = vec![];
+        let mut r = Reader::from_reader(HashingReader::new(source));
+        let mut tree = TreeBuilder::default();
+        // The track being written out as it's read, if tracks are streamed.
+        let mut streaming = None;
+        let mut filters = FilterState::new(&config.event_filters);
+        let mut at_start = true;
+        let mut stashed = None;
+        // The end tag made up for a first file whose root is self-closing, to read next.
+        let mut pending_end = None;
+        let mut seen_decl = false;
+        // Whether the line break after a left-out XML declaration is still to be left out too.
+        let mut skip_decl_space = false;
+        // The name of the root element, as it is in the file.
+        let mut root = None;
+        // Replacement metadata still to be written in the first file. If there's none, but some
+        // of the metadata is to be set, empty metadata is added for it if the file has none.
+        let mut metadata = match first {
+            None if config.metadata.is_none() && sets_metadata(config) => {
+                Some(Metadata(Element::new("metadata")))
+            }
+            None => config.metadata.clone(),
+            Some(_) => None,
+        };
+        loop {
+            let mut evt = match pending_end.take() {
+                Some(end) => end,
+                None => tree::read_event(&mut r, &mut buf)?,
+            };
+            if config.strip_cdata {
+                evt = tree::cdata_to_text(evt);
+            }
+            let expands_root = match evt {
+                Event::Empty(ref empty) => first.is_none() && path.is_empty()
+                    && path_name(empty.name(), config) == b"gpx",
+                _ => false,
+            };
+            if expands_root {
+                // A first file that's just `<gpx/>` is an empty document, which the other files'
+                // tracks go into, so it's read as `<gpx></gpx>`.
+                if let Event::Empty(empty) = evt {
+                    pending_end = Some(Event::End(BytesEnd::owned(empty.name().to_vec())));
+                    evt = Event::Start(empty);
+                }
+            }
+            if !matches!(evt, Event::Eof) {
+                let top_level = path.len() == 1 && matches!(evt, Event::Start(_) | Event::Empty(_));
+                match filters.apply(evt, &path)? {
+                    Some(filtered) => evt = filtered,
+                    None => {
+                        if top_level && first.is_none() {
+                            out.skip_element();
+                        }
+                        buf.clear();
+                        continue;
+                    }
+                }
+            }
+            let mut top_level_empty = false;
+            let mut skip = false;
+            match evt {
+                Event::Eof => break,
+                Event::Decl(_)
+                    if first.is_none() && path.is_empty() && config.suppress_xml_declaration =>
+                {
+                    skip = true;
+                    skip_decl_space = true;
+                }
+                Event::Decl(ref decl) if first.is_none() && path.is_empty() => {
+                    seen_decl = true;
+                    if config.encoding.is_some() || config.standalone.is_some() {
+                        let version = decl.version()?.into_owned();
+                        let encoding = match config.encoding {
+                            Some(ref encoding) => Some(encoding.as_bytes().to_vec()),
+                            None => decl.encoding().transpose()?.map(|e| e.into_owned()),
+                        };
+                        let standalone = match config.standalone {
+                            Some(standalone) => Some(standalone_value(standalone).to_vec()),
+                            None => decl.standalone().transpose()?.map(|s| s.into_owned()),
+                        };
+                        evt = Event::Decl(BytesDecl::new(&version, encoding.as_deref(),
+                            standalone.as_deref()));
+                    }
+                }
+                Event::Start(ref start) => {
+                    if first.is_none() && path.is_empty() {
+                        let declare = config.encoding.is_some() || config.standalone.is_some();
+                        if declare && !seen_decl {
+                            let encoding = config.encoding.as_ref().map(|e| e.as_bytes());
+                            let standalone = config.standalone.map(standalone_value);
+                            out.event(Event::Decl(BytesDecl::new(b"1.0", encoding, standalone)
+                                .into_owned()))?;
+                            out.event(Event::Text(BytesText::from_escaped(&b"\n"[..])))?;
+                        }
+                        for comment in &config.comments {
+                            out.event(Event::Comment(BytesText::from_escaped(
+                                comment.as_bytes().to_vec())))?;
+                            out.event(Event::Text(BytesText::from_escaped(&b"\n"[..])))?;
+                        }
+                    }
+                    if path.is_empty() {
+                        check_namespace(start, &mut namespace, index, config)?;
+                        root = Some(start.name().to_vec());
+                    }
+                    path.push(path_name(start.name(), config).to_owned());
+                    if first.is_none() && path.len() == 1 {
+                        if let Some(root) = rewrite_root(start, config) {
+                            evt = Event::Start(root);
+                        }
+                    }
+                }
+                Event::Empty(ref empty) if path.is_empty() => root = Some(empty.name().to_vec()),
+                Event::Empty(ref empty) if path.len() == 1 => {
+                    // A self-closing top-level element, like a degenerate `<trk/>`: treat it as
+                    // entering and leaving it in one step, so it's handled like any other.
+                    path.push(path_name(empty.name(), config).to_owned());
+                    top_level_empty = true;
+                }
+                Event::End(_) if first.is_none() && path == [b"gpx"] => {
+                    // Done with tracks, save the first file reader at this point and move on
+                    // to the next file.
+                    // Note we can only do this when we're sure there won't be additional track
+                    // elements, hence why the check is when the path is just "gpx".
+                    if let Some(Metadata(el)) = metadata.take() {
+                        out.insert_elements(process_element(el, config))?;
+                    }
+                    stashed = Some(evt.into_owned());
+                    // Leaving `buf` uncleared here is fine: the event was copied out above, and
+                    // quick_xml appends each event it reads to the end of the buffer and only
+                    // borrows from there on, so whatever's left in it is never looked at again.
+                    // It's cleared after the next event read.
+                    break;
+                }
+                Event::Text(ref text) if skip_decl_space && tree::is_whitespace(text) => {
+                    skip = true;
+                }
+                Event::Text(ref text) if at_start && config.fix && text.starts_with(BOM) => {
+                    info!("fix: removed byte order mark from source {}", index + 1);
+                    evt = Event::Text(BytesText::from_escaped(text[BOM.len() ..].to_vec()));
+                }
+                _ => (),
+            }
+            at_start = false;
+            skip_decl_space &= skip;
+            let entering = path.len() == 2 && (top_level_empty || matches!(evt, Event::Start(_)));
+            if entering && path[1] != b"metadata" {
+                // The first file has no metadata of its own: the replacement goes before
+                // whatever comes first.
+                if let Some(Metadata(el)) = metadata.take() {
+                    out.insert_elements(process_element(el, config))?;
+                }
+            }
+            let starts_track = streams && path.len() == 2 && matches!(evt, Event::Start(_))
+                && path.starts_with(&[b"gpx", b"trk"]);
+            if starts_track {
+                if config.no_tracks && first.is_none() {
+                    out.skip_element();
+                }
+                streaming = Some(StreamedTrack::start(evt.into_owned(), index, &mut out, config)?);
+            } else if let Some(ref mut trk) = streaming {
+                let is_end = matches!(evt, Event::End(_));
+                if trk.event(evt.into_owned(), &path, &mut out, config)? {
+                    streaming = None;
+                }
+                if is_end {
+                    path.pop();
+                }
+            } else if path.len() > 1 {
+                // Inside one of the top-level elements: buffer it until it's complete, so it can
+                // be looked at as a whole.
+                let is_end = top_level_empty || matches!(evt, Event::End(_));
+                if let Some(mut el) = tree.push(evt.into_owned()) {
+                    if el.name() == b"metadata" {
+                        match metadata.take() {
+                            Some(Metadata(replacement)) if config.metadata.is_some() => {
+                                el = replacement;
+                            }
+                            _ => (),
+                        }
+                    }
+                    if el.name() == b"extensions" && first.is_none() {
+                        if let Some(ext) = extensions.take() {
+                            ext.append_to(&mut el);
+                        }
+                    }
+                    // A segment outside of any track is made into a track of its own.
+                    let wrap = config.wrap_in_trk && path[1] == b"trkseg";
+                    if wrap {
+                        let mut trk = Element::new("trk");
+                        trk.insert_child(el, &[]);
+                        el = trk;
+                    }
+                    // If this is the first file, write everything, otherwise only write tracks.
+                    let is_track = wrap || path.starts_with(&[b"gpx", b"trk"]);
+                    let left_out = (is_track && config.no_tracks)
+                        || (path[1] == b"wpt" && config.no_waypoints)
+                        || (path[1] == b"rte" && config.no_routes);
+                    if is_track && !left_out && config.require_time {
+                        check_times(&el, index)?;
+                    }
+                    if left_out {
+                        if first.is_none() {
+                            out.skip_element();
+                        }
+                    } else if let (true, Some(tracks)) = (is_track, buffered_tracks.as_mut()) {
+                        if first.is_none() {
+                            out.hold_track_space();
+                        }
+                        let els = process_element(el, config);
+                        check_coordinates(&els, index, config)?;
+                        tracks.extend(els.into_iter().map(|t| (index, t)));
+                    } else if first.is_none() || is_track {
+                        let els = process_element(el, config);
+                        check_coordinates(&els, index, config)?;
+                        out.elements(els, index)?;
+                    }
+                }
+                if is_end {
+                    path.pop();
+                }
+            } else {
+                if let Event::End(ref end) = evt {
+                    match path.pop() {
+                        None => bail!("unexpected </{:?}> tag when path is empty", end.name()),
+                        Some(popped) if popped != path_name(end.name(), config) => {
+                            bail!("start/end tag mismatch: expected </{:?}>, saw </{:?}>", popped, end.name());
+                        }
+                        _ => (),
+                    }
+                }
+                if first.is_none() && !skip {
+                    out.event(evt.into_owned())?;
+                }
+            }
+            buf.clear();
+        }
+        if streaming.is_some() {
+            bail!("source {} ends in the middle of a track", index + 1);
+        }
+        // Tracks are only recognized in a <gpx> root element, so without one, a file would
+        // silently contribute nothing.
+        let is_gpx = root.as_deref().is_some_and(|name| path_name(name, config) == b"gpx");
+        match stashed {
+            Some(evt) => first = Some((r, evt)),
+            None if first.is_none() || !is_gpx => return Err(missing_root(index, root.as_deref())),
+            None => hashes[index] = r.into_underlying_reader().hash.finish(),
+        }
+        if index == 0 {
+            // The rest of the first file is read once the others are done.
+            debug!("read source 1 up to its </gpx> in {:.1?}", source_started.elapsed());
+        } else {
+            debug!("read source {} in {:.1?}", index + 1, source_started.elapsed());
+        }
+        if let Some(ref mut on_checkpoint) = on_checkpoint {
+            // Write out the whitespace being held back, so that what comes next doesn't depend on
+            // what came before the checkpoint. This can leave out some repeated indentation.
+            out.finish()?;
+            if index >= done {
+                let progress = out.writer.inner();
+                progress.flush()?;
+                let written = progress.written;
+                on_checkpoint(Checkpoint { sources_done: index + 1, bytes_written: written })?;
+            }
+        }
+    }
+    out.writer.inner().discard = false;
+
+    // Finish writing out the first file.
+    let (mut first, stashed_evt) = match first {
+        Some(first) => first,
+        None => bail!("need at least one source"),
+    };
+    if let Some(tracks) = buffered_tracks {
+        let tracks = strategy::order_tracks(tracks, config);
+        out.tracks(tracks)?;
+    }
+    if let Some(Extensions(el)) = extensions {
+        out.insert_elements(vec![el])?;
+    }
+    out.event(stashed_evt)?;
+    // The rest of the first file, including anything after its </gpx>. `buf` is still holding
+    // the stashed end tag; see where it was stashed for why that's fine.
+    let mut filters = FilterState::new(&config.event_filters);
+    loop {
+        let mut evt = tree::read_event(&mut first, &mut buf)?;
+        if config.strip_cdata {
+            evt = tree::cdata_to_text(evt);
+        }
+        if matches!(evt, Event::Eof) {
+            break;
+        }
+        if let Some(evt) = filters.apply(evt, &[])? {
+            out.event(evt.into_owned())?;
+        }
+        buf.clear();
+    }
+    out.finish()?;
+
+    hashes[0] = first.into_underlying_reader().hash.finish();
+    let mut stats = out.stats;
+    stats.files.resize(sources.len(), FileStats::default());
+    for (file, hash) in stats.files.iter_mut().zip(hashes) {
+        file.sha256 = hash;
+    }
+    debug!("joined {} sources in {:.1?}, writing {} tracks with {} points", stats.files.len(),
+        started.elapsed(), stats.tracks, stats.files.iter().map(|f| f.point_count).sum::<usize>());
+    Ok(stats)
+}
+
+/// The name of an element for finding where it is in the document: without any namespace prefix
+/// if `ignore_namespace` is set.
+fn path_name<'a>(name: &'a [u8], config: &JoinConfig) -> &'a [u8] {
+    match name.iter().rposition(|&b| b == b':') {
+        Some(colon) if config.ignore_namespace => &name[colon + 1 ..],
+        _ => name,
+    }
+}
+
+fn missing_root(index: usize, root: Option<&[u8]>) -> anyhow::Error {
+    match root {
+        Some(name) if name.contains(&b':') => anyhow::anyhow!("source {} is missing the <gpx> root \
+            element: its root element <{}> has a namespace prefix, which needs ignore_namespace",
+            index + 1, String::from_utf8_lossy(name)),
+        _ => anyhow::anyhow!("source {} is missing the <gpx> root element", index + 1),
+    }
+}
+
+fn standalone_value(standalone: bool) -> &'static [u8] {
+    if standalone { b"yes" } else { b"no" }
+}
+
+/// Warn if a file's root element is in a different namespace than the first file's, like when
+/// joining GPX 1.0 and 1.1 files. The tracks are still joined, but they end up in the first
+/// file's namespace, which might not be valid for them.
+fn check_namespace(
+    root: &BytesStart,
+    namespace: &mut Option<Option<String>>,
+    index: usize,
+    config: &JoinConfig,
+) -> anyhow::Result<()> {
+    let root = Element { start: root.to_owned(), children: vec![], end: None };
+    let this = root.attr(b"xmlns");
+    match namespace {
+        None => *namespace = Some(this),
+        Some(first) if *first != this => {
+            let (this, first) =
+                (this.as_deref().unwrap_or("(none)"), first.as_deref().unwrap_or("(none)"));
+            if config.check_gpx_version {
+                bail!("source {} has namespace {}, but the first file has namespace {}",
+                    index + 1, this, first);
+            }
+            warn!("source {} has namespace {}, but the output has namespace {} from the first \
+                file; its tracks may not be valid there", index + 1, this, first);
+        }
+        Some(_) => (),
+    }
+    Ok(())
+}
+
+/// Warn about points with out-of-range coordinates in some elements from the given source, or
+/// fail if the config says to.
+fn check_coordinates(els: &[Element], index: usize, config: &JoinConfig) -> anyhow::Result<()> {
+    for el in els {
+        report_out_of_range(&fix::out_of_range_points(el), &fix::describe(el), index, config)?;
+    }
+    Ok(())
+}
+
+/// Warn about the given out-of-range points of an element, or fail if the config says to.
+fn report_out_of_range(points: &[String], what: &str, index: usize, config: &JoinConfig)
+    -> anyhow::Result<()>
+{
+    let first = match points.first() {
+        Some(first) => first,
+        None => return Ok(()),
+    };
+    let plural = if points.len() == 1 { "" } else { "s" };
+    let message = format!("source {}: {} has {} point{} with out-of-range coordinates, like {}",
+        index + 1, what, points.len(), plural, first);
+    if config.verify_coordinates {
+        bail!(message);
+    }
+    warn!("{}", message);
+    Ok(())
+}
+
+/// Fail if any point of a track has no time, saying which segment it's in.
+fn check_times(trk: &Element, index: usize) -> anyhow::Result<()> {
+    let segments = trk.elements().filter(|el| el.name() == b"trkseg");
+    for (i, seg) in segments.enumerate() {
+        let untimed = seg.elements()
+            .filter(|el| el.name() == b"trkpt" && el.child(b"time").is_none())
+            .collect::<Vec<_>>();
+        if let Some(pt) = untimed.first() {
+            let plural = if untimed.len() == 1 { "" } else { "s" };
+            bail!("source {}: {} segment {} has {} point{} without a time, like <trkpt lat={:?} \
+                lon={:?}>", index + 1, fix::describe(trk), i + 1, untimed.len(), plural,
+                pt.attr(b"lat").unwrap_or_default(), pt.attr(b"lon").unwrap_or_default());
+        }
+    }
+    Ok(())
+}
+
+/// Whether any of the output's metadata is to be set, adding metadata if the first file has none.
+fn sets_metadata(config: &JoinConfig) -> bool {
+    config.metadata_keywords.is_some() || config.metadata_copyright.is_some()
+}
+
+/// Apply the configured changes to the first file's root element's attributes. None if there
+/// aren't any to make.
+fn rewrite_root(start: &BytesStart, config: &JoinConfig) -> Option<BytesStart<'static>> {
+    if !config.schema_location && config.gpx_version.is_none() && !config.normalize_creator {
+        return None;
+    }
+    let mut root = Element { start: start.to_owned(), children: vec![], end: None };
+    if let Some(ref version) = config.gpx_version {
+        root.set_attr(b"version", version);
+    }
+    if let (true, Some(creator)) = (config.normalize_creator, root.attr(b"creator")) {
+        root.set_attr(b"creator", &creators::normalize_creator(&creator));
+    }
+    if config.schema_location {
+        root.set_attr(b"xmlns:xsi", XSI_NAMESPACE);
+        // Other schemas may already be listed; keep them.
+        let location = match root.attr(b"xsi:schemaLocation") {
+            Some(existing) if existing.contains(GPX_SCHEMA_LOCATION) => existing,
+            Some(existing) => format!("{} {}", GPX_SCHEMA_LOCATION, existing.trim()),
+            None => GPX_SCHEMA_LOCATION.to_owned(),
+        };
+        root.set_attr(b"xsi:schemaLocation", &location);
+    }
+    Some(root.start)
+}
+
+/// Apply the configured transformations to a top-level element. It may turn into any number of
+/// elements.
+fn process_element(mut el: Element, config: &JoinConfig) -> Vec<Element> {
+    if config.no_metadata && el.name() == b"metadata" {
+        return vec![];
+    }
+    if el.name() == b"metadata" {
+        if let Some(ref keywords) = config.metadata_keywords {
+            el.set_child_text("keywords", keywords, schema::METADATA);
+        }
+        if let Some(ref copyright) = config.metadata_copyright {
+            copyright.set(&mut el);
+        }
+    }
+    if config.fix {
+        fix::fix_element(&mut el);
+    } else if config.enforce_coordinate_order {
+        fix::enforce_coordinate_order(&mut el);
+    }
+    if el.name() == b"trk" && config.fix_trk_order && el.sort_children(schema::TRK) {
+        info!("fix: reordered the elements of track {:?} as the schema requires",
+            el.track_name().unwrap_or_default());
+    }
+    if el.name() == b"trk" {
+        strategy::dedupe_names(&mut el, config.duplicate_names);
+        if let Some(max_chars) = config.truncate_track_names {
+            el.truncate_names(max_chars, config.truncate_ellipsis);
+        }
+        if let Some(start) = config.start_time {
+            el.drop_points_before(start);
+        }
+        if let Some(n) = config.sample_first {
+            el.keep_first_points(n);
+        }
+        if config.merge_segments {
+            el.merge_segments();
+        }
+        if config.reverse_points {
+            el.reverse_points();
+        }
+    }
+    if el.name() == b"trk" && !in_time_range(&el, config) {
+        info!("leaving out track {:?}, which is outside the time range",
+            el.track_name().unwrap_or_default());
+        return vec![];
+    }
+    if config.strava_to_garmin && el.name() == b"trk" {
+        garmin::strava_to_garmin(&mut el);
+    }
+    if let (Some(cmt), b"trk") = (&config.track_comment, el.name()) {
+        let new = Element::with_text("cmt", cmt);
+        if el.child(b"cmt").is_some() {
+            el.replace_children(b"cmt", vec![new]);
+        } else {
+            el.insert_child(new, schema::TRK);
+        }
+    }
+    if let Some(ref area) = config.area {
+        if !area::filter_element(&mut el, area) {
+            return vec![];
+        }
+    }
+    if config.interpolate_elevation && el.name() == b"trk" {
+        elevation::interpolate_elevation(&mut el);
+    }
+    if let (Some(max_change), b"trk") = (config.max_elevation_change, el.name()) {
+        despike::remove_spikes(&mut el, max_change);
+    }
+    if let (Some(kalman), b"trk") = (config.smooth_kalman, el.name()) {
+        kalman::smooth_track(&mut el, kalman);
+    }
+    if let (Some(max_gap), b"trk") = (config.max_gap_interpolate, el.name()) {
+        gaps::fill_segment_gaps(&mut el, max_gap);
+    }
+    if let (Some(trim), b"trk") = (config.trim_stationary, el.name()) {
+        stationary::trim_track(&mut el, trim);
+    }
+    if let (Some(algorithm), b"trk") = (config.simplify, el.name()) {
+        simplify::simplify_track(&mut el, algorithm);
+    }
+    if let (Some(resample), b"trk") = (config.resample, el.name()) {
+        resample::resample_track(&mut el, resample);
+    }
+    if let (Some(min_points), b"trk") = (config.min_points_per_segment, el.name()) {
+        let dropped = el.drop_sparse_segments(min_points);
+        if dropped > 0 {
+            info!("left out {} segments with fewer than {} points from track {:?}", dropped,
+                min_points, el.track_name().unwrap_or_default());
+        }
+    }
+    if el.name() == b"trk" {
+        annotate::annotate_track(&mut el, config);
+    }
+    match config.point_conversion {
+        Some(conversion) => convert::convert_points(el, conversion),
+        None => vec![el],
+    }
+}
+
+/// Whether a track has points within `since` and `until`, or no times at all.
+fn in_time_range(trk: &Element, config: &JoinConfig) -> bool {
+    if config.since.is_none() && config.until.is_none() {
+        return true;
+    }
+    let times = trk.track_points()
+        .filter_map(|pt| point::TrackPoint::from_element(pt).and_then(|p| p.time))
+        .collect::<Vec<_>>();
+    times.is_empty()
+        || (config.since.is_none_or(|since| times.iter().any(|&t| t >= since))
+            && config.until.is_none_or(|until| times.iter().any(|&t| t <= until)))
+}
+
+/// A track being written out as it's read, when nothing needs to look at the whole of it at once.
+/// Only its small parts are built up into trees: the points, and whatever else is in the track
+/// besides its segments.
+struct StreamedTrack {
+    source: usize,
+    /// For the stats, or None if the track is being left out.
+    recorder: Option<TrackRecorder>,
+    tree: TreeBuilder,
+    /// The track's first name, and how many names it has.
+    name: Option<String>,
+    names: usize,
+    out_of_range: Vec<String>,
+}
+
+impl StreamedTrack {
+    /// Start a track with its start tag, written out unless tracks are being left out.
+    fn start<W: Write>(start: Event<'static>, source: usize, out: &mut Output<W>,
+        config: &JoinConfig) -> quick_xml::Result<Self>
+    {
+        let recorder = match config.no_tracks {
+            true => None,
+            false => Some(out.start_track(start, source)?),
+        };
+        Ok(StreamedTrack {
+            source,
+            recorder,
+            tree: TreeBuilder::default(),
+            name: None,
+            names: 0,
+            out_of_range: vec![],
+        })
+    }
+
+    /// Handle an event inside the track, given the path to it. Returns true once the track's end
+    /// tag has been written.
+    fn event<W: Write>(&mut self, evt: Event<'static>, path: &[Vec<u8>], out: &mut Output<W>,
+        config: &JoinConfig) -> anyhow::Result<bool>
+    {
+        // How many elements the event is inside of: 1 for the track's end tag, 2 for what's
+        // directly inside the track, and 3 for what's directly inside its segments.
+        let depth = match evt {
+            Event::Start(_) | Event::End(_) => path.len() - 1,
+            _ => path.len(),
+        };
+        let building = self.tree.building();
+        let el = match evt {
+            Event::Start(ref seg) | Event::Empty(ref seg)
+                if !building && depth == 2 && seg.name() == b"trkseg" =>
+            {
+                if let Some(ref mut recorder) = self.recorder {
+                    recorder.segment(&mut out.stats);
+                }
+                self.write(evt, out)?;
+                return Ok(false);
+            }
+            Event::Start(_) | Event::Empty(_) => self.tree.push(evt),
+            _ if building => self.tree.push(evt),
+            Event::End(_) if depth == 1 => {
+                self.write(evt, out)?;
+                self.finish(config)?;
+                return Ok(true);
+            }
+            _ => {
+                // The end of a segment, or text or comments between elements.
+                self.write(evt, out)?;
+                return Ok(false);
+            }
+        };
+        let (el, recorder) = match (el, self.recorder.as_mut()) {
+            (Some(el), Some(recorder)) => (el, recorder),
+            _ => return Ok(false),
+        };
+        match (depth, el.name()) {
+            (2, b"name") => {
+                self.names += 1;
+                if self.name.is_none() {
+                    self.name = Some(el.text().trim().to_owned());
+                }
+            }
+            (3, b"trkpt") => recorder.point(&mut out.stats, &el),
+            _ => (),
+        }
+        self.out_of_range.extend(fix::out_of_range_points(&el));
+        el.write(&mut out.writer)?;
+        Ok(false)
+    }
+
+    fn write<W: Write>(&self, evt: Event<'static>, out: &mut Output<W>) -> quick_xml::Result<()> {
+        if self.recorder.is_some() {
+            out.writer.write_event(evt)?;
+        }
+        Ok(())
+    }
+
+    /// Warn about what would have been warned about if the track had been read as a whole, or
+    /// fail if the config says to.
+    fn finish(&self, config: &JoinConfig) -> anyhow::Result<()> {
+        if self.recorder.is_none() {
+            return Ok(());
+        }
+        if self.names > 1 {
+            strategy::warn_duplicate_names(self.name.as_deref().unwrap_or_default(), self.names,
+                None);
+        }
+        report_out_of_range(&self.out_of_range, &fix::describe_track(self.name.clone()),
+            self.source, config)
+    }
+}
+
+/// Writes the output, holding back whitespace between top-level elements until it's known whether
+/// an element following it will be written.
+struct Output<W: Write> {
+    writer: Writer<W>,
+    pending_space: Option<Event<'static>>,
+    /// Whitespace that preceded the first file's first track, for indenting buffered tracks.
+    track_space: Option<Event<'static>>,
+    /// The names of the sources, for commenting where each track came from.
+    source_names: Option<Vec<String>>,
+    /// Whether to add a summary to each track's extensions.
+    track_summaries: bool,
+    stats: JoinStats,
+}
+
+impl<W: Write> Output<W> {
+    fn new(writer: Writer<W>, source_names: Option<Vec<String>>, track_summaries: bool) -> Self {
+        Output {
+            writer,
+            pending_space: None,
+            track_space: None,
+            source_names,
+            track_summaries,
+            stats: JoinStats::default(),
+        }
+    }
+
+    /// Write an event that isn't part of a top-level element.
+    fn event(&mut self, evt: Event<'static>) -> quick_xml::Result<()> {
+        match evt {
+            Event::Text(ref t) if t.iter().all(u8::is_ascii_whitespace) => {
+                if let Some(space) = self.pending_space.replace(evt) {
+                    self.writer.write_event(space)?;
+                }
+                Ok(())
+            }
+            _ => {
+                if let Some(space) = self.pending_space.take() {
+                    self.writer.write_event(space)?;
+                }
+                self.writer.write_event(evt)
+            }
+        }
+    }
+
+    /// Write some top-level elements from the given source, each preceded by the pending
+    /// whitespace.
+    fn elements(&mut self, els: Vec<Element>, source: usize) -> quick_xml::Result<()> {
+        let space = self.pending_space.take();
+        for el in els {
+            self.write_element(el, source, space.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Write some top-level elements where the next one will go, leaving the pending whitespace
+    /// in place for it.
+    fn insert_elements(&mut self, els: Vec<Element>) -> quick_xml::Result<()> {
+        let space = self.pending_space.clone();
+        self.elements(els, 0)?;
+        self.pending_space = space;
+        Ok(())
+    }
+
+    /// Leave out a top-level element, along with the pending whitespace before it.
+    fn skip_element(&mut self) {
+        self.pending_space = None;
+    }
+
+    /// Hold on to the pending whitespace, if this is the first track being buffered.
+    fn hold_track_space(&mut self) {
+        let space = self.pending_space.take();
+        if self.track_space.is_none() {
+            self.track_space = space;
+        }
+    }
+
+    /// Write buffered tracks, each preceded by the whitespace that preceded the first track.
+    fn tracks(&mut self, tracks: Vec<SourceTrack>) -> quick_xml::Result<()> {
+        if tracks.is_empty() {
+            return Ok(());
+        }
+        let space = match self.track_space {
+            Some(ref space) => Some(space.clone()),
+            None => self.pending_space.take(),
+        };
+        for (source, trk) in tracks {
+            self.write_element(trk, source, space.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Write an element, preceded by some whitespace, and by a comment naming its source if it's
+    /// a track and those are wanted.
+    fn write_element(&mut self, mut el: Element, source: usize, space: Option<&Event<'static>>)
+        -> quick_xml::Result<()>
+    {
+        if el.name() == b"trk" {
+            if self.track_summaries {
+                annotate::add_summary(&mut el);
+            }
+            self.stats.record_track(&el, source);
+            self.source_comment(source, space)?;
+        }
+        if let Some(space) = space {
+            self.writer.write_event(space)?;
+        }
+        el.write(&mut self.writer)
+    }
+
+    /// Start writing a track that's written out as it's read, preceded by the pending whitespace,
+    /// and by a comment naming its source if those are wanted.
+    fn start_track(&mut self, start: Event<'static>, source: usize)
+        -> quick_xml::Result<TrackRecorder>
+    {
+        let space = self.pending_space.take();
+        let recorder = TrackRecorder::start(&mut self.stats, source);
+        self.source_comment(source, space.as_ref())?;
+        if let Some(space) = space {
+            self.writer.write_event(space)?;
+        }
+        self.writer.write_event(start)?;
+        Ok(recorder)
+    }
+
+    /// Write a comment naming the source of the track just recorded, if those are wanted,
+    /// preceded by some whitespace.
+    fn source_comment(&mut self, source: usize, space: Option<&Event<'static>>)
+        -> quick_xml::Result<()>
+    {
+        if let Some(ref names) = self.source_names {
+            if let Some(space) = space {
+                self.writer.write_event(space)?;
+            }
+            let comment = format!(" Source: {} track {} ", names[source],
+                self.stats.files[source].track_count);
+            self.writer.write_event(Event::Comment(BytesText::from_escaped(
+                comment.into_bytes())))?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> quick_xml::Result<()> {
+        if let Some(space) = self.pending_space.take() {
+            self.writer.write_event(space)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha256::Sha256;
+    use tree::Document;
+    use pretty_assertions::assert_eq;
+    use std::cell::Cell;
+    use std::io::{self, Cursor, Read};
+    use std::rc::Rc;
+
+    #[test]
+    fn starts_with() {
+        let path = vec![b"gpx".to_vec(), b"trk".to_vec()];
+        assert!(path.starts_with(&[b"gpx"]));
+        assert!(path.starts_with(&[b"gpx", b"trk"]));
+        assert!(path.starts_with(&[b"gpx".to_vec()][..]));
+        assert!(path.starts_with(&path[..]));
+        assert!(!path.starts_with(&[&b"gpx"[..], b"trk", b"trkseg"]));
+        assert!(!path.starts_with(&[b"trk".to_vec()][..]));
+    }
+
+    #[test]
+    fn starts_with_edge_cases() {
+        let empty: Vec<Vec<u8>> = vec![];
+        assert!(!empty.starts_with(&[b"gpx"]));
+        assert!(empty.starts_with(&[] as &[&[u8]]));
+
+        let path = vec![b"gpx".to_vec(), b"trk".to_vec(), b"trkseg".to_vec(), b"trkpt".to_vec()];
+        assert!(path.starts_with(&[] as &[&[u8]]));
+        assert!(path.starts_with(&[&b"gpx"[..], b"trk", b"trkseg", b"trkpt"]));
+        assert!(path.starts_with(&[&b"gpx"[..], b"trk", b"trkseg"]));
+        assert!(!path.starts_with(&[&b"gpx"[..], b"trk", b"trkseg", b"trkpt", b"ele"]));
+        // One element differing partway through, or only in length or case.
+        assert!(!path.starts_with(&[&b"gpx"[..], b"rte", b"trkseg"]));
+        assert!(!path.starts_with(&[&b"gpx"[..], b"trks"]));
+        assert!(!path.starts_with(&[&b"gpx"[..], b"tr"]));
+        assert!(!path.starts_with(&[&b"gpx"[..], b"TRK"]));
+    }
+
+    #[test]
+    fn first_file_end_after_buffering() {
+        // Plenty of events, some of them long, before the first file's </gpx>, and more after it
+        // that still need reading out of the stashed reader.
+        let points = (0 .. 200)
+            .map(|i| format!("<trkpt lat=\"47.{:04}\" lon=\"-121\"><desc>{}</desc></trkpt>", i,
+                "x".repeat(i)))
+            .collect::<String>();
+        let a = format!("<gpx><trk><trkseg>{}</trkseg></trk><wpt lat=\"1\" lon=\"2\"/></gpx>\
+            <!-- after -->", points);
+        let b = "<gpx><trk><name>b</name></trk></gpx>";
+        let out = join_str(&[&a, b], &JoinConfig::default()).unwrap();
+        assert_eq!(out, format!("<gpx><trk><trkseg>{}</trkseg></trk><wpt lat=\"1\" lon=\"2\"/>\
+            <trk><name>b</name></trk></gpx><!-- after -->", points));
+    }
+
+    #[test]
+    fn trailing_content() {
+        // Not valid XML, but seen in the wild.
+        let a = "<gpx><trk/></gpx>\n<!-- saved by x -->\n<?pi?>\ngarbage & <extra>more</extra>\n";
+        let b = "<gpx><trk><name>b</name></trk></gpx>trailing";
+        assert_eq!(join_str(&[a, b], &JoinConfig::default()).unwrap(),
+            "<gpx><trk/><trk><name>b</name></trk></gpx>\n<!-- saved by x -->\n<?pi?>\n\
+            garbage & <extra>more</extra>\n");
+    }
+
+    #[test]
+    fn trailing_whitespace() {
+        // Whatever follows the first file's </gpx> stays after the output's, where it's still
+        // well-formed, and doesn't end up between the tracks.
+        let a = "<?xml version=\"1.0\"?>\n<gpx>\n  <trk/>\n</gpx>\n\n  <!-- end -->\n \n";
+        let b = "<gpx>\n  <trk><name>b</name></trk>\n</gpx>\n\n";
+        let tail = "</gpx>\n\n  <!-- end -->\n \n";
+        for (strategy, tracks) in [
+            (JoinStrategy::Append, "<trk/>\n<trk><name>b</name></trk>"),
+            (JoinStrategy::Interleave, "<trk/>\n  <trk><name>b</name></trk>\n"),
+        ] {
+            let config = JoinConfig { strategy, ..Default::default() };
+            let out = join_str(&[a, b], &config).unwrap();
+            assert_eq!(out, format!("<?xml version=\"1.0\"?>\n<gpx>\n  {}{}", tracks, tail));
+            check_well_formed(out.as_bytes()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test() {
+        let a = Cursor::new(r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1" creator="gpxjoin" xmlns="http://www.topografix.com/GPX/1/1">
+    <metadata>
+        <name><![CDATA[this is the first file]]></name>
+        <desc>description here</desc>
+        <author><name>whatever</name></author>
+    </metadata>
+    <trk>
+        <name>first track</name>
+        <trkseg>
+            <trkpt lat="47.543448" lon="-121.096462">
+                <ele>1008.620662</ele>
+                <time>2021-08-27T18:59:24.070Z</time>
+            </trkpt>
+        </trkseg>
+    </trk>
+</gpx>
+"#.as_bytes());
+        let b = Cursor::new(r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1" creator="gpxjoin" xmlns="http://www.topografix.com/GPX/1/1">
+    <metadata>
+        <name><![CDATA[this is the second file]]></name>
+        <desc>description here</desc>
+        <author><name>whatever</name></author>
+    </metadata>
+    <trk>
+        <name>second track</name>
+        <trkseg>
+            <trkpt lat="47.552213" lon="-121.133853">
+                <ele>1750.672203</ele>
+                <time>2021-08-27T22:04:05.536Z</time>
+            </trkpt>
+        </trkseg>
+    </trk>
+</gpx>
+"#.as_bytes());
+        let mut out = Cursor::new(vec![]);
+        join_gpx(&mut [a, b], &mut out, &JoinConfig::default()).unwrap();
+
+        // Indentation at the second track is weird because XML is a bad format; there's no
+        // reasonable way around it.
+        assert_eq!(String::from_utf8(out.into_inner()).unwrap(),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1" creator="gpxjoin" xmlns="http://www.topografix.com/GPX/1/1">
+    <metadata>
+        <name><![CDATA[this is the first file]]></name>
+        <desc>description here</desc>
+        <author><name>whatever</name></author>
+    </metadata>
+    <trk>
+        <name>first track</name>
+        <trkseg>
+            <trkpt lat="47.543448" lon="-121.096462">
+                <ele>1008.620662</ele>
+                <time>2021-08-27T18:59:24.070Z</time>
+            </trkpt>
+        </trkseg>
+    </trk>
+<trk>
+        <name>second track</name>
+        <trkseg>
+            <trkpt lat="47.552213" lon="-121.133853">
+                <ele>1750.672203</ele>
+                <time>2021-08-27T22:04:05.536Z</time>
+            </trkpt>
+        </trkseg>
+    </trk></gpx>
+"#);
+    }
+
+    #[test]
+    fn single_file_round_trip() {
+        // A single file comes out byte for byte the same: comments, processing instructions,
+        // CDATA (even with markup characters in it), entities, attribute quoting, whitespace, and
+        // self-closing elements are all kept.
+        let gpx = r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+<!-- exported by something -->
+<?xml-stylesheet href="gpx.xsl"?>
+<gpx version='1.1' creator="a &amp; b" xmlns="http://www.topografix.com/GPX/1/1">
+  <metadata><name><![CDATA[<first>]]></name><desc>tom &amp; jerry&#39;s</desc></metadata>
+  <wpt lat="47.5" lon="-121.5"/>
+  <!-- between elements -->
+  <trk>
+    <name>track</name>
+    <trkseg>
+      <trkpt lat="47.0"   lon="-121.0"><ele>100</ele></trkpt>
+      <trkpt lat="47.1" lon="-121.1"></trkpt>
+    </trkseg>
+    <trkseg/>
+  </trk>
+  <trk/>
+</gpx>
+<!-- trailing -->
+"#;
+        assert_eq!(join_str(&[gpx], &JoinConfig::default()).unwrap(), gpx);
+    }
+
+    #[test]
+    fn first_file_without_tracks() {
+        let a = r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1">
+    <metadata><name>first</name></metadata>
+    <wpt lat="47.5" lon="-121.1"/>
+</gpx>
+"#;
+        let b = r#"<gpx version="1.1">
+    <metadata><name>second</name></metadata>
+    <trk><name>one</name></trk>
+    <trk><name>two</name></trk>
+</gpx>
+"#;
+        let expected = r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1">
+    <metadata><name>first</name></metadata>
+    <wpt lat="47.5" lon="-121.1"/>
+<trk><name>one</name></trk><trk><name>two</name></trk></gpx>
+"#;
+        let joined = join_str(&[a, b], &JoinConfig::default()).unwrap();
+        assert_eq!(joined, expected);
+        assert_eq!(validate_gpx(joined.as_bytes()).count(), 0);
+
+        let config = JoinConfig { strategy: JoinStrategy::Interleave, ..Default::default() };
+        let joined = join_str(&[a, b], &config).unwrap();
+        assert_eq!(joined, expected.replace("</trk><trk>", "</trk>\n<trk>"));
+        assert_eq!(validate_gpx(joined.as_bytes()).count(), 0);
+    }
+
+    #[test]
+    fn comments() {
+        let config = JoinConfig {
+            comments: vec!["joined by gpxjoin".to_owned(), " a & b ".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(join_str(&["<?xml version=\"1.0\"?>\n<gpx></gpx>\n"], &config).unwrap(),
+            "<?xml version=\"1.0\"?>\n<!--joined by gpxjoin-->\n<!-- a & b -->\n<gpx></gpx>\n");
+
+        let config = JoinConfig { comments: vec!["a -- b".to_owned()], ..Default::default() };
+        assert!(join_str(&["<gpx></gpx>"], &config).is_err());
+    }
+
+    #[test]
+    fn track_source_comments() {
+        let names = vec!["a.gpx".to_owned(), "b.gpx".to_owned()];
+        let config = JoinConfig { track_source_comments: Some(names), ..Default::default() };
+        let a = "<gpx>\n  <trk><name>1</name></trk>\n  <trk/>\n</gpx>";
+        let b = "<gpx><trk/></gpx>";
+        assert_eq!(join_str(&[a, b], &config).unwrap(), "<gpx>\n  \
+            <!-- Source: a.gpx track 1 -->\n  <trk><name>1</name></trk>\n  \
+            <!-- Source: a.gpx track 2 -->\n  <trk/>\n\
+            <!-- Source: b.gpx track 1 -->\n<trk/></gpx>");
+        let config = JoinConfig { strategy: JoinStrategy::Interleave, ..config };
+        assert_eq!(join_str(&[a, b], &config).unwrap(), "<gpx>\n  \
+            <!-- Source: a.gpx track 1 -->\n  <trk><name>1</name></trk>\n  \
+            <!-- Source: a.gpx track 2 -->\n  <trk/>\n  \
+            <!-- Source: b.gpx track 1 -->\n  <trk/>\n</gpx>");
+
+        assert!(join_str(&[a], &config).is_err());
+        let names = vec!["a--b.gpx".to_owned()];
+        let config = JoinConfig { track_source_comments: Some(names), ..Default::default() };
+        assert!(join_str(&[a], &config).is_err());
+    }
+
+    /// A GPX document with one track of many points, made up one point at a time as it's read,
+    /// which notes how much output had been written by the time each point was made.
+    struct TrackStream {
+        points: usize,
+        next: usize,
+        chunk: Vec<u8>,
+        pos: usize,
+        written: Rc<Cell<usize>>,
+        log: Vec<usize>,
+    }
+
+    impl Read for TrackStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let data = self.fill_buf()?;
+            let n = data.len().min(buf.len());
+            buf[.. n].copy_from_slice(&data[.. n]);
+            self.consume(n);
+            Ok(n)
+        }
+    }
+
+    impl BufRead for TrackStream {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            if self.pos == self.chunk.len() {
+                self.pos = 0;
+                self.chunk = match self.next {
+                    0 => b"<gpx><trk><trkseg>".to_vec(),
+                    i if i <= self.points => {
+                        self.log.push(self.written.get());
+                        point(i).into_bytes()
+                    }
+                    i if i == self.points + 1 => b"</trkseg></trk></gpx>".to_vec(),
+                    _ => vec![],
+                };
+                self.next += 1;
+            }
+            Ok(&self.chunk[self.pos ..])
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.pos += amt;
+        }
+    }
+
+    fn point(i: usize) -> String {
+        format!("<trkpt lat=\"{}\" lon=\"0\"></trkpt>", i % 90)
+    }
+
+    struct CountingWriter(Rc<Cell<usize>>);
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.set(self.0.get() + buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn streaming() {
+        let written = Rc::new(Cell::new(0));
+        let stream = |points| TrackStream {
+            points,
+            next: 0,
+            chunk: vec![],
+            pos: 0,
+            written: written.clone(),
+            log: vec![],
+        };
+        let mut sources = [stream(2), stream(1000)];
+        join_gpx(&mut sources, CountingWriter(written.clone()), &JoinConfig::default()).unwrap();
+        // Each point is written before the next one is even made, long before the end of the
+        // track it's in.
+        for log in sources.iter().map(|s| &s.log) {
+            assert!(log.windows(2).all(|w| w[0] < w[1]), "{:?}", log);
+        }
+        assert_eq!(sources[1].log.len(), 1000);
+        assert_eq!(written.get(), "<gpx><trk><trkseg></trkseg></trk></gpx>".len() * 2
+            - "<gpx></gpx>".len()
+            + (1 ..= 2).chain(1 ..= 1000).map(|i| point(i).len()).sum::<usize>());
+    }
+
+    #[test]
+    fn stats() {
+        let a = r#"<gpx><trk><trkseg>
+            <trkpt lat="47.0" lon="-121.0"><time>2021-08-27T10:00:00Z</time></trkpt>
+            <trkpt lat="47.1" lon="-121.0"/>
+            <trkpt lat="47.2" lon="-121.0"><time>2021-08-27T10:20:00Z</time></trkpt>
+        </trkseg><trkseg/></trk></gpx>"#;
+        let b = r#"<gpx><trk></trk><trk><trkseg><trkpt lat="1" lon="1"/></trkseg></trk></gpx>"#;
+        let mut sources = [a.as_bytes(), b.as_bytes()];
+        let stats = join_gpx_with_stats(&mut sources, vec![], &JoinConfig::default()).unwrap();
+        assert_eq!(stats.tracks, 3);
+        let segments = stats.segments.iter()
+            .map(|s| (s.track_index, s.segment_index_in_track, s.point_count, s.start_time,
+                s.end_time, s.distance_m.round()))
+            .collect::<Vec<_>>();
+        assert_eq!(stats.files.iter().map(|f| (f.track_count, f.point_count)).collect::<Vec<_>>(),
+            vec![(1, 3), (2, 1)]);
+        assert_eq!(stats.files[1].bounds,
+            Some(BoundingBox { min_lat: 1., min_lon: 1., max_lat: 1., max_lon: 1. }));
+        for (file, source) in stats.files.iter().zip([a, b]) {
+            let mut hash = Sha256::default();
+            hash.update(source.as_bytes());
+            assert_eq!(file.sha256, hash.finish());
+        }
+        assert_eq!(segments, vec![
+            (0, 0, 3, Timestamp::parse("2021-08-27T10:00:00Z"),
+                Timestamp::parse("2021-08-27T10:20:00Z"), 22239.),
+            (0, 1, 0, None, None, 0.),
+            (2, 0, 1, None, None, 0.),
+        ]);
+    }
+
+    fn join_str(sources: &[&str], config: &JoinConfig) -> anyhow::Result<String> {
+        let mut sources = sources.iter().map(|s| s.as_bytes()).collect::<Vec<_>>();
+        let mut out = vec![];
+        join_gpx(&mut sources, &mut out, config)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn fix() {
+        let a = "\u{FEFF}<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<gpx version=\"1.1\">
+    <wpt lat=\"-121.1\" lon=\"47.5\"><time>2021-08-27T18:00:00Z\r\n</time></wpt>
+    <trk>
+        <trkseg>
+            <trkpt lat=\"47.543448\" lon=\"-121.096462\"><time>2021-08-27T18:59:24Z</time></trkpt>
+            <trkpt lat=\"47.543448\" lon=\"-121.096462\"><time>2021-08-27T18:59:24Z</time></trkpt>
+            <trkpt lat=\"-121.1\" lon=\"47.6\"><time>2021-08-27T19:00:00Z</time></trkpt>
+        </trkseg>
+    </trk>
+</gpx>
+";
+        let config = JoinConfig { fix: true, ..Default::default() };
+        assert_eq!(join_str(&[a], &config).unwrap(), r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1">
+    <wpt lat="47.5" lon="-121.1"><time>2021-08-27T18:00:00Z</time></wpt>
+    <trk>
+        <trkseg>
+            <trkpt lat="47.543448" lon="-121.096462"><time>2021-08-27T18:59:24Z</time></trkpt>
+            <trkpt lat="47.6" lon="-121.1"><time>2021-08-27T19:00:00Z</time></trkpt>
+        </trkseg>
+    </trk>
+</gpx>
+"#);
+
+        // Without the flag, everything is passed through as-is.
+        assert_eq!(join_str(&[a], &JoinConfig::default()).unwrap(), a);
+    }
+
+    #[test]
+    fn missing_root() {
+        let err = join_str(&["<?xml version=\"1.0\"?>\n"], &JoinConfig::default()).unwrap_err();
+        assert_eq!(err.to_string(), "source 1 is missing the <gpx> root element");
+        let err = join_str(&["<gpx></gpx>", "<kml><trk/></kml>"], &JoinConfig::default())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "source 2 is missing the <gpx> root element");
+        // A later file can be empty otherwise.
+        assert_eq!(join_str(&["<gpx><trk/></gpx>", "<gpx/>"], &JoinConfig::default()).unwrap(),
+            "<gpx><trk/></gpx>");
+        // So can the first, which the other files' tracks are added to.
+        assert_eq!(join_str(&["<gpx version=\"1.1\"/>", "<gpx><trk/></gpx>"],
+            &JoinConfig::default()).unwrap(), "<gpx version=\"1.1\"><trk/></gpx>");
+        assert_eq!(join_str(&["<gpx/>"], &JoinConfig::default()).unwrap(), "<gpx></gpx>");
+        let err = join_str(&["<kml/>"], &JoinConfig::default()).unwrap_err();
+        assert_eq!(err.to_string(), "source 1 is missing the <gpx> root element");
+    }
+
+    #[test]
+    fn streamed_tracks() {
+        // Tracks written as they're read come out just like ones built up into trees first, which
+        // requiring times makes them be.
+        let a = "<gpx>\n  <trk>\n    <name>a</name>\n    <trkseg>\n      \
+            <trkpt lat=\"1\" lon=\"2\"><time>2021-08-27T10:00:00Z</time></trkpt>\n      \
+            <trkpt lat=\"1.5\" lon=\"2\"><time>2021-08-27T10:10:00Z</time></trkpt>\n    \
+            </trkseg>\n    <trkseg/>\n    <extensions><x/></extensions>\n  </trk>\n</gpx>\n";
+        let b = "<gpx><trk><!-- b --><trkseg><trkpt lat=\"2\" lon=\"3\">\
+            <time>2021-08-28T10:00:00Z</time></trkpt></trkseg></trk></gpx>";
+        let join = |config: &JoinConfig| {
+            let mut sources = [a.as_bytes(), b.as_bytes()];
+            let mut out = vec![];
+            let stats = join_gpx_with_stats(&mut sources, &mut out, config).unwrap();
+            (String::from_utf8(out).unwrap(), stats)
+        };
+        let config = JoinConfig {
+            track_source_comments: Some(vec!["a".into(), "b".into()]),
+            ..Default::default()
+        };
+        assert!(streams_tracks(&config));
+        let built = JoinConfig { require_time: true, ..config.clone() };
+        assert!(!streams_tracks(&built));
+        assert_eq!(join(&config), join(&built));
+
+        let err = join_str(&[a, "<gpx><trk><trkseg><trkpt lat=\"1\" lon=\"2\"/>"],
+            &config).unwrap_err();
+        assert_eq!(err.to_string(), "source 2 ends in the middle of a track");
+    }
+
+    #[test]
+    fn no_metadata() {
+        let a = r#"<gpx>
+    <metadata><name>a</name><time>2021-08-27T10:00:00Z</time></metadata>
+    <wpt lat="1" lon="1"/>
+</gpx>"#;
+        let b = "<gpx><metadata><name>b</name></metadata><trk></trk></gpx>";
+        let config = JoinConfig { no_metadata: true, ..Default::default() };
+        assert_eq!(join_str(&[a, b], &config).unwrap(),
+            "<gpx>\n    <wpt lat=\"1\" lon=\"1\"/>\n<trk></trk></gpx>");
+    }
+
+    #[test]
+    fn no_tracks() {
+        let a = "<gpx>\n  <metadata/>\n  <trk/>\n  <wpt lat=\"1\" lon=\"1\"/>\n  <trk/>\n</gpx>";
+        let b = "<gpx><wpt lat=\"2\" lon=\"2\"/><trk><name>b</name></trk></gpx>";
+        let config = JoinConfig { no_tracks: true, ..Default::default() };
+        assert_eq!(join_str(&[a, b], &config).unwrap(),
+            "<gpx>\n  <metadata/>\n  <wpt lat=\"1\" lon=\"1\"/>\n</gpx>");
+        let config = JoinConfig { strategy: JoinStrategy::Interleave, ..config };
+        assert_eq!(join_str(&[a, b], &config).unwrap(),
+            "<gpx>\n  <metadata/>\n  <wpt lat=\"1\" lon=\"1\"/>\n</gpx>");
+    }
+
+    #[test]
+    fn no_waypoints() {
+        let a = "<gpx>\n  <wpt lat=\"1\" lon=\"1\"><name>a</name></wpt>\n  <trk/>\n  \
+            <wpt lat=\"2\" lon=\"2\"/>\n</gpx>";
+        let config = JoinConfig { no_waypoints: true, ..Default::default() };
+        assert_eq!(join_str(&[a], &config).unwrap(), "<gpx>\n  <trk/>\n</gpx>");
+        let config = JoinConfig { no_tracks: true, ..config };
+        assert_eq!(join_str(&[a], &config).unwrap(), "<gpx>\n</gpx>");
+    }
+
+    #[test]
+    fn event_filters() {
+        /// Leaves out waypoints at (0, 0), and shouts the names of tracks.
+        struct Filter;
+        impl GpxEventFilter for Filter {
+            fn filter(&mut self, event: &Event, path: &[Vec<u8>]) -> FilterAction {
+                match event {
+                    Event::Start(s) | Event::Empty(s) if s.name() == b"wpt"
+                        && s.attributes().flatten().all(|a| &*a.value == b"0") =>
+                    {
+                        FilterAction::Drop
+                    }
+                    Event::Text(t) if path.ends_with(&[b"trk".to_vec(), b"name".to_vec()]) => {
+                        FilterAction::Replace(Event::Text(BytesText::from_escaped(
+                            t.to_ascii_uppercase())))
+                    }
+                    _ => FilterAction::Keep,
+                }
+            }
+        }
+        let a = "<gpx>\n  <wpt lat=\"0\" lon=\"0\"><name>x</name></wpt>\n  \
+            <wpt lat=\"1\" lon=\"0\"/>\n  <wpt lat=\"0\" lon=\"0\"/>\n  \
+            <trk><name>a</name></trk>\n</gpx>";
+        let b = "<gpx><trk><name>b</name></trk></gpx>";
+        let filters: Vec<Box<dyn GpxEventFilter>> = vec![Box::new(Filter)];
+        let config = JoinConfig { event_filters: filters.into(), ..Default::default() };
+        assert_eq!(join_str(&[a, b], &config).unwrap(), "<gpx>\n  <wpt lat=\"1\" lon=\"0\"/>\n  \
+            <trk><name>A</name></trk>\n<trk><name>B</name></trk></gpx>");
+    }
+
+    #[test]
+    fn no_routes() {
+        let a = "<gpx>\n  <wpt lat=\"1\" lon=\"1\"/>\n  <rte><rtept lat=\"1\" lon=\"1\"/></rte>\n  \
+            <trk/>\n</gpx>";
+        let config = JoinConfig { no_routes: true, ..Default::default() };
+        assert_eq!(join_str(&[a], &config).unwrap(),
+            "<gpx>\n  <wpt lat=\"1\" lon=\"1\"/>\n  <trk/>\n</gpx>");
+        let config = JoinConfig { no_waypoints: true, ..config };
+        assert_eq!(join_str(&[a], &config).unwrap(), "<gpx>\n  <trk/>\n</gpx>");
+    }
+
+    #[test]
+    fn metadata() {
+        let replacement = "<gpx><metadata><name>b</name></metadata><trk/></gpx>";
+        let metadata = Metadata::read(replacement.as_bytes()).unwrap();
+        assert!(metadata.is_some());
+        let config = JoinConfig { metadata, ..Default::default() };
+
+        let a = "<gpx>\n    <metadata><name>a</name></metadata>\n    <trk></trk>\n</gpx>";
+        assert_eq!(join_str(&[a, replacement], &config).unwrap(),
+            "<gpx>\n    <metadata><name>b</name></metadata>\n    <trk></trk>\n<trk/></gpx>");
+
+        let a = "<gpx>\n    <wpt lat=\"1\" lon=\"1\"/>\n</gpx>";
+        assert_eq!(join_str(&[a], &config).unwrap(),
+            "<gpx>\n    <metadata><name>b</name></metadata>\n    <wpt lat=\"1\" lon=\"1\"/>\n</gpx>");
+
+        assert_eq!(join_str(&["<gpx>\n</gpx>"], &config).unwrap(),
+            "<gpx>\n<metadata><name>b</name></metadata>\n</gpx>");
+
+        let config = JoinConfig { no_metadata: true, ..config };
+        assert_eq!(join_str(&["<gpx>\n</gpx>"], &config).unwrap(), "<gpx>\n</gpx>");
+    }
+
+    #[test]
+    fn metadata_keywords() {
+        let keywords = "hiking, mountain, Pacific Northwest";
+        let config =
+            JoinConfig { metadata_keywords: Some(keywords.to_owned()), ..Default::default() };
+        let a = "<gpx><metadata><name>a</name><keywords>old</keywords><bounds/></metadata></gpx>";
+        assert_eq!(join_str(&[a], &config).unwrap(), "<gpx><metadata><name>a</name>\
+            <keywords>hiking, mountain, Pacific Northwest</keywords><bounds/></metadata></gpx>");
+        let a = "<gpx>\n  <metadata>\n    <name>a</name>\n  </metadata>\n  <trk/>\n</gpx>";
+        assert_eq!(join_str(&[a], &config).unwrap(), "<gpx>\n  <metadata>\n    <name>a</name>\n    \
+            <keywords>hiking, mountain, Pacific Northwest</keywords>\n  </metadata>\n  <trk/>\n\
+            </gpx>");
+        // Metadata is added for the keywords if there's none.
+        assert_eq!(join_str(&["<gpx>\n  <trk/>\n</gpx>"], &config).unwrap(), "<gpx>\n  \
+            <metadata><keywords>hiking, mountain, Pacific Northwest</keywords></metadata>\n  \
+            <trk/>\n</gpx>");
+    }
+
+    #[test]
+    fn metadata_copyright() {
+        let copyright = Some("2024,Alice".parse().unwrap());
+        let config = JoinConfig { metadata_copyright: copyright, ..Default::default() };
+        let a = r#"<gpx><metadata><copyright author="Bob"><year>2020</year></copyright>
+            </metadata><trk/></gpx>"#;
+        let b = "<gpx><metadata><name>b</name></metadata><trk/></gpx>";
+        let copyright = r#"<copyright author="Alice"><year>2024</year></copyright>"#;
+        assert_eq!(join_str(&[a, b], &config).unwrap(),
+            format!("<gpx><metadata>{}\n            </metadata><trk/><trk/></gpx>", copyright));
+        assert_eq!(join_str(&["<gpx><trk/></gpx>"], &config).unwrap(),
+            format!("<gpx><metadata>{}</metadata><trk/></gpx>", copyright));
+    }
+
+    #[test]
+    fn gpx_extensions() {
+        let ext = Extensions::read("<x:a>1</x:a>\n<x:b/>\n".as_bytes()).unwrap();
+        let config = JoinConfig { gpx_extensions: Some(ext), ..Default::default() };
+        let b = "<gpx><trk/><extensions><y/></extensions></gpx>";
+
+        let a = "<gpx>\n    <trk></trk>\n    <extensions><z/></extensions>\n</gpx>";
+        assert_eq!(join_str(&[a, b], &config).unwrap(),
+            "<gpx>\n    <trk></trk>\n    <extensions><z/><x:a>1</x:a><x:b/></extensions>\n\
+            <trk/></gpx>");
+
+        let a = "<gpx>\n    <trk></trk>\n</gpx>";
+        assert_eq!(join_str(&[a, b], &config).unwrap(),
+            "<gpx>\n    <trk></trk>\n<trk/><extensions><x:a>1</x:a>\n<x:b/></extensions></gpx>");
+        let config = JoinConfig { strategy: JoinStrategy::Interleave, ..config };
+        assert_eq!(join_str(&[a, b], &config).unwrap(),
+            "<gpx>\n    <trk></trk>\n    <trk/>\n<extensions><x:a>1</x:a>\n<x:b/></extensions>\n\
+            </gpx>");
+    }
+
+    #[test]
+    fn encoding() {
+        let config = JoinConfig { encoding: Some("UTF-16".to_owned()), ..Default::default() };
+        let a = "<?xml version='1.0' encoding='utf-8' standalone='yes'?>\n<gpx></gpx>";
+        assert_eq!(join_str(&[a], &config).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-16\" standalone=\"yes\"?>\n<gpx></gpx>");
+        assert_eq!(join_str(&["<gpx></gpx>"], &config).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-16\"?>\n<gpx></gpx>");
+
+        let config = JoinConfig { encoding: Some("utf 8".to_owned()), ..Default::default() };
+        assert!(join_str(&[a], &config).is_err());
+    }
+
+    #[test]
+    fn standalone() {
+        let config = JoinConfig { standalone: Some(true), ..Default::default() };
+        let a = "<?xml version='1.0' encoding='utf-8' standalone='no'?>\n<gpx></gpx>";
+        assert_eq!(join_str(&[a], &config).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>\n<gpx></gpx>");
+        assert_eq!(join_str(&["<gpx></gpx>"], &config).unwrap(),
+            "<?xml version=\"1.0\" standalone=\"yes\"?>\n<gpx></gpx>");
+        let config = JoinConfig { standalone: Some(false), ..Default::default() };
+        assert_eq!(join_str(&["<?xml version='1.0'?><gpx></gpx>"], &config).unwrap(),
+            "<?xml version=\"1.0\" standalone=\"no\"?><gpx></gpx>");
+        assert_eq!(join_str(&[a], &JoinConfig::default()).unwrap(), a);
+    }
+
+    #[test]
+    fn suppress_xml_declaration() {
+        let config = JoinConfig { suppress_xml_declaration: true, ..Default::default() };
+        let a = "<?xml version=\"1.0\"?>\n<!-- a -->\n<gpx><trk/></gpx>\n";
+        let b = "<?xml version=\"1.0\"?>\n<gpx><trk/></gpx>";
+        assert_eq!(join_str(&[a, b], &config).unwrap(), "<!-- a -->\n<gpx><trk/><trk/></gpx>\n");
+
+        let config = JoinConfig { encoding: Some("utf-8".to_owned()), ..config };
+        assert!(join_str(&[a], &config).is_err());
+    }
+
+    #[test]
+    fn schema_location() {
+        let config = JoinConfig { schema_location: true, ..Default::default() };
+        let a = r#"<gpx version="1.1"><trk></trk></gpx>"#;
+        let b = r#"<gpx version="1.0" xsi:schemaLocation="urn:x x.xsd"><trk/></gpx>"#;
+        assert_eq!(join_str(&[a, b], &config).unwrap(), concat!(
+            r#"<gpx version="1.1" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" "#,
+            r#"xsi:schemaLocation="http://www.topografix.com/GPX/1/1 "#,
+            r#"http://www.topografix.com/GPX/1/1/gpx.xsd"><trk></trk><trk/></gpx>"#));
+
+        let c = r#"<gpx xsi:schemaLocation="urn:x x.xsd"></gpx>"#;
+        assert_eq!(join_str(&[c], &config).unwrap(), concat!(
+            r#"<gpx xsi:schemaLocation="http://www.topografix.com/GPX/1/1 "#,
+            r#"http://www.topografix.com/GPX/1/1/gpx.xsd urn:x x.xsd" "#,
+            r#"xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"></gpx>"#));
+        let joined = join_str(&[c], &config).unwrap();
+        assert_eq!(join_str(&[&joined], &config).unwrap(), joined);
+    }
+
+    #[test]
+    fn gpx_version() {
+        let config = JoinConfig { gpx_version: Some("1.0".to_owned()), ..Default::default() };
+        let a = r#"<gpx version="1.1" creator="x"><trk/></gpx>"#;
+        let b = r#"<gpx version="1.1"><trk/></gpx>"#;
+        assert_eq!(join_str(&[a, b], &config).unwrap(),
+            r#"<gpx version="1.0" creator="x"><trk/><trk/></gpx>"#);
+        assert_eq!(join_str(&["<gpx><trk/></gpx>"], &config).unwrap(),
+            r#"<gpx version="1.0"><trk/></gpx>"#);
+    }
+
+    #[test]
+    fn normalize_creator() {
+        let config = JoinConfig { normalize_creator: true, ..Default::default() };
+        let a = r#"<gpx version="1.1" creator="Garmin_Connect v7.23.1"><trk/></gpx>"#;
+        let b = r#"<gpx creator="StravaGPX"><trk/></gpx>"#;
+        assert_eq!(join_str(&[a, b], &config).unwrap(),
+            r#"<gpx version="1.1" creator="Garmin Connect"><trk/><trk/></gpx>"#);
+        assert_eq!(join_str(&["<gpx><trk/></gpx>"], &config).unwrap(), "<gpx><trk/></gpx>");
+    }
+
+    #[test]
+    fn mixed_versions() {
+        let a = r#"<gpx version="1.0" xmlns="http://www.topografix.com/GPX/1/0">
+    <trk><name>a</name></trk>
+</gpx>"#;
+        let b = r#"<gpx version="1.1" xmlns="http://www.topografix.com/GPX/1/1">
+    <trk><name>b</name><extensions><x:y xmlns:x="urn:x"/></extensions></trk>
+</gpx>"#;
+        let joined = join_str(&[a, b], &JoinConfig::default()).unwrap();
+        let doc = Document::read(joined.as_bytes()).unwrap();
+        assert_eq!(doc.root.attr(b"xmlns").as_deref(), Some("http://www.topografix.com/GPX/1/0"));
+        let names = doc.root.elements().map(|trk| trk.track_name()).collect::<Vec<_>>();
+        assert_eq!(names, [Some("a".to_owned()), Some("b".to_owned())]);
+
+        let config = JoinConfig { check_gpx_version: true, ..Default::default() };
+        let err = join_str(&[a, b], &config).unwrap_err();
+        assert_eq!(err.to_string(), "source 2 has namespace http://www.topografix.com/GPX/1/1, \
+            but the first file has namespace http://www.topografix.com/GPX/1/0");
+        assert!(join_str(&[a, a], &config).is_ok());
+    }
+
+    #[test]
+    fn time_range() {
+        let track = |name: &str, times: &[&str]| {
+            let points = times.iter()
+                .map(|t| format!(r#"<trkpt lat="1" lon="1"><time>{}</time></trkpt>"#, t))
+                .collect::<String>();
+            format!("<trk><name>{}</name><trkseg>{}</trkseg></trk>", name, points)
+        };
+        let gpx = format!("<gpx>{}{}{}{}</gpx>",
+            track("before", &["2023-12-31T10:00:00Z", "2023-12-31T23:59:59Z"]),
+            track("spanning", &["2023-12-31T23:00:00Z", "2024-01-01T01:00:00Z"]),
+            track("untimed", &[]),
+            track("after", &["2024-01-02T00:00:00Z"]));
+        let names = |config: &JoinConfig| {
+            let joined = join_str(&[&gpx], config).unwrap();
+            Document::read(joined.as_bytes()).unwrap().root.elements()
+                .filter_map(|trk| trk.track_name())
+                .collect::<Vec<_>>()
+        };
+        let config = JoinConfig {
+            since: Timestamp::parse("2024-01-01T00:00:00Z"),
+            ..Default::default()
+        };
+        assert_eq!(names(&config), ["spanning", "untimed", "after"]);
+        let config = JoinConfig {
+            until: Timestamp::parse("2024-01-01T23:59:59.999Z"),
+            ..config
+        };
+        assert_eq!(names(&config), ["spanning", "untimed"]);
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let a = "<gpx>\n    <trk>\n        <name>a</name>\n        <name>b</name>\n    </trk>\n\
+            </gpx>";
+        assert_eq!(join_str(&[a], &JoinConfig::default()).unwrap(), a);
+        let config = JoinConfig {
+            duplicate_names: Some(DuplicateNames::FirstWins),
+            ..Default::default()
+        };
+        assert_eq!(join_str(&[a], &config).unwrap(),
+            "<gpx>\n    <trk>\n        <name>a</name>\n    </trk>\n</gpx>");
+        let config = JoinConfig {
+            duplicate_names: Some(DuplicateNames::LastWins),
+            ..Default::default()
+        };
+        assert_eq!(join_str(&[a], &config).unwrap(),
+            "<gpx>\n    <trk>\n        <name>b</name>\n    </trk>\n</gpx>");
+    }
+
+    #[test]
+    fn track_comment() {
+        let a = "<gpx><trk><name>a</name><trkseg/></trk></gpx>";
+        let b = "<gpx><trk><cmt>old</cmt><cmt>older</cmt></trk></gpx>";
+        let config = JoinConfig {
+            track_comment: Some("Merged from 2 files & more".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(join_str(&[a, b], &config).unwrap(), concat!(
+            "<gpx><trk><name>a</name><cmt>Merged from 2 files &amp; more</cmt><trkseg/></trk>",
+            "<trk><cmt>Merged from 2 files &amp; more</cmt></trk></gpx>"));
+    }
+
+    #[test]
+    fn osmand_extensions() {
+        // The way OsmAnd writes its tracks.
+        let osmand = |name: &str, color: &str| format!(r#"<?xml version='1.0' encoding='UTF-8'?>
+<gpx version="1.1" creator="OsmAnd~ 4.0.8" xmlns="http://www.topografix.com/GPX/1/1"
+    xmlns:osmand="https://osmand.net">
+  <trk>
+    <name>{}</name>
+    <trkseg>
+      <trkpt lat="47.543448" lon="-121.096462">
+        <ele>1008.6</ele>
+        <time>2021-08-27T18:59:24Z</time>
+        <hdop>3.8</hdop>
+        <extensions>
+          <speed>1.3</speed>
+          <heading>271</heading>
+        </extensions>
+      </trkpt>
+    </trkseg>
+    <extensions>
+      <osmand:color>{}</osmand:color>
+      <osmand:width>thick</osmand:width>
+      <osmand:activity>hiking</osmand:activity>
+    </extensions>
+  </trk>
+</gpx>"#, name, color);
+        let a = osmand("a", "#a71de1");
+        let b = osmand("b", "#10c0f0");
+        let out = join_str(&[&a, &b], &JoinConfig::default()).unwrap();
+        let doc = Document::read(out.as_bytes()).unwrap();
+        let tracks = doc.root.elements().filter(|el| el.name() == b"trk").collect::<Vec<_>>();
+        assert_eq!(tracks.len(), 2);
+        for (trk, color) in tracks.iter().zip(["#a71de1", "#10c0f0"]) {
+            let extensions = trk.child(b"extensions").unwrap();
+            assert_eq!(extensions.child(b"osmand:color").unwrap().text(), color);
+            assert_eq!(extensions.child(b"osmand:width").unwrap().text(), "thick");
+            assert_eq!(extensions.child(b"osmand:activity").unwrap().text(), "hiking");
+            let pt = trk.track_points().next().unwrap().child(b"extensions").unwrap();
+            assert_eq!(pt.child(b"speed").unwrap().text(), "1.3");
+            assert_eq!(pt.child(b"heading").unwrap().text(), "271");
+        }
+    }
+
+    #[test]
+    fn multiple_segments() {
+        let a = r#"<gpx>
+  <trk>
+    <trkseg>
+      <trkpt lat="1" lon="1"/>
+      <trkpt lat="2" lon="2"/>
+    </trkseg>
+    <trkseg>
+      <trkpt lat="3" lon="3"/>
+      <extensions><x/></extensions>
+    </trkseg>
+  </trk>
+</gpx>"#;
+        let b = r#"<gpx><trk><trkseg><trkpt lat="4" lon="4"/></trkseg><trkseg/><trkseg>
+<trkpt lat="5" lon="5"/></trkseg></trk></gpx>"#;
+        let out = join_str(&[a, b], &JoinConfig::default()).unwrap();
+        let doc = Document::read(out.as_bytes()).unwrap();
+        let segments = |trk: &Element| trk.elements().filter(|el| el.name() == b"trkseg").count();
+        let tracks = doc.root.elements().filter(|el| el.name() == b"trk").collect::<Vec<_>>();
+        assert_eq!(tracks.iter().map(|trk| segments(trk)).collect::<Vec<_>>(), [2, 3]);
+
+        let config = JoinConfig { merge_segments: true, ..Default::default() };
+        assert_eq!(join_str(&[a, b], &config).unwrap(), r#"<gpx>
+  <trk>
+    <trkseg>
+      <trkpt lat="1" lon="1"/>
+      <trkpt lat="2" lon="2"/>
+      <trkpt lat="3" lon="3"/>
+    </trkseg>
+  </trk>
+<trk><trkseg><trkpt lat="4" lon="4"/><trkpt lat="5" lon="5"/></trkseg></trk></gpx>"#);
+    }
+
+    #[test]
+    fn checkpoint() {
+        let files = [
+            "<gpx>\n  <trk><name>a</name></trk>\n</gpx>\n<!-- a -->\n",
+            "<gpx><trk><name>b</name></trk></gpx>",
+            "<gpx><trk><name>c</name></trk></gpx>",
+        ];
+        let mut sources = files.iter().map(|f| f.as_bytes()).collect::<Vec<_>>();
+        let mut full = vec![];
+        let mut checkpoints = vec![];
+        join_gpx_resumable(&mut sources, &mut full, &JoinConfig::default(), None, |c| {
+            checkpoints.push(c);
+            Ok(())
+        }).unwrap();
+        assert_eq!(String::from_utf8(full.clone()).unwrap(), concat!(
+            "<gpx>\n  <trk><name>a</name></trk>\n<trk><name>b</name></trk>",
+            "<trk><name>c</name></trk></gpx>\n<!-- a -->\n"));
+        assert_eq!(checkpoints.iter().map(|c| c.sources_done).collect::<Vec<_>>(), [1, 2, 3]);
+
+        // Picking up from each checkpoint, as if it had been interrupted after writing some more,
+        // gives the same result.
+        for &checkpoint in &checkpoints {
+            let mut out = full[.. checkpoint.bytes_written as usize].to_vec();
+            let mut sources = files.iter().map(|f| f.as_bytes()).collect::<Vec<_>>();
+            let mut later = vec![];
+            join_gpx_resumable(&mut sources, &mut out, &JoinConfig::default(), Some(checkpoint),
+                |c| {
+                    later.push(c);
+                    Ok(())
+                }).unwrap();
+            assert_eq!(out, full);
+            assert_eq!(later, checkpoints[checkpoint.sources_done ..]);
+        }
+
+        let config = JoinConfig { strategy: JoinStrategy::Interleave, ..Default::default() };
+        assert!(join_gpx_resumable(&mut sources, vec![], &config, None, |_| Ok(())).is_err());
+    }
+
+    #[test]
+    fn zip() {
+        let a = "<gpx><trk><name>1</name><trkseg><trkpt lat=\"1\" lon=\"1\"/></trkseg></trk>\
+            <trk><name>2</name><trkseg/></trk><trk><name>3</name></trk></gpx>";
+        let b = "<gpx><trk><name>b1</name><trkseg><trkpt lat=\"2\" lon=\"2\"/></trkseg>\
+            <trkseg/></trk><trk><name>b2</name><trkseg><trkpt lat=\"3\" lon=\"3\"/></trkseg>\
+            </trk></gpx>";
+        let config = JoinConfig { strategy: JoinStrategy::Zip, ..Default::default() };
+        assert_eq!(join_str(&[a, b], &config).unwrap(), concat!("<gpx><trk><name>1</name>",
+            r#"<trkseg><trkpt lat="1" lon="1"/></trkseg><trkseg><trkpt lat="2" lon="2"/></trkseg>"#,
+            r#"<trkseg/></trk><trk><name>2</name><trkseg/><trkseg><trkpt lat="3" lon="3"/>"#,
+            "</trkseg></trk><trk><name>3</name></trk></gpx>"));
+
+        // A file with fewer tracks is padded out, whichever file it is, so no tracks are lost.
+        let one = "<gpx><trk><name>1</name><trkseg><trkpt lat=\"1\" lon=\"1\"/></trkseg></trk>\
+            </gpx>";
+        let three = "<gpx><trk><name>x1</name><trkseg><trkpt lat=\"2\" lon=\"2\"/></trkseg></trk>\
+            <trk><name>x2</name><trkseg/></trk><trk><name>x3</name></trk></gpx>";
+        assert_eq!(join_str(&[one, three], &config).unwrap(), concat!("<gpx><trk><name>1</name>",
+            r#"<trkseg><trkpt lat="1" lon="1"/></trkseg><trkseg><trkpt lat="2" lon="2"/></trkseg>"#,
+            "</trk><trk><name>x2</name><trkseg/></trk><trk><name>x3</name></trk></gpx>"));
+        assert_eq!(join_str(&[three, one, three], &config).unwrap(), concat!("<gpx><trk>",
+            r#"<name>x1</name><trkseg><trkpt lat="2" lon="2"/></trkseg><trkseg><trkpt lat="1" "#,
+            r#"lon="1"/></trkseg><trkseg><trkpt lat="2" lon="2"/></trkseg></trk>"#,
+            "<trk><name>x2</name><trkseg/><trkseg/></trk><trk><name>x3</name></trk></gpx>"));
+    }
+
+    #[test]
+    fn reverse_tracks() {
+        let a = "<gpx>\n  <trk><name>1</name></trk>\n  <trk><name>2</name></trk>\n</gpx>";
+        let b = "<gpx><trk><name>3</name><trkseg><trkpt lat=\"1\" lon=\"1\"/>\
+            <trkpt lat=\"2\" lon=\"2\"/></trkseg></trk></gpx>";
+        let config = JoinConfig { reverse_tracks: true, ..Default::default() };
+        assert_eq!(join_str(&[a, b], &config).unwrap(), concat!("<gpx>\n  <trk><name>3</name>",
+            r#"<trkseg><trkpt lat="1" lon="1"/><trkpt lat="2" lon="2"/></trkseg></trk>"#,
+            "\n  <trk><name>2</name></trk>\n  <trk><name>1</name></trk>\n</gpx>"));
+    }
+
+    #[test]
+    fn flatten_tracks() {
+        let a = "<gpx>\n  <trk><name>1</name><trkseg><trkpt lat=\"1\" lon=\"1\"/></trkseg>\
+            </trk>\n  <trk><name>2</name><trkseg/><trkseg/></trk>\n</gpx>";
+        let b = "<gpx><trk><name>3</name></trk><trk><name>4</name><trkseg/></trk></gpx>";
+        let config = JoinConfig { flatten_tracks: true, ..Default::default() };
+        assert_eq!(join_str(&[a, b], &config).unwrap(), concat!("<gpx>\n  <trk><name>1</name>",
+            r#"<trkseg><trkpt lat="1" lon="1"/></trkseg><trkseg/><trkseg/></trk>"#,
+            "\n  <trk><name>3</name><trkseg/></trk>\n</gpx>"));
+    }
+
+    #[test]
+    fn wrap_in_trk() {
+        let a = "<gpx>\n  <trkseg><trkpt lat=\"1\" lon=\"1\"/></trkseg>\n  <trk/>\n</gpx>";
+        let b = "<gpx><trkseg><trkpt lat=\"2\" lon=\"2\"/></trkseg><trk/></gpx>";
+        assert_eq!(join_str(&[a, b], &JoinConfig::default()).unwrap(),
+            "<gpx>\n  <trkseg><trkpt lat=\"1\" lon=\"1\"/></trkseg>\n  <trk/>\n<trk/></gpx>");
+        let config = JoinConfig { wrap_in_trk: true, ..Default::default() };
+        assert_eq!(join_str(&[a, b], &config).unwrap(),
+            "<gpx>\n  <trk><trkseg><trkpt lat=\"1\" lon=\"1\"/></trkseg></trk>\n  <trk/>\n\
+            <trk><trkseg><trkpt lat=\"2\" lon=\"2\"/></trkseg></trk><trk/></gpx>");
+    }
+
+    #[test]
+    fn track_summaries() {
+        let a = "<gpx><trk><name>a</name><trkseg>\
+            <trkpt lat=\"47.000\" lon=\"-121\"><time>2021-08-27T10:00:00Z</time></trkpt>\
+            <trkpt lat=\"47.001\" lon=\"-121\"><time>2021-08-27T10:00:10.5Z</time></trkpt>\
+            </trkseg></trk><trk><extensions><summary/><x/></extensions></trk></gpx>";
+        let config = JoinConfig { track_summaries: true, ..Default::default() };
+        assert_eq!(join_str(&[a], &config).unwrap(), "<gpx><trk><name>a</name><extensions>\
+            <summary><points>2</points><distance_m>111.2</distance_m><duration_s>10.5</duration_s>\
+            </summary></extensions><trkseg>\
+            <trkpt lat=\"47.000\" lon=\"-121\"><time>2021-08-27T10:00:00Z</time></trkpt>\
+            <trkpt lat=\"47.001\" lon=\"-121\"><time>2021-08-27T10:00:10.5Z</time></trkpt>\
+            </trkseg></trk><trk><extensions><summary><points>0</points><distance_m>0</distance_m>\
+            </summary><x/></extensions></trk></gpx>");
+    }
+
+    #[test]
+    fn truncate_track_names() {
+        let a = "<gpx><trk><name> Überlingen – Konstanz </name></trk>\
+            <trk><name><![CDATA[Tom & Jerry's walk]]></name></trk><trk><name>Short</name></trk>\
+            </gpx>";
+        let config = JoinConfig { truncate_track_names: Some(10), ..Default::default() };
+        assert_eq!(join_str(&[a], &config).unwrap(), "<gpx><trk><name>Überlingen</name></trk>\
+            <trk><name><![CDATA[Tom & Jerr]]></name></trk><trk><name>Short</name></trk></gpx>");
+        let config = JoinConfig { truncate_ellipsis: true, ..config };
+        let joined = join_str(&[a], &config).unwrap();
+        assert!(joined.starts_with("<gpx><trk><name>Überlingen…</name></trk>\
+            <trk><name><![CDATA[Tom & Jerr…]]></name></trk><trk><name>Short</name>"), "{}", joined);
+    }
+
+    #[test]
+    fn reverse_points() {
+        let a = r#"<gpx><trk><trkseg>
+  <trkpt lat="1" lon="1"/>
+  <!-- 2 -->
+  <trkpt lat="2" lon="2"/>
+  <trkpt lat="3" lon="3"/>
+  <extensions/>
+</trkseg><trkseg><trkpt lat="4" lon="4"/></trkseg></trk></gpx>"#;
+        let config = JoinConfig { reverse_points: true, ..Default::default() };
+        assert_eq!(join_str(&[a], &config).unwrap(), r#"<gpx><trk><trkseg>
+  <trkpt lat="3" lon="3"/>
+  <!-- 2 -->
+  <trkpt lat="2" lon="2"/>
+  <trkpt lat="1" lon="1"/>
+  <extensions/>
+</trkseg><trkseg><trkpt lat="4" lon="4"/></trkseg></trk></gpx>"#);
+    }
+
+    #[test]
+    fn ignore_namespace() {
+        let a = r#"<gpx:gpx xmlns:gpx="http://www.topografix.com/GPX/1/1"><gpx:trk/></gpx:gpx>"#;
+        let b = r#"<gpx><trk/></gpx>"#;
+        let c = r#"<g:gpx xmlns:g="http://www.topografix.com/GPX/1/1"><g:trk><g:name>c</g:name>
+            </g:trk><g:wpt lat="1" lon="2"/></g:gpx>"#;
+        assert!(join_str(&[a, b], &JoinConfig::default()).is_err());
+        let config = JoinConfig { ignore_namespace: true, ..Default::default() };
+        assert_eq!(join_str(&[a, b, c], &config).unwrap(), concat!(
+            r#"<gpx:gpx xmlns:gpx="http://www.topografix.com/GPX/1/1"><gpx:trk/><trk/>"#,
+            "<g:trk><g:name>c</g:name>\n            </g:trk></gpx:gpx>"));
+    }
+
+    #[test]
+    fn mixed_namespace_prefixes() {
+        // One file in the default namespace and one with a prefix for it. Without
+        // ignore_namespace, the prefixed file is refused rather than having its tracks silently
+        // left out.
+        let a = r#"<gpx xmlns="http://www.topografix.com/GPX/1/1"><trk><name>a</name></trk></gpx>"#;
+        let b = r#"<gpx:gpx xmlns:gpx="http://www.topografix.com/GPX/1/1">
+  <gpx:trk><gpx:name>b</gpx:name><gpx:trkseg><gpx:trkpt lat="1" lon="2"/></gpx:trkseg></gpx:trk>
+</gpx:gpx>"#;
+        let err = join_str(&[a, b], &JoinConfig::default()).unwrap_err();
+        assert!(err.to_string().contains("gpx:gpx"), "{}", err);
+        let config = JoinConfig { ignore_namespace: true, ..Default::default() };
+        assert_eq!(join_str(&[a, b], &config).unwrap(), concat!(
+            r#"<gpx xmlns="http://www.topografix.com/GPX/1/1"><trk><name>a</name></trk>"#,
+            r#"<gpx:trk><gpx:name>b</gpx:name><gpx:trkseg><gpx:trkpt lat="1" lon="2"/>"#,
+            "</gpx:trkseg></gpx:trk></gpx>"));
+    }
+
+    #[test]
+    fn self_closing_tracks() {
+        let a = "<gpx>\n    <trk/>\n    <trk><name>a</name></trk>\n</gpx>";
+        let b = "<gpx>\n    <wpt lat=\"1\" lon=\"1\"/>\n    <trk/>\n    <trk><name>b</name></trk>\n</gpx>";
+        let joined = join_str(&[a, b], &JoinConfig::default()).unwrap();
+        assert_eq!(joined,
+            "<gpx>\n    <trk/>\n    <trk><name>a</name></trk>\n<trk/><trk><name>b</name></trk></gpx>");
+
+        let mut sources = [a.as_bytes(), b.as_bytes()];
+        let stats = join_gpx_with_stats(&mut sources, vec![], &JoinConfig::default()).unwrap();
+        assert_eq!(stats.tracks, 4);
+    }
+
+    #[test]
+    fn cdata() {
+        // Features that look at or change element content see through CDATA sections.
+        let a = r#"<gpx>
+    <metadata><name><![CDATA[<a> & co]]></name></metadata>
+    <trk><name><![CDATA[Tom & Jerry]]></name><type><![CDATA[Run]]></type><trkseg>
+        <trkpt lat="47.0" lon="-121.0"><time><![CDATA[2021-08-27T10:00:00Z
+]]></time></trkpt>
+    </trkseg></trk>
+</gpx>"#;
+        let b = "<gpx><trk><name>tom &amp; jerry</name></trk></gpx>";
+        let config = JoinConfig {
+            fix: true,
+            strategy: JoinStrategy::Replace,
+            strava_to_garmin: true,
+            ..Default::default()
+        };
+        assert_eq!(join_str(&[a, a], &config).unwrap(), join_str(&[a], &config).unwrap());
+        assert_eq!(join_str(&[a, b], &config).unwrap(), r#"<gpx>
+    <metadata><name><![CDATA[<a> & co]]></name></metadata>
+    <trk><name>tom &amp; jerry</name></trk>
+</gpx>"#);
+        let joined = join_str(&[a], &config).unwrap();
+        assert!(joined.contains("<time>2021-08-27T10:00:00Z</time>"), "{}", joined);
+        assert!(joined.contains("<gpxx:DisplayColor>"), "{}", joined);
+
+        let metadata = Metadata::read(a.as_bytes()).unwrap();
+        let config = JoinConfig { metadata, ..Default::default() };
+        assert_eq!(join_str(&["<gpx>\n    <wpt lat=\"1\" lon=\"1\"/>\n</gpx>"], &config).unwrap(),
+            r#"<gpx>
+    <metadata><name><![CDATA[<a> & co]]></name></metadata>
+    <wpt lat="1" lon="1"/>
+</gpx>"#);
+    }
+
+    #[test]
+    fn strip_cdata() {
+        let a = "<gpx><trk><name><![CDATA[Rock & Roll < 3]]></name></trk></gpx>\n\
+            <!-- <![CDATA[not a section]]> -->";
+        let b = "<gpx><trk><desc><![CDATA[]]></desc></trk></gpx>";
+        let config = JoinConfig { strip_cdata: true, ..Default::default() };
+        let joined = join_str(&[a, b], &config).unwrap();
+        assert_eq!(joined, "<gpx><trk><name>Rock &amp; Roll &lt; 3</name></trk><trk><desc></desc>\
+            </trk></gpx>\n<!-- <![CDATA[not a section]]> -->");
+        // Nothing is lost: the text reads back the same as the section did.
+        let doc = Document::read(joined.as_bytes()).unwrap();
+        assert_eq!(doc.root.child(b"trk").unwrap().track_name().unwrap(), "Rock & Roll < 3");
+    }
+
+    #[test]
+    fn enforce_coordinate_order() {
+        let gpx = r#"<gpx><wpt lat="-121.5" lon="47.25"/><trk><name>a</name><trkseg>
+            <trkpt lat="-121.5" lon="47.25"/>
+            <trkpt lat="47.25" lon="-121.5"/>
+            <trkpt lat="-121.5" lon="-121.5"/>
+        </trkseg></trk></gpx>"#;
+        let mut doc = Document::read(gpx.as_bytes()).unwrap();
+        let trk = doc.root.child_mut(b"trk").unwrap();
+        assert_eq!(fix::enforce_coordinate_order(trk), 1);
+        assert_eq!(fix::enforce_coordinate_order(trk), 0);
+
+        let config = JoinConfig { enforce_coordinate_order: true, ..Default::default() };
+        // Both of the swapped points are fixed, and the one that's invalid either way is left.
+        assert_eq!(join_str(&[gpx], &config).unwrap(),
+            gpx.replace(r#"lat="-121.5" lon="47.25""#, r#"lat="47.25" lon="-121.5""#));
+    }
+
+    #[test]
+    fn fix_trk_order() {
+        let a = "<gpx>\n  <trk>\n    <trkseg/>\n    <!-- c -->\n    <type>hike</type>\n    \
+            <name>a</name>\n    <trkseg/>\n  </trk>\n</gpx>";
+        assert_eq!(join_str(&[a], &JoinConfig::default()).unwrap(), a);
+        let config = JoinConfig { fix_trk_order: true, ..Default::default() };
+        assert_eq!(join_str(&[a], &config).unwrap(), "<gpx>\n  <trk>\n    <name>a</name>\n    \
+            <!-- c -->\n    <type>hike</type>\n    <trkseg/>\n    <trkseg/>\n  </trk>\n</gpx>");
+    }
+
+    #[test]
+    fn require_time() {
+        let a = "<gpx><trk><trkseg><trkpt lat=\"1\" lon=\"1\"><time>2021-08-27T10:00:00Z</time>\
+            </trkpt></trkseg></trk><wpt lat=\"1\" lon=\"1\"/></gpx>";
+        let b = "<gpx><trk><name>b</name><trkseg/><trkseg><trkpt lat=\"1\" lon=\"1\"/>\
+            <trkpt lat=\"2\" lon=\"2\"/></trkseg></trk></gpx>";
+        assert!(join_str(&[a, b], &JoinConfig::default()).is_ok());
+        let config = JoinConfig { require_time: true, ..Default::default() };
+        assert!(join_str(&[a, a], &config).is_ok());
+        assert_eq!(join_str(&[a, b], &config).unwrap_err().to_string(), "source 2: track \"b\" \
+            segment 2 has 2 points without a time, like <trkpt lat=\"1\" lon=\"1\">");
+        let config = JoinConfig { no_tracks: true, ..config };
+        assert!(join_str(&[a, b], &config).is_ok());
+    }
+
+    #[test]
+    fn sample_first() {
+        let a = "<gpx><trk><trkseg>\n  <trkpt lat=\"1\" lon=\"1\"/>\n  \
+            <trkpt lat=\"2\" lon=\"2\"/>\n  <trkpt lat=\"3\" lon=\"3\"/>\n  <extensions/>\n\
+            </trkseg><trkseg><trkpt lat=\"4\" lon=\"4\"/></trkseg></trk></gpx>";
+        let b = "<gpx><trk><trkseg><trkpt lat=\"5\" lon=\"5\"/><trkpt lat=\"6\" lon=\"6\"/>\
+            </trkseg></trk></gpx>";
+        // Each segment is sampled before they're merged.
+        let config = JoinConfig { sample_first: Some(2), merge_segments: true,
+            ..Default::default() };
+        assert_eq!(join_str(&[a, b], &config).unwrap(), "<gpx><trk><trkseg>\n  \
+            <trkpt lat=\"1\" lon=\"1\"/>\n  <trkpt lat=\"2\" lon=\"2\"/>\n  \
+            <trkpt lat=\"4\" lon=\"4\"/>\n  <extensions/>\n</trkseg></trk>\
+            <trk><trkseg><trkpt lat=\"5\" lon=\"5\"/><trkpt lat=\"6\" lon=\"6\"/></trkseg>\
+            </trk></gpx>");
+    }
+
+    #[test]
+    fn start_time() {
+        let pt = |lat, time| format!("<trkpt lat=\"{}\" lon=\"1\"><time>2021-08-27T{}Z</time>\
+            </trkpt>", lat, time);
+        let a = format!("<gpx><trk><trkseg>{}<trkpt lat=\"2\" lon=\"1\"/>{}{}</trkseg>\
+            <trkseg>{}</trkseg><trkseg><trkpt lat=\"6\" lon=\"1\"/></trkseg></trk></gpx>",
+            pt(1, "09:00:00"), pt(3, "10:00:00"), pt(4, "09:30:00"), pt(5, "09:59:59"));
+        let config = JoinConfig {
+            start_time: Timestamp::parse("2021-08-27T10:00:00Z"),
+            ..Default::default()
+        };
+        // Only the points before the first one that's late enough are left out, along with any
+        // untimed ones among them, and segments without times are left alone.
+        assert_eq!(join_str(&[&a], &config).unwrap(), format!("<gpx><trk><trkseg>{}{}</trkseg>\
+            <trkseg></trkseg><trkseg><trkpt lat=\"6\" lon=\"1\"/></trkseg></trk></gpx>",
+            pt(3, "10:00:00"), pt(4, "09:30:00")));
+    }
+
+    #[test]
+    fn verify_coordinates() {
+        let a = r#"<gpx><wpt lat="47" lon="-121"/><trk><name>a</name></trk></gpx>"#;
+        let b = r#"<gpx><trk><name>b</name><trkseg><trkpt lat="47.6" lon="-122.3"/>
+            <trkpt lat="5272000" lon="550000"/><trkpt lat="-121.0" lon="47.0"/></trkseg></trk>
+            </gpx>"#;
+        // Without the option, they're only warned about.
+        assert!(join_str(&[a, b], &JoinConfig::default()).is_ok());
+
+        let config = JoinConfig { verify_coordinates: true, ..Default::default() };
+        assert!(join_str(&[a, a], &config).is_ok());
+        assert_eq!(join_str(&[a, b], &config).unwrap_err().to_string(), "source 2: track \"b\" \
+            has 2 points with out-of-range coordinates, like <trkpt lat=\"5272000\" \
+            lon=\"550000\">");
+        // Swapping fixes the one that can be fixed.
+        let config = JoinConfig { fix: true, ..config };
+        assert_eq!(join_str(&[a, b], &config).unwrap_err().to_string(), "source 2: track \"b\" \
+            has 1 point with out-of-range coordinates, like <trkpt lat=\"5272000\" \
+            lon=\"550000\">");
+        let b = r#"<gpx><rte><rtept lat="0" lon="181"/></rte></gpx>"#;
+        assert!(join_str(&[b], &config).is_err());
+    }
+
+    #[test]
+    fn min_points_per_segment() {
+        let a = r#"<gpx><trk><trkseg>
+    <trkpt lat="1" lon="1"/>
+</trkseg><trkseg>
+    <trkpt lat="2" lon="2"/>
+    <trkpt lat="3" lon="3"/>
+</trkseg><trkseg/></trk></gpx>"#;
+        let config = JoinConfig { min_points_per_segment: Some(2), ..Default::default() };
+        assert_eq!(join_str(&[a], &config).unwrap(), r#"<gpx><trk><trkseg>
+    <trkpt lat="2" lon="2"/>
+    <trkpt lat="3" lon="3"/>
+</trkseg></trk></gpx>"#);
+    }
+
+    #[test]
+    fn example() {
+        assert_eq!(validate_gpx(EXAMPLE_GPX.as_bytes()).collect::<Vec<_>>(), []);
+        let mut sources = [EXAMPLE_GPX.as_bytes(), EXAMPLE_GPX.as_bytes()];
+        let stats = join_gpx_with_stats(&mut sources, vec![], &JoinConfig::default()).unwrap();
+        assert_eq!(stats.tracks, 2);
+        assert_eq!(stats.segments.iter().map(|s| s.point_count).collect::<Vec<_>>(), [2, 2]);
+    }
+
+    #[test]
+    fn entities_round_trip() {
+        let text = r#"Rock & Roll <3 > cafe "here" 'too'"#;
+        let escaped = "Rock &amp; Roll &lt;3 &gt; cafe &quot;here&quot; &apos;too&apos;";
+        let a = format!(r#"<gpx creator="{0}"><trk><name>{0}</name></trk></gpx>"#, escaped);
+        // Text and attributes gpxjoin writes itself are escaped the same way as the ones copied
+        // from the file.
+        let config = JoinConfig {
+            track_comment: Some(text.to_owned()),
+            gpx_version: Some(text.to_owned()),
+            ..Default::default()
+        };
+        let joined = join_str(&[&a], &config).unwrap();
+        assert_eq!(joined, format!(
+            r#"<gpx creator="{0}" version="{0}"><trk><name>{0}</name><cmt>{0}</cmt></trk></gpx>"#,
+            escaped));
+
+        let doc = Document::read(joined.as_bytes()).unwrap();
+        assert_eq!(doc.root.attr(b"creator").unwrap(), text);
+        assert_eq!(doc.root.attr(b"version").unwrap(), text);
+        let trk = doc.root.child(b"trk").unwrap();
+        assert_eq!(trk.track_name().unwrap(), text);
+        assert_eq!(trk.child(b"cmt").unwrap().text(), text);
+    }
+
+    #[test]
+    fn point_conversion() {
+        let wpts = r#"<gpx>
+    <wpt lat="47.5" lon="-121.1"><ele>100</ele><name>start</name></wpt>
+    <wpt lat="47.6" lon="-121.2"/>
+    <trk/>
+</gpx>"#;
+        let rte = r#"<gpx>
+    <rte><rtept lat="47.5" lon="-121.1"><ele>100</ele><name>start</name></rtept></rte>
+    <rte><rtept lat="47.6" lon="-121.2"/></rte>
+    <trk/>
+</gpx>"#;
+        let config = JoinConfig {
+            point_conversion: Some(PointConversion::WptToRtept),
+            ..Default::default()
+        };
+        assert_eq!(join_str(&[wpts], &config).unwrap(), rte);
+
+        let config = JoinConfig {
+            point_conversion: Some(PointConversion::RteptToWpt),
+            ..Default::default()
+        };
+        assert_eq!(join_str(&[rte], &config).unwrap(), wpts);
+    }
+
+    #[test]
+    fn circle() {
+        // About 1.1 km apart, north to south.
+        let gpx = r#"<gpx>
+    <wpt lat="47.54" lon="-121.1"/>
+    <wpt lat="47.55" lon="-121.1"/>
+    <trk><trkseg>
+        <trkpt lat="47.54" lon="-121.1"/>
+        <trkpt lat="47.55" lon="-121.1"/>
+    </trkseg><trkseg>
+        <trkpt lat="47.56" lon="-121.1"/>
+    </trkseg></trk>
+</gpx>"#;
+        let config = JoinConfig {
+            area: Some(Area::Circle { lat: 47.54, lon: -121.1, radius: 1000. }),
+            ..Default::default()
+        };
+        assert_eq!(join_str(&[gpx], &config).unwrap(), r#"<gpx>
+    <wpt lat="47.54" lon="-121.1"/>
+    <trk><trkseg>
+        <trkpt lat="47.54" lon="-121.1"/>
+    </trkseg><trkseg>
+    </trkseg></trk>
+</gpx>"#);
+    }
+
+    #[test]
+    fn interleave() {
+        let a = r#"<gpx>
+    <metadata><name>a</name></metadata>
+    <trk><name>a1</name><trkseg><trkpt lat="1" lon="1"><time>2021-08-27T10:00:00Z</time></trkpt></trkseg></trk>
+    <trk><name>a2</name><trkseg><trkpt lat="1" lon="1"><time>2021-08-27T12:00:00Z</time></trkpt></trkseg></trk>
+</gpx>
+"#;
+        let b = r#"<gpx>
+    <trk><name>b1</name></trk>
+    <trk><name>b2</name><trkseg><trkpt lat="1" lon="1"><time>2021-08-27T11:00:00Z</time></trkpt></trkseg></trk>
+</gpx>
+"#;
+        let config = JoinConfig { strategy: JoinStrategy::Interleave, ..Default::default() };
+        assert_eq!(join_str(&[a, b], &config).unwrap(), r#"<gpx>
+    <metadata><name>a</name></metadata>
+    <trk><name>a1</name><trkseg><trkpt lat="1" lon="1"><time>2021-08-27T10:00:00Z</time></trkpt></trkseg></trk>
+    <trk><name>b2</name><trkseg><trkpt lat="1" lon="1"><time>2021-08-27T11:00:00Z</time></trkpt></trkseg></trk>
+    <trk><name>a2</name><trkseg><trkpt lat="1" lon="1"><time>2021-08-27T12:00:00Z</time></trkpt></trkseg></trk>
+    <trk><name>b1</name></trk>
+</gpx>
+"#);
+    }
+
+    #[test]
+    fn replace() {
+        let a = "<gpx>\n    <trk><name>One</name></trk>\n    <trk><name>two</name></trk>\n</gpx>\n";
+        let b = "<gpx>\n    <trk><name>three</name></trk>\n    <trk><name>one</name><desc>new</desc></trk>\n</gpx>\n";
+        let mut config = JoinConfig { strategy: JoinStrategy::Replace, ..Default::default() };
+        assert_eq!(join_str(&[a, b], &config).unwrap(), "<gpx>
+    <trk><name>one</name><desc>new</desc></trk>
+    <trk><name>two</name></trk>
+    <trk><name>three</name></trk>
+</gpx>
+");
+
+        config.case_sensitive_names = true;
+        assert_eq!(join_str(&[a, b], &config).unwrap(), "<gpx>
+    <trk><name>One</name></trk>
+    <trk><name>two</name></trk>
+    <trk><name>three</name></trk>
+    <trk><name>one</name><desc>new</desc></trk>
+</gpx>
+");
+    }
+
+    #[test]
+    fn merge() {
+        let trk = |name: &str, time: &str| format!("<trk><name>{}</name><trkseg><trkpt lat=\"1\" \
+            lon=\"1\"><time>{}</time></trkpt></trkseg></trk>", name, time);
+        let a = format!("<gpx>{}{}</gpx>", trk("x", "2021-08-27T10:00:00Z"),
+            trk("y", "2021-08-27T11:00:00Z"));
+        let b = format!("<gpx>{}</gpx>", trk("X", "2021-08-27T12:00:00Z"));
+        let c = format!("<gpx>{}</gpx>", trk("x", "2021-08-27T09:00:00Z"));
+        let sources = [&a[..], &b[..], &c[..]];
+        let join = |merge| {
+            let config = JoinConfig { merge_strategy: Some(merge), ..Default::default() };
+            join_str(&sources, &config).unwrap()
+        };
+        assert_eq!(join(MergeStrategy::FirstWins), format!("<gpx>{}{}</gpx>",
+            trk("x", "2021-08-27T10:00:00Z"), trk("y", "2021-08-27T11:00:00Z")));
+        assert_eq!(join(MergeStrategy::LastWins), format!("<gpx>{}{}</gpx>",
+            trk("x", "2021-08-27T09:00:00Z"), trk("y", "2021-08-27T11:00:00Z")));
+        assert_eq!(join(MergeStrategy::Newest), format!("<gpx>{}{}</gpx>",
+            trk("X", "2021-08-27T12:00:00Z"), trk("y", "2021-08-27T11:00:00Z")));
+    }
+
+    #[test]
+    fn strava() {
+        let strava = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx creator="StravaGPX" version="1.1" xmlns="http://www.topografix.com/GPX/1/1">
+ <metadata>
+  <time>2021-08-27T18:59:24Z</time>
+ </metadata>
+ <trk>
+  <name>Morning Run</name>
+  <type>Run</type>
+  <trkseg>
+   <trkpt lat="47.5434480" lon="-121.0964620">
+    <ele>120.4</ele>
+    <time>2021-08-27T18:59:24Z</time>
+   </trkpt>
+  </trkseg>
+ </trk>
+</gpx>
+"#;
+        let ride = strava.replace("Morning Run", "Afternoon Ride").replace(">Run<", ">cycling<");
+        let skate = strava.replace(">Run<", ">InlineSkate<");
+
+        // The activity type is passed through as-is.
+        let joined = join_str(&[strava, &ride, &skate], &JoinConfig::default()).unwrap();
+        assert_eq!(joined.matches("<type>").count(), 3);
+        assert!(!joined.contains("DisplayColor"));
+
+        let config = JoinConfig { strava_to_garmin: true, ..Default::default() };
+        let joined = join_str(&[strava, &ride, &skate], &config).unwrap();
+        let colors = joined.split("<gpxx:DisplayColor>")
+            .skip(1)
+            .map(|s| s.split('<').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(colors, ["Red", "Blue"]);
+        assert!(joined.contains(r#"  <type>Run</type>
+  <extensions><gpxx:TrackExtension xmlns:gpxx="http://www.garmin.com/xmlschemas/GpxExtensions/v3">"#),
+            "{}", joined);
+    }
+}
@@ -0,0 +1,174 @@
+//! Logging of warnings and progress details to standard error.
+//!
+//! Messages are logged with the [`error!`], [`warn!`], [`info!`], and [`debug!`] macros. Which of
+//! them are shown, and how, is set for the whole process with [`set_level`], [`set_format`], and
+//! [`set_color`]. By default, errors and warnings are shown as plain text without color.
+
+use crate::Timestamp;
+use std::fmt;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+
+    /// The ANSI SGR parameters to show the level's prefix in.
+    fn color(self) -> &'static str {
+        match self {
+            Level::Error => "1;31",
+            Level::Warn => "1;33",
+            Level::Info => "32",
+            Level::Debug => "2",
+        }
+    }
+}
+
+impl std::str::FromStr for Level {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Level> {
+        Ok(match s {
+            "error" => Level::Error,
+            "warn" => Level::Warn,
+            "info" => Level::Info,
+            "debug" => Level::Debug,
+            _ => anyhow::bail!("unknown log level {:?}", s),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Plain text, one message per line. Warnings and errors are prefixed with their level.
+    Text = 0,
+    /// JSON Lines: one object per message, with `timestamp`, `level`, and `message` fields.
+    Json = 1,
+}
+
+impl std::str::FromStr for Format {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Format> {
+        Ok(match s {
+            "text" => Format::Text,
+            "json" => Format::Json,
+            _ => anyhow::bail!("unknown log format {:?}", s),
+        })
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+static FORMAT: AtomicU8 = AtomicU8::new(Format::Text as u8);
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Show messages of this level and more severe ones.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn set_format(format: Format) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+pub fn format() -> Format {
+    if FORMAT.load(Ordering::Relaxed) == Format::Json as u8 {
+        Format::Json
+    } else {
+        Format::Text
+    }
+}
+
+/// Use ANSI escape codes to color text messages by their level. Meant for when standard error is
+/// a terminal; JSON messages are never colored.
+pub fn set_color(color: bool) {
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+pub fn color() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// Whether messages of the given level are being shown.
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+#[doc(hidden)]
+pub fn log(level: Level, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let line = match format() {
+        Format::Text => match level {
+            Level::Info => args.to_string(),
+            _ => format!("{}: {}", paint(level, level.name()), args),
+        },
+        Format::Json => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            format!(r#"{{"timestamp":"{}","level":"{}","message":"{}"}}"#,
+                Timestamp(now.as_millis() as i64), level.name(), json_escape(&args.to_string()))
+        }
+    };
+    // Write the whole line at once so messages from different threads don't get mixed up.
+    let _ = writeln!(std::io::stderr().lock(), "{}", line);
+}
+
+/// Color some text for the given level, if color is turned on.
+pub fn paint(level: Level, text: &str) -> String {
+    if color() {
+        format!("\x1b[{}m{}\x1b[0m", level.color(), text)
+    } else {
+        text.to_owned()
+    }
+}
+
+/// Escape a string for putting inside the quotes of a JSON string.
+pub fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Error, format_args!($($arg)*)) }
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Warn, format_args!($($arg)*)) }
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Info, format_args!($($arg)*)) }
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Debug, format_args!($($arg)*)) }
+}
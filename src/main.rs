@@ -1,206 +1,1712 @@
-use anyhow::{bail, Context as _};
-use quick_xml::{Reader, Writer};
-use quick_xml::events::Event;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
-use std::path::PathBuf;
-
-trait StartsWithExt<U> {
-    fn starts_with(&self, other: U) -> bool;
-}
-
-// Because the stdlib's slice::starts_with() doesn't work when the inner type is Vec<u8> and we're
-// comparing against &[u8].
-impl<S: AsRef<[u8]>> StartsWithExt<&[S]> for Vec<Vec<u8>> {
-    fn starts_with(&self, other: &[S]) -> bool {
-        if self.len() < other.len() {
-            return false;
-        }
-        for (a, b) in self.iter().zip(other) {
-            if a != b.as_ref() {
-                return false;
-            }
-        }
-        true
-    }
+use anyhow::{anyhow, bail, Context as _};
+use gpxjoin::{check_well_formed, diff_tracks, elevation_profile, error, info,
+    join_gpx_resumable, join_gpx_with_stats, log, snap_waypoints, split_by_date, validate_gpx,
+    warn, write_header, write_nmea, Area, Checkpoint, DuplicateNames, Extensions, Index,
+    JoinConfig, JoinStats, JoinStrategy, Kalman, MergeStrategy, Metadata, PointConversion,
+    Resample, SimplifyAlgorithm, Summary, Timestamp, TrackSummary, TrimStationary, ZipArchive,
+    ZipWriter, EXAMPLE_GPX};
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, IsTerminal as _, Seek, SeekFrom,
+    Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, PartialEq)]
+enum Mode {
+    Join,
+    Check,
+    /// Write an index of the track point times in a file.
+    Index,
+    /// Write out the first file without its tracks.
+    Header,
+    /// Describe how the tracks in the other files differ from those in the first.
+    Diff,
+    /// Write out an example GPX file.
+    Example,
+    /// Describe each track in each file.
+    ListTracks,
+    /// Print the number of tracks the result would have.
+    Count,
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+enum Format {
+    Gpx,
+    Nmea,
+    #[cfg(feature = "prost")]
+    Protobuf,
+    #[cfg(feature = "fit-output")]
+    Fit,
+}
+
+const OPTIONS: &str = "
+options:
+  --base=FILE           use FILE as the first file, which provides the metadata, waypoints,
+                        and everything else besides tracks, wherever it is in the arguments
+  --join-strategy=append|interleave|replace|zip
+                        how to combine tracks: append them in file order (the default),
+                        interleave them by start time, replace tracks in the first file
+                        with tracks of the same name from subsequent files, or combine the
+                        first tracks of each file into one, then the second, and so on
+  --zip-tracks          the same as --join-strategy=zip
+  --wrap-in-trk         put each track segment that isn't in a track into a new track of its
+                        own, so that it's kept from every file
+  --flatten-tracks      combine all the tracks from each file into one, keeping their segments
+  --reverse-tracks      write the tracks in the opposite order, last to first
+  --reverse-points      reverse the order of the points in each track segment
+  --merge-strategy=first-wins|last-wins|newest
+                        when several tracks have the same name, keep only the first one, the
+                        last one, or the one that starts latest
+  --ignore-namespace    recognize <gpx> and <trk> elements with a namespace prefix too, like
+                        <gpx:trk>
+  --merge-segments      join all the segments of each track into one
+  --first-name-wins, --last-name-wins
+                        keep only the first or last <name> of tracks that have more than one
+                        (by default they're all kept, with a warning)
+  --truncate-track-names=N
+                        shorten track names to at most N characters
+  --truncate-ellipsis   end the names shortened by --truncate-track-names with an ellipsis
+  --case-sensitive-names
+                        match track names exactly for --join-strategy=replace and
+                        --merge-strategy, instead of ignoring case
+  --fix                 repair swapped lat/lon, line breaks in timestamps, byte order marks,
+                        and duplicate consecutive points
+  --enforce-coordinate-order
+                        only swap lat and lon where the latitude is out of range, warning
+                        about each track or waypoint fixed
+  --fix-trk-order       put the elements inside each track in the order the GPX schema
+                        requires
+  --verify-coordinates  fail if any point's latitude or longitude is out of range, instead of
+                        warning about it
+  --require-time        fail if any track point doesn't have a time
+  --no-metadata         leave out the first file's <metadata> element
+  --no-trk              leave out all the tracks, keeping the first file's metadata,
+                        waypoints, and routes
+  --no-wpt              leave out the first file's waypoints
+  --no-rte              leave out the first file's routes
+  --tracks-only         leave out the waypoints and routes; the same as --no-wpt --no-rte
+  --metadata-from=N     use the <metadata> element of the Nth file instead of the first's
+  --merge-metadata      use the first file's <metadata>, with the links and author names from
+                        all the files' added
+  --merge-emails        with --merge-metadata, take the author's email from another file if
+                        the first doesn't have one
+  --track-source-comment
+                        put a comment before each track naming the file it came from and its
+                        number among that file's tracks
+  --inject-gpx-extensions=XML_FILE
+                        add the elements in XML_FILE to the root element's <extensions>,
+                        adding one if the first file has none
+  --metadata-keywords=KEYWORDS
+                        set the <keywords> of the output's metadata to KEYWORDS
+  --metadata-copyright=YEAR,AUTHOR[,LICENSE]
+                        set the <copyright> of the output's metadata, with a link to the
+                        license if it's given
+  --comment=TEXT        add an XML comment before the root element; can be given more than
+                        once
+  --gpx-version=1.0|1.1 set the version attribute of the root element, instead of keeping the
+                        first file's
+  --check-gpx-version   fail, writing nothing, if a file's GPX namespace (and so its version)
+                        differs from the first file's, instead of warning
+  --schema-location     add an xsi:schemaLocation attribute pointing at the GPX 1.1 schema to
+                        the root element
+  --normalize-creator   clean up the root element's creator attribute, leaving out version
+                        numbers and using the usual names of well-known apps
+  --encoding=NAME       declare NAME as the encoding in the XML declaration; the output is
+                        still UTF-8
+  --no-xml-declaration  leave out the XML declaration
+  --strip-cdata         write the content of CDATA sections as escaped text
+  --xml-standalone=yes|no
+                        declare whether the output is standalone in the XML declaration
+  --cmt=TEXT            set the <cmt> of every track to TEXT
+  --strava-to-garmin    give tracks with a Strava activity type (Run, Ride, Hike, ...) the
+                        matching Garmin track display color
+  --format=gpx|nmea|protobuf|fit
+                        write the result as GPX (the default), as NMEA 0183 sentences, as a
+                        protobuf message (see proto/gpx.proto; needs the \"prost\"
+                        feature), or as a FIT activity file (needs the \"fit-output\" feature)
+  -o, --output=FILE     write to FILE instead of standard output. FILE is only replaced once
+                        the result has been completely written. If FILE ends in .zip, it's
+                        written as a zip archive with the result in it.
+  --zip-entry-name=NAME with -o FILE.zip, the name of the result in the archive, instead of
+                        merged.gpx
+  --recursive           for zip archives given as files to join, which are replaced by the
+                        .gpx files in them in name order, also include the ones in
+                        directories inside the archive
+  --checkpoint=STATE_FILE
+                        write the -o file as it's made, instead of all at once, and keep track
+                        in STATE_FILE of how far it's got
+  --resume              with --checkpoint, carry on with a join that was interrupted, from
+                        where STATE_FILE says it got to
+  --watch=DIR           join the GPX files in DIR, after any others given, and join them
+                        again whenever one changes, until killed. Requires -o.
+  --summary             after writing the result, print a line to standard output for each
+                        input file, like \"a.gpx: 3 tracks, 2847 pts, 42.3km, 2021-08-27\"
+  --in-place            write the result back to the input file, which must be the only one;
+                        it's only replaced once the result has been completely written, and
+                        only as GPX
+  --wpt-as-rtept        convert each waypoint into a route with a single route point
+  --rtept-as-wpt        convert route points into standalone waypoints, removing the routes
+  --diff                instead of joining, describe each track in the files after the first
+                        as added, changed, or unchanged compared to the first file, going by
+                        name and time range
+  --emit-header-only    write out the file's XML declaration, metadata, and everything else up
+                        to its first track, then close the root element, making a template
+                        with no tracks
+  --generate-example    instead of joining, write a small example GPX file to standard output
+  --list-tracks         instead of joining, print a line to standard output for each track in
+                        each file, with its file and number, name, date, and length
+  --count               instead of writing the result, print the number of tracks it would
+                        have
+  --throughput          print to standard error how fast each file was read, counting only
+                        the time spent reading it
+  --verify-output       read the output back to check that it's well-formed GPX, renaming it
+                        with a .corrupt suffix if it isn't (requires -o)
+  --split-by-date       write one file per UTC date of the track points, splitting tracks at
+                        midnight. Requires --split-output.
+  --split-output=TEMPLATE
+                        file name template for split output; \"{date}\" is replaced with the
+                        date as YYYY-MM-DD. With -o FILE.zip, the files are written into that
+                        archive instead.
+  --since=DATE          only keep tracks with a point on or after DATE (YYYY-MM-DD, UTC)
+  --until=DATE          only keep tracks with a point on or before DATE (YYYY-MM-DD, UTC)
+  --start-time=TIME     leave out the points at the start of each track segment before TIME,
+                        like 2021-08-27T10:00:00Z. Files after the first with an index are
+                        read from the indexed point before TIME in their first segment.
+  --max-age=DAYS        only keep tracks with a point in the last DAYS days
+  --center=LAT,LON --radius=KM
+                        only keep track points and waypoints within KM kilometers of the
+                        given point
+  --crop-to-reference=FILE
+                        only keep track points and waypoints within the bounding box of all
+                        the points in FILE, which isn't otherwise part of the output
+  --interpolate-elevation
+                        give track points without an elevation one interpolated from the
+                        points before and after them
+  --max-elevation-change=METERS
+                        remove track points whose elevation jumps by more than METERS and
+                        then comes back at the next point
+  --smooth-kalman       smooth the noise out of track point coordinates with a Kalman filter
+  --kalman-q=METERS     how far the position is expected to move each second, for
+                        --smooth-kalman (default: 3); lower values smooth more
+  --kalman-r=METERS     how far off the GPS's positions typically are, for --smooth-kalman
+                        (default: 10); higher values smooth more
+  --smooth-kalman-elevation
+                        smooth the elevations too, with --smooth-kalman
+  --trim-stationary=METERS
+                        keep only the first and last points of each cluster of track points
+                        within METERS of their centroid, logged while standing still
+  --stationary-window=SECONDS
+                        how long a cluster has to last for --trim-stationary (default: 60)
+  --max-gap-interpolate=MINUTES
+                        bridge time gaps of at most MINUTES between consecutive track segments
+                        and tracks with a segment of interpolated points
+  --simplify-vw=POINTS  reduce each track segment to at most POINTS points, dropping the ones
+                        that matter least to its shape (Visvalingam-Whyatt)
+  --resample-distance=METERS
+                        replace the points of each track segment with points every METERS
+                        along it, interpolating elevation and time
+  --resample-time=SECONDS
+                        replace the points of each track segment with points every SECONDS,
+                        interpolating position and elevation
+  --min-points-per-segment=N
+                        leave out track segments with fewer than N points
+  --no-empty-segments   leave out track segments with no points; the same as
+                        --min-points-per-segment=1
+  --sample-first=N      keep only the first N points of each track segment, for a quick preview
+  --add-speed-extension
+                        add each track point's speed, in m/s, to its extensions
+  --speed-extension-name=ELEMENT
+                        name of the element for --add-speed-extension (default: speed)
+  --add-cumulative-distance
+                        add the distance in meters along its track segment to each track
+                        point's extensions, as <dist>
+  --cumulative-across-segments
+                        with --add-cumulative-distance, count the distance along the whole
+                        track instead of starting again at each segment
+  --add-bearing         add the compass bearing from each track point to the next to its
+                        extensions, as <bearing>
+  --emit-track-summaries
+                        add each track's number of points, distance, and duration to its
+                        extensions, as <summary>
+  --snap-waypoints=METERS
+                        move each waypoint onto the nearest track point, if it's within
+                        METERS
+  --elevation-profile=FILE
+                        also write an SVG chart of elevation against distance for the tracks
+  --elevation-profile-width=PIXELS
+                        width of the elevation profile chart (default: 800)
+  --elevation-profile-height=PIXELS
+                        height of the elevation profile chart (default: 300)
+  -v, --verbose         log details to standard error; same as --log-level=info
+  --log-level=debug|info|warn|error
+                        which messages to log to standard error (default: warn)
+  --log-format=text|json
+                        log as plain text (the default) or as JSON Lines
+  --no-color            don't color log messages, even on a terminal (setting NO_COLOR does
+                        the same)";
+
+/// Everything given on the command line.
+#[derive(Debug)]
+struct ParsedArgs {
+    mode: Mode,
+    /// The file given with --base, which goes before all the others.
+    base: Option<PathBuf>,
+    paths: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    /// The name of the result in the archive, when writing a zip archive.
+    zip_entry_name: Option<String>,
+    /// Whether to also join the GPX files in the directories inside zip archives.
+    recursive: bool,
+    in_place: bool,
+    summary: bool,
+    watch: Option<PathBuf>,
+    /// The state file for --checkpoint.
+    checkpoint: Option<PathBuf>,
+    resume: bool,
+    format: Format,
+    split_by_date: bool,
+    split_output: Option<String>,
+    snap_waypoints: Option<f64>,
+    center: Option<(f64, f64)>,
+    radius: Option<f64>,
+    crop_reference: Option<PathBuf>,
+    add_speed_extension: bool,
+    speed_extension_name: Option<String>,
+    trim_stationary: Option<f64>,
+    stationary_window: Option<f64>,
+    smooth_kalman: bool,
+    verify_output: bool,
+    kalman_q: Option<f64>,
+    kalman_r: Option<f64>,
+    smooth_kalman_elevation: bool,
+    /// Only keep tracks with points within this many days of now.
+    max_age: Option<u32>,
+    /// Which file, counting from 1, to take the metadata from.
+    metadata_from: Option<usize>,
+    merge_metadata: bool,
+    merge_emails: bool,
+    gpx_extensions: Option<PathBuf>,
+    track_source_comment: bool,
+    throughput: bool,
+    no_empty_segments: bool,
+    elevation_profile: Option<PathBuf>,
+    profile_size: Option<(u32, u32)>,
+    log_level: Option<log::Level>,
+    log_format: Option<log::Format>,
+    no_color: bool,
+    config: JoinConfig,
 }
 
-fn parse_args() -> Vec<PathBuf> {
-    let mut paths = vec![];
+fn parse_args() -> anyhow::Result<ParsedArgs> {
+    parse_args_from(std::env::args_os().skip(1))
+}
+
+/// Parse the arguments, not including the program name.
+fn parse_args_from(argv: impl Iterator<Item = OsString>) -> anyhow::Result<ParsedArgs> {
+    let mut args = ParsedArgs {
+        mode: Mode::Join,
+        base: None,
+        paths: vec![],
+        output: None,
+        zip_entry_name: None,
+        recursive: false,
+        in_place: false,
+        summary: false,
+        watch: None,
+        checkpoint: None,
+        resume: false,
+        format: Format::Gpx,
+        split_by_date: false,
+        split_output: None,
+        snap_waypoints: None,
+        center: None,
+        radius: None,
+        crop_reference: None,
+        add_speed_extension: false,
+        speed_extension_name: None,
+        trim_stationary: None,
+        stationary_window: None,
+        smooth_kalman: false,
+        verify_output: false,
+        kalman_q: None,
+        kalman_r: None,
+        smooth_kalman_elevation: false,
+        max_age: None,
+        metadata_from: None,
+        merge_metadata: false,
+        merge_emails: false,
+        gpx_extensions: None,
+        track_source_comment: false,
+        throughput: false,
+        no_empty_segments: false,
+        elevation_profile: None,
+        profile_size: None,
+        log_level: None,
+        log_format: None,
+        no_color: false,
+        config: JoinConfig::default(),
+    };
     let mut ignore_flags = false;
-    for arg in std::env::args_os().skip(1) {
+    let mut argv = argv.enumerate();
+    while let Some((i, arg)) = argv.next() {
         if !ignore_flags {
-            match arg.to_str() {
-                Some("-h") | Some("--help") | Some("-V") | Some("--version") => {
-                    eprintln!("gpxjoin v{} (c) 2021 {}",
-                        env!("CARGO_PKG_VERSION"),
-                        env!("CARGO_PKG_AUTHORS"));
-                    eprintln!("usage: {} <file1.gpx> [<file2.gpx>, ...] > out.gpx",
-                        std::env::args().next().unwrap());
-                    eprintln!("Concatenates GPX files by appending tracks from subsequent GPX \
-                        files after tracks from the first.\n\
-                        Writes result to standard output.");
-                    std::process::exit(1);
+            if let Some(s) = arg.to_str() {
+                // Options can take a value either as "--name=value" or as "--name value".
+                let (flag, mut inline_value) = match s.split_once('=') {
+                    Some((flag, value)) if flag.starts_with("--") => (flag, Some(value)),
+                    _ => (s, None),
+                };
+                let mut value = || -> anyhow::Result<String> {
+                    match inline_value.take() {
+                        Some(value) => Ok(value.to_owned()),
+                        None => match argv.next() {
+                            Some((_, value)) => value.into_string()
+                                .map_err(|v| anyhow!("invalid value for {}: {:?}", flag, v)),
+                            None => bail!("{} requires a value", flag),
+                        }
+                    }
+                };
+                match flag {
+                    "-h" | "--help" | "-V" | "--version" => {
+                        args.mode = Mode::Help;
+                        return Ok(args);
+                    }
+                    "--" => {
+                        ignore_flags = true;
+                        continue;
+                    }
+                    "--base" => {
+                        if args.base.is_some() {
+                            bail!("--base can only be given once");
+                        }
+                        args.base = Some(PathBuf::from(value()?));
+                    }
+                    "-o" | "--output" => args.output = Some(PathBuf::from(value()?)),
+                    "--in-place" => args.in_place = true,
+                    "--summary" => args.summary = true,
+                    "--watch" => args.watch = Some(PathBuf::from(value()?)),
+                    "--checkpoint" => args.checkpoint = Some(PathBuf::from(value()?)),
+                    "--resume" => args.resume = true,
+                    "--format" => {
+                        args.format = match value()?.as_str() {
+                            "gpx" => Format::Gpx,
+                            "nmea" => Format::Nmea,
+                            #[cfg(feature = "prost")]
+                            "protobuf" => Format::Protobuf,
+                            #[cfg(not(feature = "prost"))]
+                            "protobuf" => bail!("this build of gpxjoin doesn't support protobuf \
+                                output; it needs the \"prost\" feature"),
+                            #[cfg(feature = "fit-output")]
+                            "fit" => Format::Fit,
+                            #[cfg(not(feature = "fit-output"))]
+                            "fit" => bail!("this build of gpxjoin doesn't support FIT output; it \
+                                needs the \"fit-output\" feature"),
+                            other => bail!("unknown output format {:?}", other),
+                        };
+                    }
+                    "--emit-header-only" => args.mode = Mode::Header,
+                    "--diff" => args.mode = Mode::Diff,
+                    "--generate-example" => args.mode = Mode::Example,
+                    "--list-tracks" => args.mode = Mode::ListTracks,
+                    "--count" => args.mode = Mode::Count,
+                    "--throughput" => args.throughput = true,
+                    "--verify-output" => args.verify_output = true,
+                    "--split-by-date" => args.split_by_date = true,
+                    "--split-output" => args.split_output = Some(value()?),
+                    "--zip-entry-name" => args.zip_entry_name = Some(value()?),
+                    "--recursive" => args.recursive = true,
+                    "--since" | "--until" => {
+                        let value = value()?;
+                        let date = Timestamp::parse_date(&value)
+                            .ok_or_else(|| anyhow!("invalid {} {:?}; expected YYYY-MM-DD", flag,
+                                value))?;
+                        if flag == "--since" {
+                            args.config.since = Some(date);
+                        } else {
+                            // Up to the end of the day.
+                            args.config.until = Some(Timestamp(date.0 + 86_400_000 - 1));
+                        }
+                    }
+                    "--start-time" => {
+                        let value = value()?;
+                        let time = Timestamp::parse(&value)
+                            .ok_or_else(|| anyhow!("invalid --start-time {:?}; expected a time \
+                                like 2021-08-27T10:00:00Z", value))?;
+                        args.config.start_time = Some(time);
+                    }
+                    "--max-age" => {
+                        args.max_age = Some(value()?.parse::<u32>()
+                            .map_err(|_| anyhow!("--max-age must be a number of days"))?);
+                    }
+                    "--center" => {
+                        let value = value()?;
+                        let center = value.split_once(',')
+                            .and_then(|(lat, lon)| Some((lat.trim().parse::<f64>().ok()?,
+                                lon.trim().parse::<f64>().ok()?)))
+                            .filter(|(lat, lon)| lat.abs() <= 90. && lon.abs() <= 180.)
+                            .ok_or_else(|| anyhow!("invalid --center {:?}; expected LAT,LON",
+                                value))?;
+                        args.center = Some(center);
+                    }
+                    "--radius" => {
+                        let km = value()?.parse::<f64>()
+                            .ok()
+                            .filter(|km| km.is_finite() && *km >= 0.)
+                            .ok_or_else(|| anyhow!("--radius must be a distance in km"))?;
+                        args.radius = Some(km);
+                    }
+                    "--crop-to-reference" => {
+                        args.crop_reference = Some(PathBuf::from(value()?));
+                    }
+                    "--simplify-vw" => {
+                        let max_points = value()?.parse::<usize>()
+                            .ok()
+                            .filter(|&n| n >= 2)
+                            .ok_or_else(|| anyhow!("--simplify-vw must be a number of points, \
+                                at least 2"))?;
+                        args.config.simplify =
+                            Some(SimplifyAlgorithm::VisvalingamWhyatt { max_points });
+                    }
+                    "--interpolate-elevation" => args.config.interpolate_elevation = true,
+                    "--max-elevation-change" => {
+                        let meters = value()?.parse::<f64>()
+                            .ok()
+                            .filter(|m| m.is_finite() && *m >= 0.)
+                            .ok_or_else(|| anyhow!("--max-elevation-change must be a distance \
+                                in meters"))?;
+                        args.config.max_elevation_change = Some(meters);
+                    }
+                    "--smooth-kalman" => args.smooth_kalman = true,
+                    "--smooth-kalman-elevation" => args.smooth_kalman_elevation = true,
+                    "--kalman-q" | "--kalman-r" => {
+                        let meters = value()?.parse::<f64>()
+                            .ok()
+                            .filter(|m| m.is_finite() && *m > 0.)
+                            .ok_or_else(|| anyhow!("{} must be a distance in meters", flag))?;
+                        if flag == "--kalman-q" {
+                            args.kalman_q = Some(meters);
+                        } else {
+                            args.kalman_r = Some(meters);
+                        }
+                    }
+                    "--trim-stationary" => {
+                        let meters = value()?.parse::<f64>()
+                            .ok()
+                            .filter(|m| m.is_finite() && *m > 0.)
+                            .ok_or_else(|| anyhow!("--trim-stationary must be a distance in \
+                                meters"))?;
+                        args.trim_stationary = Some(meters);
+                    }
+                    "--stationary-window" => {
+                        let seconds = value()?.parse::<f64>()
+                            .ok()
+                            .filter(|s| s.is_finite() && *s >= 0.)
+                            .ok_or_else(|| anyhow!("--stationary-window must be a number of \
+                                seconds"))?;
+                        args.stationary_window = Some(seconds);
+                    }
+                    "--max-gap-interpolate" => {
+                        let minutes = value()?.parse::<f64>()
+                            .ok()
+                            .filter(|m| m.is_finite() && *m > 0.)
+                            .ok_or_else(|| anyhow!("--max-gap-interpolate must be a number of \
+                                minutes"))?;
+                        args.config.max_gap_interpolate = Some(minutes * 60.);
+                    }
+                    "--resample-distance" => {
+                        let meters = value()?.parse::<f64>()
+                            .ok()
+                            .filter(|m| m.is_finite() && *m > 0.)
+                            .ok_or_else(|| anyhow!("--resample-distance must be a distance in \
+                                meters"))?;
+                        if args.config.resample.is_some() {
+                            bail!("--resample-distance and --resample-time are mutually exclusive");
+                        }
+                        args.config.resample = Some(Resample::Distance(meters));
+                    }
+                    "--resample-time" => {
+                        let seconds = value()?.parse::<f64>()
+                            .ok()
+                            .filter(|s| s.is_finite() && *s > 0.)
+                            .ok_or_else(|| anyhow!("--resample-time must be a number of \
+                                seconds"))?;
+                        if args.config.resample.is_some() {
+                            bail!("--resample-distance and --resample-time are mutually exclusive");
+                        }
+                        args.config.resample = Some(Resample::Time(seconds));
+                    }
+                    "--min-points-per-segment" => {
+                        let min_points = value()?.parse::<usize>()
+                            .map_err(|_| anyhow!("--min-points-per-segment must be a number of \
+                                points"))?;
+                        args.config.min_points_per_segment = Some(min_points);
+                    }
+                    "--no-empty-segments" => args.no_empty_segments = true,
+                    "--sample-first" => {
+                        let n = value()?.parse::<usize>()
+                            .map_err(|_| anyhow!("--sample-first must be a number of \
+                                points"))?;
+                        args.config.sample_first = Some(n);
+                    }
+                    "--add-speed-extension" => args.add_speed_extension = true,
+                    "--speed-extension-name" => {
+                        let name = value()?;
+                        if !is_element_name(&name) {
+                            bail!("invalid element name {:?}", name);
+                        }
+                        args.speed_extension_name = Some(name);
+                    }
+                    "--add-cumulative-distance" => args.config.distance_extension = true,
+                    "--cumulative-across-segments" => {
+                        args.config.distance_across_segments = true;
+                    }
+                    "--add-bearing" => args.config.bearing_extension = true,
+                    "--emit-track-summaries" => args.config.track_summaries = true,
+                    "--snap-waypoints" => {
+                        let meters = value()?.parse::<f64>()
+                            .ok()
+                            .filter(|m| m.is_finite() && *m >= 0.)
+                            .ok_or_else(|| anyhow!("--snap-waypoints must be a distance in \
+                                meters"))?;
+                        args.snap_waypoints = Some(meters);
+                    }
+                    "--elevation-profile" => args.elevation_profile = Some(PathBuf::from(value()?)),
+                    "--elevation-profile-width" | "--elevation-profile-height" => {
+                        let pixels = value()?.parse::<u32>()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .ok_or_else(|| anyhow!("{} must be a positive number", flag))?;
+                        let size = args.profile_size.get_or_insert((800, 300));
+                        if flag == "--elevation-profile-width" {
+                            size.0 = pixels;
+                        } else {
+                            size.1 = pixels;
+                        }
+                    }
+                    "--join-strategy" => {
+                        args.config.strategy = match value()?.as_str() {
+                            "append" => JoinStrategy::Append,
+                            "interleave" => JoinStrategy::Interleave,
+                            "replace" => JoinStrategy::Replace,
+                            "zip" => JoinStrategy::Zip,
+                            other => bail!("unknown join strategy {:?}", other),
+                        };
+                    }
+                    "--zip-tracks" => args.config.strategy = JoinStrategy::Zip,
+                    "--reverse-tracks" => args.config.reverse_tracks = true,
+                    "--wrap-in-trk" => args.config.wrap_in_trk = true,
+                    "--flatten-tracks" => args.config.flatten_tracks = true,
+                    "--reverse-points" => args.config.reverse_points = true,
+                    "--merge-strategy" => {
+                        args.config.merge_strategy = Some(match value()?.as_str() {
+                            "first-wins" => MergeStrategy::FirstWins,
+                            "last-wins" => MergeStrategy::LastWins,
+                            "newest" => MergeStrategy::Newest,
+                            other => bail!("unknown merge strategy {:?}", other),
+                        });
+                    }
+                    "--merge-segments" => args.config.merge_segments = true,
+                    "--ignore-namespace" => args.config.ignore_namespace = true,
+                    "--first-name-wins" | "--last-name-wins" => {
+                        if args.config.duplicate_names.is_some() {
+                            bail!("--first-name-wins and --last-name-wins are mutually exclusive");
+                        }
+                        args.config.duplicate_names = Some(if flag == "--first-name-wins" {
+                            DuplicateNames::FirstWins
+                        } else {
+                            DuplicateNames::LastWins
+                        });
+                    }
+                    "--truncate-track-names" => {
+                        let max_chars = value()?.parse::<usize>()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .ok_or_else(|| anyhow!("--truncate-track-names must be a positive \
+                                number of characters"))?;
+                        args.config.truncate_track_names = Some(max_chars);
+                    }
+                    "--truncate-ellipsis" => args.config.truncate_ellipsis = true,
+                    "--case-sensitive-names" => args.config.case_sensitive_names = true,
+                    "--fix" => args.config.fix = true,
+                    "--enforce-coordinate-order" => args.config.enforce_coordinate_order = true,
+                    "--fix-trk-order" => args.config.fix_trk_order = true,
+                    "--verify-coordinates" => args.config.verify_coordinates = true,
+                    "--require-time" => args.config.require_time = true,
+                    "--no-metadata" => args.config.no_metadata = true,
+                    "--no-trk" => args.config.no_tracks = true,
+                    "--no-wpt" => args.config.no_waypoints = true,
+                    "--no-rte" => args.config.no_routes = true,
+                    "--tracks-only" => {
+                        args.config.no_waypoints = true;
+                        args.config.no_routes = true;
+                    }
+                    "--merge-metadata" => args.merge_metadata = true,
+                    "--merge-emails" => args.merge_emails = true,
+                    "--metadata-from" => {
+                        let n = value()?.parse::<usize>()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .ok_or_else(|| anyhow!("--metadata-from must be a file number, \
+                                counting from 1"))?;
+                        args.metadata_from = Some(n);
+                    }
+                    "--inject-gpx-extensions" => {
+                        args.gpx_extensions = Some(PathBuf::from(value()?));
+                    }
+                    "--metadata-keywords" => args.config.metadata_keywords = Some(value()?),
+                    "--metadata-copyright" => {
+                        let copyright = value()?.parse()
+                            .context("--metadata-copyright must be YEAR,AUTHOR[,LICENSE]")?;
+                        args.config.metadata_copyright = Some(copyright);
+                    }
+                    "--comment" => args.config.comments.push(value()?),
+                    "--track-source-comment" => args.track_source_comment = true,
+                    "--gpx-version" => {
+                        let version = value()?;
+                        if version != "1.0" && version != "1.1" {
+                            bail!("--gpx-version must be 1.0 or 1.1, not {:?}", version);
+                        }
+                        args.config.gpx_version = Some(version);
+                    }
+                    "--check-gpx-version" => args.config.check_gpx_version = true,
+                    "--encoding" => args.config.encoding = Some(value()?),
+                    "--no-xml-declaration" => args.config.suppress_xml_declaration = true,
+                    "--strip-cdata" => args.config.strip_cdata = true,
+                    "--xml-standalone" => {
+                        args.config.standalone = Some(match value()?.as_str() {
+                            "yes" => true,
+                            "no" => false,
+                            other => bail!("--xml-standalone must be yes or no, not {:?}", other),
+                        });
+                    }
+                    "--schema-location" => args.config.schema_location = true,
+                    "--normalize-creator" => args.config.normalize_creator = true,
+                    "--cmt" => args.config.track_comment = Some(value()?),
+                    "--strava-to-garmin" => args.config.strava_to_garmin = true,
+                    "--wpt-as-rtept" | "--rtept-as-wpt" => {
+                        if args.config.point_conversion.is_some() {
+                            bail!("--wpt-as-rtept and --rtept-as-wpt are mutually exclusive");
+                        }
+                        args.config.point_conversion = Some(if flag == "--wpt-as-rtept" {
+                            PointConversion::WptToRtept
+                        } else {
+                            PointConversion::RteptToWpt
+                        });
+                    }
+                    "-v" | "--verbose" => args.log_level = Some(log::Level::Info),
+                    "--log-level" => args.log_level = Some(value()?.parse()?),
+                    "--log-format" => args.log_format = Some(value()?.parse()?),
+                    "--no-color" => args.no_color = true,
+                    "check" if i == 0 => args.mode = Mode::Check,
+                    "index" if i == 0 => args.mode = Mode::Index,
+                    _ if flag.starts_with('-') => {
+                        bail!("unrecognized option {:?} (use -- before file names starting with \
+                            '-')", flag);
+                    }
+                    _ => {
+                        args.paths.push(PathBuf::from(arg));
+                        continue;
+                    }
                 }
-                Some("--") => {
-                    ignore_flags = true;
-                    continue;
+                if inline_value.is_some() {
+                    bail!("{} doesn't take a value", flag);
                 }
-                _ => ()
+                continue;
             }
         }
-        paths.push(PathBuf::from(arg));
+        args.paths.push(PathBuf::from(arg));
     }
-    paths
+    match (args.center, args.radius) {
+        (Some(_), Some(_)) if args.crop_reference.is_some() => {
+            bail!("--center and --radius can't be used with --crop-to-reference");
+        }
+        (Some((lat, lon)), Some(km)) => {
+            args.config.area = Some(Area::Circle { lat, lon, radius: km * 1000. });
+        }
+        (None, None) => (),
+        _ => bail!("--center and --radius must be used together"),
+    }
+    match (args.add_speed_extension, args.speed_extension_name.take()) {
+        (true, name) => {
+            args.config.speed_extension = Some(name.unwrap_or_else(|| "speed".to_owned()));
+        }
+        (false, Some(_)) => bail!("--speed-extension-name requires --add-speed-extension"),
+        (false, None) => (),
+    }
+    match (args.trim_stationary, args.stationary_window) {
+        (Some(radius), window) => {
+            let window = window.unwrap_or(TrimStationary::DEFAULT_WINDOW);
+            args.config.trim_stationary = Some(TrimStationary { radius, window });
+        }
+        (None, Some(_)) => bail!("--stationary-window requires --trim-stationary"),
+        (None, None) => (),
+    }
+    if args.smooth_kalman {
+        args.config.smooth_kalman = Some(Kalman {
+            process_noise: args.kalman_q.unwrap_or(Kalman::DEFAULT_PROCESS_NOISE),
+            measurement_noise: args.kalman_r.unwrap_or(Kalman::DEFAULT_MEASUREMENT_NOISE),
+            elevation: args.smooth_kalman_elevation,
+        });
+    } else if args.kalman_q.is_some() || args.kalman_r.is_some() || args.smooth_kalman_elevation {
+        bail!("--kalman-q, --kalman-r, and --smooth-kalman-elevation require --smooth-kalman");
+    }
+    if args.config.truncate_ellipsis && args.config.truncate_track_names.is_none() {
+        bail!("--truncate-ellipsis requires --truncate-track-names");
+    }
+    if args.config.distance_across_segments && !args.config.distance_extension {
+        bail!("--cumulative-across-segments requires --add-cumulative-distance");
+    }
+    if args.config.no_metadata && args.metadata_from.is_some() {
+        bail!("--no-metadata and --metadata-from are mutually exclusive");
+    }
+    if args.merge_metadata && (args.config.no_metadata || args.metadata_from.is_some()) {
+        bail!("--merge-metadata can't be used with --no-metadata or --metadata-from");
+    }
+    let sets_metadata =
+        args.config.metadata_keywords.is_some() || args.config.metadata_copyright.is_some();
+    if args.config.no_metadata && sets_metadata {
+        bail!("--no-metadata can't be used with --metadata-keywords or --metadata-copyright");
+    }
+    if args.merge_emails && !args.merge_metadata {
+        bail!("--merge-emails requires --merge-metadata");
+    }
+    if args.checkpoint.is_some() && (args.watch.is_some() || args.in_place) {
+        bail!("--checkpoint can't be used with --watch or --in-place");
+    }
+    // Anything but a joined GPX file written back would destroy the input.
+    if args.in_place && args.mode != Mode::Join {
+        bail!("--in-place can't be used with check, index, --diff, --emit-header-only, \
+            --generate-example, --list-tracks, or --count");
+    }
+    if args.in_place && args.format != Format::Gpx {
+        bail!("--in-place only writes back GPX, so --format has to be gpx");
+    }
+    if args.resume && args.checkpoint.is_none() {
+        bail!("--resume requires --checkpoint");
+    }
+    if args.no_empty_segments {
+        let min_points = args.config.min_points_per_segment.get_or_insert(1);
+        *min_points = (*min_points).max(1);
+    }
+    Ok(args)
 }
 
-pub fn join_gpx<R: BufRead, W: Write>(sources: &mut [R], dest: W) -> anyhow::Result<()> {
-    let mut first = None;
-    let mut buf = vec![];
-    let mut writer = Writer::new(dest);
-    for source in sources {
-        let mut path = vec![];
-        let mut r = Reader::from_reader(source);
-        loop {
-            let evt = r.read_event(&mut buf)?;
-            match evt {
-                Event::Eof => break,
-                Event::Start(ref start) => {
-                    path.push(start.name().to_owned());
-                }
-                Event::End(_) if first.is_none() && path == [b"gpx"] => {
-                    // Done with tracks, save the first file reader at this point and move on
-                    // to the next file.
-                    // Note we can only do this when we're sure there won't be additional track
-                    // elements, hence why the check is when the path is just "gpx".
-                    first = Some((r, evt.into_owned()));
-                    break;
-                }
-                _ => (),
+/// Whether a string will do as an XML element name, possibly with a namespace prefix.
+fn is_element_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || "_-.:".contains(c))
+}
+
+/// The earliest time a track can have a point at to be kept with `--max-age=DAYS`.
+fn max_age_cutoff(now: SystemTime, days: u32) -> Timestamp {
+    let now = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    Timestamp(now.as_millis() as i64 - i64::from(days) * 86_400_000)
+}
+
+fn print_help() {
+    eprintln!("gpxjoin v{} (c) 2021 {}",
+        env!("CARGO_PKG_VERSION"),
+        env!("CARGO_PKG_AUTHORS"));
+    eprintln!("usage: {} [options] <file1.gpx> [<file2.gpx>, ...] > out.gpx",
+        std::env::args().next().unwrap());
+    eprintln!("       {} check <file1.gpx> [<file2.gpx>, ...]",
+        std::env::args().next().unwrap());
+    eprintln!("       {} index <file.gpx> > file.gpxi",
+        std::env::args().next().unwrap());
+    eprintln!("Concatenates GPX files by appending tracks from subsequent GPX \
+        files after tracks from the first.\n\
+        Writes result to standard output.\n\
+        The 'check' command validates GPX files and reports any problems \
+        found.\n\
+        The 'index' command writes an index of the times in a GPX file, which --since uses to \
+        skip files that are all too early.");
+    eprintln!("{}", OPTIONS);
+}
+
+fn is_zip(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+fn open(path: &Path) -> anyhow::Result<Box<dyn BufRead>> {
+    if let Some((archive, name)) = zip_entry(path) {
+        let data = File::open(archive)
+            .with_context(|| format!("failed to open {:?}", archive))
+            .and_then(|file| ZipArchive::new(BufReader::new(file)))
+            .and_then(|mut zip| zip.read(&name))
+            .with_context(|| format!("failed to read {:?} from {:?}", name, archive))?;
+        return Ok(Box::new(Cursor::new(data)));
+    }
+    if is_zip(path) {
+        bail!("can't read {:?}: zip archives can only be given as files to join", path);
+    }
+    Ok(Box::new(BufReader::new(
+        File::open(path)
+            .with_context(|| format!("failed to open {:?}", path))?)))
+}
+
+/// Replace each zip archive in the paths with the GPX files in it, in name order, as paths
+/// inside the archive like `tracks.zip/a.gpx`. Only the files at the top of the archive are
+/// included, unless `recursive` is set. Anything else in it is left out.
+fn expand_zips(paths: Vec<PathBuf>, recursive: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let mut expanded = vec![];
+    for path in paths {
+        if !is_zip(&path) {
+            expanded.push(path);
+            continue;
+        }
+        let zip = File::open(&path)
+            .with_context(|| format!("failed to open {:?}", path))
+            .and_then(|file| ZipArchive::new(BufReader::new(file)))
+            .with_context(|| format!("failed to read {:?}", path))?;
+        let mut names = zip.names()
+            .filter(|name| recursive || !name.contains('/'))
+            .filter(|name| Path::new(name).extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("gpx")))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        if names.is_empty() {
+            info!("no GPX files in {:?}", path);
+        }
+        expanded.extend(names.into_iter().map(|name| path.join(name)));
+    }
+    Ok(expanded)
+}
+
+/// The zip archive a path is inside of, and the name of the entry it is in there, if it's like
+/// the paths from [`expand_zips`].
+fn zip_entry(path: &Path) -> Option<(&Path, String)> {
+    let archive = path.ancestors().skip(1).find(|dir| is_zip(dir) && dir.is_file())?;
+    let name = path.strip_prefix(archive).ok()?.iter()
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    Some((archive, name))
+}
+
+fn check(paths: &[PathBuf]) -> anyhow::Result<()> {
+    let mut num_errors = 0;
+    for path in paths {
+        for err in validate_gpx(open(path)?) {
+            println!("{}:{}", path.display(), err);
+            num_errors += 1;
+        }
+    }
+    if num_errors != 0 {
+        bail!("found {} problem(s)", num_errors);
+    }
+    Ok(())
+}
+
+/// The up-to-date index of a file, if it has one next to it. The file is read from the start
+/// otherwise.
+fn read_index(path: &Path) -> Option<Index> {
+    let index = match File::open(path.with_extension("gpxi")).map(BufReader::new) {
+        Ok(file) => Index::read(file),
+        Err(_) => return None,
+    };
+    let len = std::fs::metadata(path).map(|m| m.len()).ok();
+    match index {
+        Ok(index) if Some(index.file_len) == len => Some(index),
+        Ok(_) => {
+            warn!("ignoring the index of {:?}, which is out of date", path);
+            None
+        }
+        Err(e) => {
+            warn!("ignoring the index of {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Open a file to join, past the track points at the start of its first segment that its index
+/// says are all before `start`, if it has an index.
+fn open_from(path: &Path, index: Option<&Index>, start: Option<Timestamp>)
+    -> anyhow::Result<Box<dyn BufRead>>
+{
+    let (index, start) = match (index, start) {
+        (Some(index), Some(start)) => (index, start),
+        _ => return open(path),
+    };
+    let (first, seek) = match (index.first_segment, index.seek_position(start)) {
+        (Some((first, _)), Some(seek)) if seek > first => (first, seek),
+        _ => return open(path),
+    };
+    info!("skipping {} bytes of track points in {:?}, which its index says are before {}",
+        seek - first, path, start);
+    let file = File::open(path).with_context(|| format!("failed to open {:?}", path))?;
+    Ok(Box::new(BufReader::new(SkipReader { file, pos: 0, skip_from: first, skip_to: seek })))
+}
+
+/// Reads a file, leaving out the part of it from one offset up to another.
+struct SkipReader<R> {
+    file: R,
+    pos: u64,
+    skip_from: u64,
+    skip_to: u64,
+}
+
+impl<R: io::Read + Seek> io::Read for SkipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.skip_from {
+            self.pos = self.file.seek(SeekFrom::Start(self.skip_to))?;
+        }
+        let len = match self.skip_from.checked_sub(self.pos) {
+            Some(before_skip) => before_skip.min(buf.len() as u64) as usize,
+            None => buf.len(),
+        };
+        let n = self.file.read(&mut buf[.. len])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+/// Print how the tracks in each file compare to the first file, to standard error.
+fn diff<R: BufRead>(paths: &[PathBuf], files: &mut [R], config: &JoinConfig)
+    -> anyhow::Result<()>
+{
+    for d in diff_tracks(files, config)? {
+        let path = paths[d.source].display().to_string();
+        match log::format() {
+            log::Format::Text => {
+                let time = |t: Option<Timestamp>| t.map(|t| t.to_string())
+                    .unwrap_or_else(|| "?".to_owned());
+                let name = d.name.map(|name| format!("{:?}", name))
+                    .unwrap_or_else(|| "(unnamed)".to_owned());
+                eprintln!("{}: {} track {} ({} to {})", path, d.change, name, time(d.start),
+                    time(d.end));
             }
-            // If this is the first file, write everything, otherwise only write events if our path
-            // begins with a track element.
-            if first.is_none() || path.starts_with(&[b"gpx", b"trk"]) {
-                writer.write_event(&evt)?;
+            log::Format::Json => {
+                let string = |s: Option<String>| match s {
+                    Some(s) => format!("\"{}\"", log::json_escape(&s)),
+                    None => "null".to_owned(),
+                };
+                eprintln!(r#"{{"file":{},"track":{},"change":"{}","start":{},"end":{}}}"#,
+                    string(Some(path)), string(d.name), d.change,
+                    string(d.start.map(|t| t.to_string())), string(d.end.map(|t| t.to_string())));
             }
-            if let Event::End(ref end) = evt {
-                match path.pop() {
-                    None => bail!("unexpected </{:?}> tag when path is empty", end.name()),
-                    Some(popped) if popped != end.name() => {
-                        bail!("start/end tag mismatch: expected </{:?}>, saw </{:?}>", popped, end.name());
-                    }
-                    _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    log::set_color(use_color());
+    let result = run();
+    if let Err(ref e) = result {
+        // Log the error the same way as everything else, if that's any different from how it
+        // would be printed otherwise.
+        if log::format() == log::Format::Json || log::color() {
+            error!("{:#}", e);
+            std::process::exit(1);
+        }
+    }
+    result
+}
+
+/// Whether to color messages on standard error: only if it's a terminal, and NO_COLOR
+/// (<https://no-color.org/>) isn't set.
+fn use_color() -> bool {
+    io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+}
+
+fn run() -> anyhow::Result<()> {
+    let mut args = parse_args()?;
+    if args.mode == Mode::Help {
+        print_help();
+        std::process::exit(1);
+    }
+    if let Some(level) = args.log_level {
+        log::set_level(level);
+    }
+    if let Some(format) = args.log_format {
+        log::set_format(format);
+    }
+    if args.no_color {
+        log::set_color(false);
+    }
+    if let Some(ref path) = args.crop_reference {
+        let area = Area::bounding_box_of(open(path)?)
+            .with_context(|| format!("can't crop to the bounding box of {:?}", path))?;
+        info!("cropping to {:?}", area);
+        args.config.area = Some(area);
+    }
+    if let Some(ref path) = args.gpx_extensions {
+        let extensions = Extensions::read(open(path)?)
+            .with_context(|| format!("can't read extensions from {:?}", path))?;
+        args.config.gpx_extensions = Some(extensions);
+    }
+    if args.zip_entry_name.is_some() && !args.output.as_deref().is_some_and(is_zip) {
+        bail!("--zip-entry-name requires -o with a .zip file");
+    }
+    if let Some(ref dir) = args.watch {
+        return watch(&args, dir);
+    }
+    if let Mode::Example = args.mode {
+        return Ok(io::stdout().write_all(EXAMPLE_GPX.as_bytes())?);
+    }
+    let paths = args.base.iter().chain(&args.paths).cloned().collect::<Vec<_>>();
+    let paths = expand_zips(paths, args.recursive)?;
+    if paths.is_empty() {
+        bail!("need at least one source file");
+    }
+    if let Mode::Check = args.mode {
+        return check(&paths);
+    }
+    if let Mode::Index = args.mode {
+        if paths.len() != 1 {
+            bail!("index takes a single file");
+        }
+        let index = Index::build(open(&paths[0])?)
+            .with_context(|| format!("failed to index {:?}", paths[0]))?;
+        let mut out = BufWriter::new(io::stdout());
+        index.write(&mut out)?;
+        return Ok(out.flush()?);
+    }
+    if let Mode::ListTracks = args.mode {
+        for path in &paths {
+            let tracks = TrackSummary::all(open(path)?)
+                .with_context(|| format!("failed to read {:?}", path))?;
+            for (i, track) in tracks.iter().enumerate() {
+                println!("{}:{} {}", path.display(), i + 1, track);
+            }
+        }
+        return Ok(());
+    }
+    if args.in_place {
+        if paths.len() != 1 {
+            bail!("--in-place only works with a single file");
+        }
+        if zip_entry(&paths[0]).is_some() {
+            bail!("--in-place can't write back into a zip archive");
+        }
+        if args.output.is_some() || args.split_by_date {
+            bail!("--in-place can't be used with -o or --split-by-date");
+        }
+        // This is written safely: the file is only replaced once the result is complete.
+        args.output = Some(paths[0].clone());
+    }
+    if args.summary && args.output.is_none() && !args.split_by_date {
+        bail!("--summary prints to standard output, so the result has to go elsewhere, with -o, \
+            --in-place, or --split-by-date");
+    }
+    // Summarize the inputs before anything is written, in case one of them gets overwritten.
+    let mut summaries = vec![];
+    if args.summary {
+        for path in &paths {
+            let summary = Summary::of(open(path)?)
+                .with_context(|| format!("failed to read {:?}", path))?;
+            summaries.push(format!("{}: {}", path.display(), summary));
+        }
+    }
+    join_files(&args, &paths)?;
+    for summary in &summaries {
+        println!("{}", summary);
+    }
+    Ok(())
+}
+
+/// Join the files and write the result wherever the arguments say.
+fn join_files(args: &ParsedArgs, paths: &[PathBuf]) -> anyhow::Result<()> {
+    let mut files = vec![];
+    let mut names = vec![];
+    let mut skipped = 0;
+    for (i, path) in paths.iter().enumerate() {
+        let uses_index = args.config.since.is_some() || args.config.start_time.is_some();
+        // The first file is always read from the start, for everything besides its tracks.
+        let index = if i > 0 && args.mode == Mode::Join && uses_index {
+            read_index(path)
+        } else {
+            None
+        };
+        if let (Some(index), Some(since)) = (&index, args.config.since) {
+            if index.all_before(since) {
+                info!("skipping {:?}, which its index says is all before {}", path, since);
+                skipped += 1;
+                continue;
+            }
+        }
+        let file = open_from(path, index.as_ref(), args.config.start_time)?;
+        files.push(ThroughputReader::new(file, path, args.throughput));
+        names.push(path.display().to_string());
+    }
+    let mut config = args.config.clone();
+    if args.track_source_comment {
+        config.track_source_comments = Some(names);
+    }
+    if let Some(n) = args.metadata_from.filter(|&n| n > 1) {
+        let path = paths.get(n - 1)
+            .ok_or_else(|| anyhow!("--metadata-from={} but there are only {} files", n,
+                paths.len()))?;
+        config.metadata = Metadata::read(open(path)?)
+            .with_context(|| format!("failed to read metadata from {:?}", path))?;
+        // If that file has no metadata, neither does the result.
+        config.no_metadata = config.metadata.is_none();
+    }
+    if args.merge_metadata {
+        let mut all = vec![];
+        for path in paths {
+            let metadata = Metadata::read(open(path)?)
+                .with_context(|| format!("failed to read metadata from {:?}", path))?;
+            all.extend(metadata);
+        }
+        config.metadata = Metadata::merge(all, args.merge_emails);
+    }
+    if let Some(days) = args.max_age {
+        // Worked out for each join, since --watch can keep running for days.
+        config.since = config.since.max(Some(max_age_cutoff(SystemTime::now(), days)));
+    }
+    let config = &config;
+    if let Mode::Diff = args.mode {
+        if files.len() < 2 {
+            bail!("--diff needs a base file and at least one more to compare it with");
+        }
+        return diff(paths, &mut files, config);
+    }
+    if let Mode::Count = args.mode {
+        if args.output.is_some() {
+            bail!("--count doesn't write the result, so it can't be used with -o or --in-place");
+        }
+        let stats = join_gpx_with_stats(&mut files, io::sink(), config)?;
+        println!("{}", stats.tracks);
+        return Ok(());
+    }
+    if let Mode::Header = args.mode {
+        if files.len() != 1 {
+            bail!("--emit-header-only takes a single file");
+        }
+        let file = files.pop().unwrap();
+        return match args.output {
+            Some(ref path) => write_output(path, args, |out| write_header(file, out)),
+            None => write_header(file, io::stdout()),
+        };
+    }
+    let split_template = if args.split_by_date {
+        if args.output.as_deref().is_some_and(|path| !is_zip(path)) {
+            bail!("-o can't be used with --split-by-date, except to write the files into a zip \
+                archive");
+        }
+        if args.zip_entry_name.is_some() {
+            bail!("--zip-entry-name can't be used with --split-by-date, which names the files \
+                with --split-output");
+        }
+        if args.format != Format::Gpx {
+            bail!("--split-by-date only writes GPX");
+        }
+        match args.split_output {
+            Some(ref template) if template.contains("{date}") => Some(template),
+            Some(_) => bail!("--split-output template must contain \"{date}\""),
+            None => bail!("--split-by-date requires --split-output"),
+        }
+    } else if args.split_output.is_some() {
+        bail!("--split-output requires --split-by-date");
+    } else {
+        None
+    };
+    if args.verify_output {
+        if args.output.is_none() {
+            bail!("--verify-output requires -o");
+        }
+        if split_template.is_some() || args.format != Format::Gpx
+            || args.output.as_deref().is_some_and(is_zip)
+        {
+            bail!("--verify-output only checks GPX output written to a single file, not a zip \
+                archive");
+        }
+    }
+    if args.elevation_profile.is_none() && args.profile_size.is_some() {
+        bail!("--elevation-profile-width and --elevation-profile-height require \
+            --elevation-profile");
+    }
+
+    // The joined result is needed in memory to snap, split, convert, or draw a profile of it, or
+    // so that none of it goes to standard output if --check-gpx-version fails partway; otherwise
+    // it's written out as it's made.
+    let in_memory = split_template.is_some() || args.elevation_profile.is_some()
+        || args.format != Format::Gpx || args.snap_waypoints.is_some()
+        || (args.config.check_gpx_version && args.output.is_none());
+    if let Some(ref state) = args.checkpoint {
+        if in_memory {
+            bail!("--checkpoint can't be used with --split-by-date, --elevation-profile, \
+                --snap-waypoints, or formats other than GPX");
+        }
+        let output = args.output.as_ref().ok_or_else(|| anyhow!("--checkpoint requires -o"))?;
+        if is_zip(output) {
+            bail!("--checkpoint can't write a zip archive");
+        }
+        let stats = join_with_checkpoints(&mut files, output, state, args.resume, config)?;
+        if args.verify_output {
+            verify_output(output)?;
+        }
+        log_report(&stats, skipped);
+        return Ok(());
+    }
+    let mut stats = None;
+    let joined = if in_memory {
+        let mut joined = vec![];
+        stats = Some(join_gpx_with_stats(&mut files, &mut joined, config)?);
+        if let Some(meters) = args.snap_waypoints {
+            let mut snapped = vec![];
+            snap_waypoints(&joined[..], &mut snapped, meters)?;
+            joined = snapped;
+        }
+        Some(joined)
+    } else {
+        None
+    };
+
+    if let Some(ref path) = args.elevation_profile {
+        let (width, height) = args.profile_size.unwrap_or((800, 300));
+        let svg = elevation_profile(joined.as_deref().unwrap(), width, height)?;
+        write_atomically(path, |out| Ok(out.write_all(svg.as_bytes())?))?;
+        info!("{} {:?}", log::paint(log::Level::Info, "wrote"), path);
+    }
+
+    if let (Some(template), Some(archive)) = (split_template, args.output.as_ref()) {
+        let days = split_by_date(joined.as_deref().unwrap())?;
+        write_atomically(archive, |out| {
+            let mut zip = ZipWriter::new(out);
+            for (date, doc) in days {
+                let name = template.replace("{date}", &date);
+                zip.start_file(&name)?;
+                zip.write_all(&doc)?;
+                info!("{} {:?} in {:?}", log::paint(log::Level::Info, "wrote"), name, archive);
+            }
+            zip.finish()?;
+            Ok(())
+        })?;
+    } else if let Some(template) = split_template {
+        for (date, doc) in split_by_date(joined.as_deref().unwrap())? {
+            let path = PathBuf::from(template.replace("{date}", &date));
+            write_atomically(&path, |out| Ok(out.write_all(&doc)?))?;
+            info!("{} {:?}", log::paint(log::Level::Info, "wrote"), path);
+        }
+    } else {
+        let mut write = |out: &mut dyn Write| match joined {
+            Some(ref joined) if args.format == Format::Nmea => write_nmea(&joined[..], out),
+            #[cfg(feature = "prost")]
+            Some(ref joined) if args.format == Format::Protobuf => {
+                gpxjoin::write_protobuf(&joined[..], out)
+            }
+            #[cfg(feature = "fit-output")]
+            Some(ref joined) if args.format == Format::Fit => gpxjoin::write_fit(&joined[..], out),
+            Some(ref joined) => Ok(out.write_all(joined)?),
+            None => join_gpx_with_stats(&mut files, out, config).map(|s| stats = Some(s)),
+        };
+        match args.output {
+            Some(ref path) => {
+                write_output(path, args, |out| write(out))?;
+                if args.verify_output {
+                    verify_output(path)?;
                 }
             }
-            buf.clear();
+            None => write(&mut io::stdout())?,
         }
     }
+    if let Some(ref stats) = stats {
+        log_report(stats, skipped);
+    }
+    Ok(())
+}
 
-    // Finish writing out the first file.
-    let (mut first, stashed_evt) = first.unwrap();
-    writer.write_event(stashed_evt)?;
-    loop {
-        let evt = first.read_event(&mut buf)?;
-        if matches!(evt, Event::Eof) {
-            break;
+/// At the info level, log totals for the joined result, set apart from the messages about each
+/// file before them.
+fn log_report(stats: &JoinStats, skipped: usize) {
+    if log::enabled(log::Level::Info) {
+        for line in report(stats, skipped) {
+            info!("{}", line);
         }
-        writer.write_event(evt)?;
-        buf.clear();
     }
+}
+
+fn report(stats: &JoinStats, skipped: usize) -> Vec<String> {
+    let mut lines = vec![
+        "---".to_owned(),
+        format!("files: {} joined, {} skipped", stats.files.len(), skipped),
+        format!("tracks: {}, {} pts, {:.1}km", stats.tracks, stats.points(),
+            stats.distance_m() / 1000.),
+    ];
+    if let Some(b) = stats.bounds() {
+        lines.push(format!("bounds: lat {} to {}, lon {} to {}", b.min_lat, b.max_lat, b.min_lon,
+            b.max_lon));
+    }
+    if let Some((start, end)) = stats.time_range() {
+        lines.push(format!("time: {} to {}", start, end));
+    }
+    lines
+}
 
+/// Read the output back to check that it's well-formed, and if it isn't, move it aside with a
+/// `.corrupt` suffix so it won't be mistaken for good output.
+fn verify_output(path: &Path) -> anyhow::Result<()> {
+    let result = File::open(path)
+        .with_context(|| format!("failed to open {:?}", path))
+        .and_then(|file| check_well_formed(BufReader::new(file)));
+    if let Err(e) = result {
+        let mut corrupt = path.as_os_str().to_owned();
+        corrupt.push(".corrupt");
+        std::fs::rename(path, &corrupt)
+            .with_context(|| format!("failed to rename {:?} to {:?}", path, corrupt))?;
+        return Err(e.context(format!("the output is not valid GPX; moved it to {:?}", corrupt)));
+    }
+    info!("verified {:?}", path);
     Ok(())
 }
 
-fn main() -> anyhow::Result<()> {
+/// Join the files straight into the output, recording in the state file how far it's got after
+/// each one, so that the join can be picked up from there with --resume if it's interrupted. The
+/// state file is removed once the join is done.
+fn join_with_checkpoints<R: BufRead>(
+    files: &mut [R],
+    output: &Path,
+    state: &Path,
+    resume: bool,
+    config: &JoinConfig,
+) -> anyhow::Result<JoinStats> {
+    let checkpoint = if resume {
+        let text = std::fs::read_to_string(state)
+            .with_context(|| format!("failed to read {:?}", state))?;
+        Some(text.parse::<Checkpoint>().with_context(|| format!("failed to read {:?}", state))?)
+    } else {
+        None
+    };
+    let file = match checkpoint {
+        Some(checkpoint) => {
+            let mut file = OpenOptions::new().write(true).open(output)
+                .with_context(|| format!("failed to open {:?}", output))?;
+            if file.metadata()?.len() < checkpoint.bytes_written {
+                bail!("{:?} is shorter than {:?} says it should be", output, state);
+            }
+            file.set_len(checkpoint.bytes_written)?;
+            file.seek(SeekFrom::End(0))?;
+            info!("resuming after {} of {} files", checkpoint.sources_done, files.len());
+            file
+        }
+        None => File::create(output).with_context(|| format!("failed to create {:?}", output))?,
+    };
+    let mut out = BufWriter::new(file);
+    let stats = join_gpx_resumable(files, &mut out, config, checkpoint, |checkpoint| {
+        write_atomically(state, |f| Ok(writeln!(f, "{}", checkpoint)?))
+    })?;
+    out.flush().with_context(|| format!("failed to write {:?}", output))?;
+    std::fs::remove_file(state).with_context(|| format!("failed to remove {:?}", state))?;
+    Ok(stats)
+}
+
+/// How often to check the watched directory for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Join the GPX files in a directory, after any files given as arguments, and join them again
+/// whenever one is added, removed, or modified. Runs until killed.
+///
+/// The directory is polled rather than watched with OS notifications, which keeps this portable
+/// and free of extra dependencies.
+fn watch(args: &ParsedArgs, dir: &Path) -> anyhow::Result<()> {
+    if dir == Path::new("-") {
+        bail!("--watch needs a directory, not standard input");
+    }
+    let output = match args.output {
+        Some(ref output) => output,
+        None => bail!("--watch requires -o"),
+    };
+    if args.mode != Mode::Join || args.in_place || args.summary || args.split_by_date {
+        bail!("--watch can't be used with check, --diff, --emit-header-only, --generate-example, \
+            --list-tracks, --count, --in-place, --summary, or --split-by-date");
+    }
+    let mut last = None;
+    loop {
+        let snapshot = scan_directory(dir, output)?;
+        if last.as_ref() != Some(&snapshot) {
+            let paths = args.base.iter()
+                .chain(&args.paths)
+                .chain(snapshot.iter().map(|(path, _)| path))
+                .cloned()
+                .collect::<Vec<_>>();
+            let paths = expand_zips(paths, args.recursive)?;
+            if paths.is_empty() {
+                info!("no GPX files in {:?} yet", dir);
+            } else {
+                // A file that's still being written may fail to parse; it'll be tried again once
+                // it changes.
+                match join_files(args, &paths) {
+                    Ok(()) => info!("{} {} files into {:?}", log::paint(log::Level::Info, "joined"),
+                        paths.len(), output),
+                    Err(e) => error!("{:#}", e),
+                }
+            }
+            last = Some(snapshot);
+        }
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// The GPX files in a directory, other than the output file, sorted by name, along with their
+/// modification times.
+fn scan_directory(dir: &Path, output: &Path) -> anyhow::Result<Vec<(PathBuf, SystemTime)>> {
+    let output = output.canonicalize().ok();
     let mut files = vec![];
-    for path in parse_args() {
-        let r = BufReader::new(
-                File::open(&path)
-                    .with_context(|| format!("failed to open {:?}", path))?);
-        files.push(r);
+    for entry in std::fs::read_dir(dir).with_context(|| format!("failed to read {:?}", dir))? {
+        let entry = entry.with_context(|| format!("failed to read {:?}", dir))?;
+        let path = entry.path();
+        if !path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gpx"))
+            || (output.is_some() && path.canonicalize().ok() == output)
+        {
+            continue;
+        }
+        let metadata = match entry.metadata() {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => continue,
+        };
+        files.push((path, metadata.modified()?));
     }
-    if files.is_empty() {
-        bail!("need at least one source file");
+    files.sort();
+    Ok(files)
+}
+
+/// Write the result to the -o file, in a zip archive if it's one, replacing the file only once
+/// it's been completely written.
+fn write_output(
+    path: &Path,
+    args: &ParsedArgs,
+    write: impl FnOnce(&mut dyn Write) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    if !is_zip(path) {
+        return write_atomically(path, |out| write(out));
+    }
+    write_atomically(path, |out| {
+        let mut zip = ZipWriter::new(out);
+        zip.start_file(args.zip_entry_name.as_deref().unwrap_or("merged.gpx"))?;
+        write(&mut zip)?;
+        zip.finish()?;
+        Ok(())
+    })
+}
+
+/// Write to a temporary file next to the given path, then move it into place only if writing
+/// succeeds, so the destination is never left half-written.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(path.file_name().ok_or_else(|| anyhow!("invalid output path {:?}", path))?);
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let result = File::create(&tmp_path)
+        .with_context(|| format!("failed to create {:?}", tmp_path))
+        .and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write(&mut out)?;
+            out.flush().with_context(|| format!("failed to write {:?}", tmp_path))
+        })
+        .and_then(|()| std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("failed to rename {:?} to {:?}", tmp_path, path)));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Reads a file, timing the reads, and prints how fast it was read once it's been read to the
+/// end, if `report` is set. Only the time spent reading is counted, not the time spent doing
+/// anything with what was read.
+struct ThroughputReader<R> {
+    inner: R,
+    name: String,
+    report: bool,
+    bytes: u64,
+    elapsed: Duration,
+}
+
+impl<R: BufRead> ThroughputReader<R> {
+    fn new(inner: R, path: &Path, report: bool) -> Self {
+        let name = path.display().to_string();
+        ThroughputReader { inner, name, report, bytes: 0, elapsed: Duration::ZERO }
+    }
+
+    fn finish(&mut self) {
+        if self.report {
+            eprintln!("[{}] {}", self.name, throughput(self.bytes, self.elapsed));
+            self.report = false;
+        }
     }
-    join_gpx(&mut files, io::stdout())?;
-    Ok(())
+}
+
+impl<R: BufRead> io::Read for ThroughputReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let n = data.len().min(buf.len());
+        buf[.. n].copy_from_slice(&data[.. n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for ThroughputReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.report {
+            let start = Instant::now();
+            let at_end = self.inner.fill_buf()?.is_empty();
+            self.elapsed += start.elapsed();
+            if at_end {
+                self.finish();
+            }
+        }
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.bytes += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+/// Describe a rate of reading, like `42.3 MB in 0.21s = 201 MB/s`.
+fn throughput(bytes: u64, elapsed: Duration) -> String {
+    let megabytes = bytes as f64 / 1e6;
+    let seconds = elapsed.as_secs_f64();
+    format!("{:.1} MB in {:.2}s = {:.0} MB/s", megabytes, seconds, megabytes / seconds.max(1e-6))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use pretty_assertions::assert_eq;
-    use std::io::Cursor;
+    use std::io::Read as _;
+
+    fn parse(args: &[&str]) -> anyhow::Result<ParsedArgs> {
+        parse_args_from(args.iter().map(OsString::from))
+    }
+
+    #[test]
+    fn empty() {
+        let args = parse(&[]).unwrap();
+        assert_eq!(args.mode, Mode::Join);
+        assert!(args.paths.is_empty());
+    }
+
+    #[test]
+    fn help() {
+        for flag in &["-h", "--help", "-V", "--version"] {
+            assert_eq!(parse(&["a.gpx", flag, "--bogus"]).unwrap().mode, Mode::Help);
+        }
+    }
+
+    #[test]
+    fn double_dash() {
+        let args = parse(&["--fix", "--", "-o", "--fix", "check"]).unwrap();
+        assert!(args.config.fix);
+        assert_eq!(args.output, None);
+        assert_eq!(args.paths,
+            [PathBuf::from("-o"), PathBuf::from("--fix"), PathBuf::from("check")]);
+    }
+
+    #[test]
+    fn values() {
+        let args = parse(&["check", "-o", "out.gpx", "--base=base.gpx", "a.gpx", "check"]).unwrap();
+        assert_eq!(args.mode, Mode::Check);
+        assert_eq!(args.output, Some(PathBuf::from("out.gpx")));
+        assert_eq!(args.base, Some(PathBuf::from("base.gpx")));
+        assert_eq!(args.paths, [PathBuf::from("a.gpx"), PathBuf::from("check")]);
+
+        assert_eq!(parse(&["--fix=yes"]).unwrap_err().to_string(), "--fix doesn't take a value");
+        assert_eq!(parse(&["-o"]).unwrap_err().to_string(), "-o requires a value");
+        assert!(parse(&["--bogus"]).is_err());
+    }
+
+    #[test]
+    fn max_age() {
+        let args = parse(&["--max-age=30", "a.gpx"]).unwrap();
+        assert_eq!(args.max_age, Some(30));
+        assert!(parse(&["--max-age=-1"]).is_err());
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_630_090_764);
+        assert_eq!(max_age_cutoff(now, 30).to_string(), "2021-07-28T18:59:24Z");
+        assert_eq!(max_age_cutoff(now, 0).to_string(), "2021-08-27T18:59:24Z");
+    }
+
+    #[test]
+    fn speed_extension() {
+        let args = parse(&["--add-speed-extension"]).unwrap();
+        assert_eq!(args.config.speed_extension.as_deref(), Some("speed"));
+        let args = parse(&["--speed-extension-name=gpxtpx:speed", "--add-speed-extension"])
+            .unwrap();
+        assert_eq!(args.config.speed_extension.as_deref(), Some("gpxtpx:speed"));
+        assert!(parse(&["--speed-extension-name=speed"]).is_err());
+        assert!(parse(&["--add-speed-extension", "--speed-extension-name=a b"]).is_err());
+    }
+
+    #[test]
+    fn in_place() {
+        assert!(parse(&["--in-place", "a.gpx"]).unwrap().in_place);
+        assert!(parse(&["--in-place", "--format=gpx", "a.gpx"]).is_ok());
+        assert_eq!(parse(&["--in-place", "--format=nmea", "a.gpx"]).unwrap_err().to_string(),
+            "--in-place only writes back GPX, so --format has to be gpx");
+        let modes = [&["--in-place", "--emit-header-only", "a.gpx"][..],
+            &["--in-place", "--count", "a.gpx"], &["check", "--in-place", "a.gpx"]];
+        for args in &modes {
+            let err = parse(args).unwrap_err().to_string();
+            assert!(err.starts_with("--in-place can't be used with check"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn no_empty_segments() {
+        let min_points = |args: &[&str]| parse(args).unwrap().config.min_points_per_segment;
+        assert_eq!(min_points(&["--no-empty-segments"]), Some(1));
+        assert_eq!(min_points(&["--no-empty-segments", "--min-points-per-segment=0"]), Some(1));
+        assert_eq!(min_points(&["--min-points-per-segment=3", "--no-empty-segments"]), Some(3));
+    }
+
+    #[test]
+    fn join_report() {
+        let a = "<gpx><trk><trkseg>\
+            <trkpt lat=\"47.0\" lon=\"-121.0\"><time>2021-08-27T10:00:00Z</time></trkpt>\
+            <trkpt lat=\"47.1\" lon=\"-121.0\"/></trkseg></trk></gpx>";
+        let b = "<gpx><trk><trkseg><trkpt lat=\"46.9\" lon=\"-120.5\">\
+            <time>2021-08-28T09:30:00Z</time></trkpt></trkseg></trk></gpx>";
+        let stats = join_gpx_with_stats(&mut [a.as_bytes(), b.as_bytes()], io::sink(),
+            &JoinConfig::default()).unwrap();
+        assert_eq!(report(&stats, 1), [
+            "---",
+            "files: 2 joined, 1 skipped",
+            "tracks: 2, 3 pts, 11.1km",
+            "bounds: lat 46.9 to 47.1, lon -121 to -120.5",
+            "time: 2021-08-27T10:00:00Z to 2021-08-28T09:30:00Z",
+        ]);
+        let stats = join_gpx_with_stats(&mut ["<gpx></gpx>".as_bytes()], io::sink(),
+            &JoinConfig::default()).unwrap();
+        assert_eq!(report(&stats, 0),
+            ["---", "files: 1 joined, 0 skipped", "tracks: 0, 0 pts, 0.0km"]);
+    }
+
+    #[test]
+    fn skip_reader() {
+        let data = (0 .. 100u8).collect::<Vec<_>>();
+        let mut reader =
+            SkipReader { file: Cursor::new(&data), pos: 0, skip_from: 10, skip_to: 95 };
+        let mut out = vec![];
+        let mut buf = [0; 7];
+        loop {
+            match reader.read(&mut buf).unwrap() {
+                0 => break,
+                n => out.extend_from_slice(&buf[.. n]),
+            }
+        }
+        assert_eq!(out, (0 .. 10).chain(95 .. 100).collect::<Vec<_>>());
+    }
 
     #[test]
-    fn test() {
-        let a = Cursor::new(r#"<?xml version="1.0" encoding="utf-8"?>
-<gpx version="1.1" creator="gpxjoin" xmlns="http://www.topografix.com/GPX/1/1">
-    <metadata>
-        <name><![CDATA[this is the first file]]></name>
-        <desc>description here</desc>
-        <author><name>whatever</name></author>
-    </metadata>
-    <trk>
-        <name>first track</name>
-        <trkseg>
-            <trkpt lat="47.543448" lon="-121.096462">
-                <ele>1008.620662</ele>
-                <time>2021-08-27T18:59:24.070Z</time>
-            </trkpt>
-        </trkseg>
-    </trk>
-</gpx>
-"#.as_bytes());
-        let b = Cursor::new(r#"<?xml version="1.0" encoding="utf-8"?>
-<gpx version="1.1" creator="gpxjoin" xmlns="http://www.topografix.com/GPX/1/1">
-    <metadata>
-        <name><![CDATA[this is the second file]]></name>
-        <desc>description here</desc>
-        <author><name>whatever</name></author>
-    </metadata>
-    <trk>
-        <name>second track</name>
-        <trkseg>
-            <trkpt lat="47.552213" lon="-121.133853">
-                <ele>1750.672203</ele>
-                <time>2021-08-27T22:04:05.536Z</time>
-            </trkpt>
-        </trkseg>
-    </trk>
-</gpx>
-"#.as_bytes());
-        let mut out = Cursor::new(vec![]);
-        join_gpx(&mut [a, b], &mut out).unwrap();
-
-        // Indentation at the second track is weird because XML is a bad format; there's no
-        // reasonable way around it.
-        assert_eq!(String::from_utf8(out.into_inner()).unwrap(),
-            r#"<?xml version="1.0" encoding="utf-8"?>
-<gpx version="1.1" creator="gpxjoin" xmlns="http://www.topografix.com/GPX/1/1">
-    <metadata>
-        <name><![CDATA[this is the first file]]></name>
-        <desc>description here</desc>
-        <author><name>whatever</name></author>
-    </metadata>
-    <trk>
-        <name>first track</name>
-        <trkseg>
-            <trkpt lat="47.543448" lon="-121.096462">
-                <ele>1008.620662</ele>
-                <time>2021-08-27T18:59:24.070Z</time>
-            </trkpt>
-        </trkseg>
-    </trk>
-<trk>
-        <name>second track</name>
-        <trkseg>
-            <trkpt lat="47.552213" lon="-121.133853">
-                <ele>1750.672203</ele>
-                <time>2021-08-27T22:04:05.536Z</time>
-            </trkpt>
-        </trkseg>
-    </trk></gpx>
-"#);
+    fn throughput_reader() {
+        let data = "<gpx></gpx>".repeat(1000);
+        let mut reader = ThroughputReader::new(data.as_bytes(), Path::new("a.gpx"), true);
+        let mut read = String::new();
+        reader.read_to_string(&mut read).unwrap();
+        assert_eq!(read, data);
+        assert_eq!(reader.bytes, 11_000);
+        // Reported once, at the end.
+        assert!(!reader.report);
+        assert_eq!(throughput(42_300_000, Duration::from_millis(210)),
+            "42.3 MB in 0.21s = 201 MB/s");
     }
 }
@@ -0,0 +1,173 @@
+use crate::schema;
+use crate::tree::{Document, Element};
+use std::io::BufRead;
+
+/// A `<metadata>` element from a GPX document, for using in place of the first file's.
+#[derive(Debug, Clone)]
+pub struct Metadata(pub(crate) Element);
+
+impl Metadata {
+    /// Read the `<metadata>` element of a GPX document, if it has one.
+    pub fn read<R: BufRead>(source: R) -> anyhow::Result<Option<Metadata>> {
+        let doc = Document::read(source)?;
+        Ok(doc.root.child(b"metadata").cloned().map(Metadata))
+    }
+
+    /// Combine the metadata of several files. Everything comes from the first, except that it
+    /// gets a `<link>` for each distinct URL linked to by any of them, and its author's name
+    /// becomes all the authors' distinct names, separated by "; ".
+    ///
+    /// The other files' authors' emails are dropped, unless `merge_emails` is set, in which case
+    /// the first one found is used if the first file's author doesn't have one.
+    pub fn merge(all: Vec<Metadata>, merge_emails: bool) -> Option<Metadata> {
+        let mut all = all.into_iter();
+        let Metadata(mut merged) = all.next()?;
+        let mut names = author_names(&merged);
+        let mut email = None;
+        for Metadata(other) in all {
+            for link in other.elements().filter(|el| el.name() == b"link") {
+                let href = link.attr(b"href");
+                let seen = merged.elements()
+                    .any(|el| el.name() == b"link" && el.attr(b"href") == href);
+                if !seen {
+                    merged.insert_child(link.clone(), schema::METADATA);
+                }
+            }
+            for name in author_names(&other) {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            if email.is_none() && merge_emails {
+                email = other.child(b"author").and_then(|a| a.child(b"email")).cloned();
+            }
+        }
+        if !names.is_empty() {
+            if merged.child(b"author").is_none() {
+                merged.insert_child(Element::new("author"), schema::METADATA);
+            }
+            let author = merged.child_mut(b"author").unwrap();
+            author.set_child_text("name", &names.join("; "), schema::PERSON);
+            if let (None, Some(email)) = (author.child(b"email"), email) {
+                author.insert_child(email, schema::PERSON);
+            }
+        }
+        Some(Metadata(merged))
+    }
+}
+
+/// A `<copyright>` element for the output's metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Copyright {
+    pub year: i32,
+    /// Who holds the copyright.
+    pub author: String,
+    /// A link to the license.
+    pub license: Option<String>,
+}
+
+impl Copyright {
+    /// Put this in place of the metadata's `<copyright>`, or add it if it has none.
+    pub(crate) fn set(&self, metadata: &mut Element) {
+        let mut el = Element::new("copyright");
+        el.set_attr(b"author", &self.author);
+        el.insert_child(Element::with_text("year", &self.year.to_string()), &[]);
+        if let Some(ref license) = self.license {
+            el.insert_child(Element::with_text("license", license), &[]);
+        }
+        if metadata.child(b"copyright").is_some() {
+            metadata.replace_children(b"copyright", vec![el]);
+        } else {
+            metadata.insert_child(el, schema::METADATA);
+        }
+    }
+}
+
+/// Parses `YEAR,AUTHOR` or `YEAR,AUTHOR,LICENSE`. Everything after the second comma is the
+/// license, so it can have commas in it, but the author can't.
+impl std::str::FromStr for Copyright {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Copyright> {
+        let mut parts = s.splitn(3, ',').map(str::trim);
+        let year = parts.next().and_then(|year| year.parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("the copyright must start with a year"))?;
+        let author = match parts.next() {
+            Some(author) if !author.is_empty() => author.to_owned(),
+            _ => anyhow::bail!("the copyright needs an author after the year"),
+        };
+        let license = parts.next().filter(|license| !license.is_empty()).map(str::to_owned);
+        Ok(Copyright { year, author, license })
+    }
+}
+
+fn author_names(metadata: &Element) -> Vec<String> {
+    metadata.child(b"author")
+        .and_then(|author| author.child(b"name"))
+        .map(|name| name.text().trim().to_owned())
+        .filter(|name| !name.is_empty())
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(xml: &str) -> Metadata {
+        Metadata::read(format!("<gpx>{}</gpx>", xml).as_bytes()).unwrap().unwrap()
+    }
+
+    fn write(Metadata(el): Metadata) -> String {
+        let mut out = vec![];
+        el.write(&mut quick_xml::Writer::new(&mut out)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn merge() {
+        let a = metadata(r#"<metadata><name>a</name><author><name>Alice</name></author>
+            <link href="https://a.example"/><time>2021-08-27T10:00:00Z</time></metadata>"#);
+        let b = metadata(r#"<metadata><author><name>Bob</name>
+            <email id="bob" domain="example.com"/></author><link href="https://a.example"/>
+            <link href="https://b.example"><text>b</text></link></metadata>"#);
+        let c = metadata("<metadata><author><name>Alice</name></author></metadata>");
+        let merged = Metadata::merge(vec![a.clone(), b.clone(), c.clone()], false).unwrap();
+        assert_eq!(write(merged), concat!(
+            "<metadata><name>a</name><author><name>Alice; Bob</name></author>\n            ",
+            r#"<link href="https://a.example"/><link href="https://b.example"><text>b</text>"#,
+            "</link><time>2021-08-27T10:00:00Z</time></metadata>"));
+        let merged = Metadata::merge(vec![c, b], true).unwrap();
+        assert_eq!(write(merged), concat!("<metadata><author><name>Alice; Bob</name>",
+            r#"<email id="bob" domain="example.com"/></author>"#,
+            r#"<link href="https://a.example"/><link href="https://b.example"><text>b</text>"#,
+            "</link></metadata>"));
+        assert!(Metadata::merge(vec![], false).is_none());
+    }
+
+    #[test]
+    fn copyright() {
+        let copyright = "2024, Alice ,https://creativecommons.org/licenses/by/4.0/"
+            .parse::<Copyright>().unwrap();
+        assert_eq!(copyright, Copyright {
+            year: 2024,
+            author: "Alice".to_owned(),
+            license: Some("https://creativecommons.org/licenses/by/4.0/".to_owned()),
+        });
+        assert_eq!("2024,Bob & co".parse::<Copyright>().unwrap().license, None);
+        assert!("Alice".parse::<Copyright>().is_err());
+        assert!("2024".parse::<Copyright>().is_err());
+        assert!("2024,,x".parse::<Copyright>().is_err());
+
+        let Metadata(mut el) = metadata("<metadata><name>a</name><time>2021-08-27T10:00:00Z</time>\
+            </metadata>");
+        copyright.set(&mut el);
+        let with_copyright = write(Metadata(el.clone()));
+        assert_eq!(with_copyright, concat!(r#"<metadata><name>a</name><copyright author="Alice">"#,
+            "<year>2024</year><license>https://creativecommons.org/licenses/by/4.0/</license>",
+            "</copyright><time>2021-08-27T10:00:00Z</time></metadata>"));
+        "1999,Bob & co".parse::<Copyright>().unwrap().set(&mut el);
+        assert_eq!(write(Metadata(el)), concat!(r#"<metadata><name>a</name>"#,
+            r#"<copyright author="Bob &amp; co"><year>1999</year></copyright>"#,
+            "<time>2021-08-27T10:00:00Z</time></metadata>"));
+    }
+}
@@ -0,0 +1,170 @@
+use crate::geo::{haversine_distance, initial_bearing};
+use crate::point::TrackPoint;
+use crate::tree::{Document, Element};
+use std::io::{BufRead, Write};
+
+/// Meters per second to knots.
+const KNOTS: f64 = 3600. / 1852.;
+
+/// Convert a GPX document to NMEA 0183 sentences: a `$GPWPL` for each waypoint, then a `$GPRMC`
+/// and a `$GPGGA` for each track point.
+///
+/// Speed and course in `$GPRMC` are worked out from the previous point in the segment, and left
+/// empty if either point has no time. Fields that the GPX doesn't have, such as the time of an
+/// untimed point, are left empty too.
+pub fn write_nmea<R: BufRead, W: Write>(source: R, mut dest: W) -> anyhow::Result<()> {
+    let doc = Document::read(source)?;
+    for wpt in doc.root.elements().filter(|el| el.name() == b"wpt") {
+        let point = match TrackPoint::from_element(wpt) {
+            Some(point) => point,
+            None => continue,
+        };
+        let name = wpt.child(b"name")
+            .map(|name| name.text().chars().filter(|&c| is_field_char(c)).collect::<String>())
+            .unwrap_or_default();
+        sentence(&mut dest, &format!("GPWPL,{},{},{}",
+            latitude(point.lat), longitude(point.lon), name.trim()))?;
+    }
+
+    for trk in doc.root.elements().filter(|el| el.name() == b"trk") {
+        for seg in trk.elements().filter(|el| el.name() == b"trkseg") {
+            let mut prev = None::<TrackPoint>;
+            for el in seg.elements().filter(|el| el.name() == b"trkpt") {
+                let point = match TrackPoint::from_element(el) {
+                    Some(point) => point,
+                    None => continue,
+                };
+                write_point(&mut dest, el, &point, prev.as_ref())?;
+                prev = Some(point);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_point<W: Write>(
+    dest: &mut W,
+    el: &Element,
+    point: &TrackPoint,
+    prev: Option<&TrackPoint>,
+) -> anyhow::Result<()> {
+    let (time, date) = match point.time {
+        Some(t) => {
+            let ms = t.0.rem_euclid(86_400_000);
+            let (year, month, day) = t.date();
+            (format!("{:02}{:02}{:02}.{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60,
+                ms % 1000),
+                format!("{:02}{:02}{:02}", day, month, year.rem_euclid(100)))
+        }
+        None => (String::new(), String::new()),
+    };
+    let (lat, lon) = (latitude(point.lat), longitude(point.lon));
+
+    let (mut speed, mut course) = (String::new(), String::new());
+    if let (Some(prev), Some(t)) = (prev, point.time) {
+        if let Some(prev_t) = prev.time.filter(|prev_t| *prev_t < t) {
+            let seconds = (t.0 - prev_t.0) as f64 / 1000.;
+            speed = format!("{:.1}", haversine_distance(prev, point) / seconds * KNOTS);
+            course = format!("{:.1}", initial_bearing(prev, point));
+        }
+    }
+    sentence(dest, &format!("GPRMC,{},A,{},{},{},{},{},,,A", time, lat, lon, speed, course, date))?;
+
+    let child = |name: &[u8]| el.child(name).map(|c| c.text().trim().to_owned());
+    let quality = match child(b"fix").as_deref() {
+        Some("none") => 0,
+        Some("dgps") => 2,
+        Some("pps") => 3,
+        _ => 1,
+    };
+    let number = |name: &[u8]| child(name).filter(|v| v.parse::<f64>().is_ok()).unwrap_or_default();
+    let altitude = match point.ele {
+        Some(ele) => format!("{:.1},M", ele),
+        None => ",".to_owned(),
+    };
+    let geoid = match child(b"geoidheight").and_then(|g| g.parse::<f64>().ok()) {
+        Some(geoid) => format!("{:.1},M", geoid),
+        None => ",".to_owned(),
+    };
+    sentence(dest, &format!("GPGGA,{},{},{},{},{},{},{},{},,", time, lat, lon, quality,
+        number(b"sat"), number(b"hdop"), altitude, geoid))?;
+    Ok(())
+}
+
+/// Write a sentence, adding the `$`, the checksum, and the line ending.
+fn sentence<W: Write>(dest: &mut W, body: &str) -> anyhow::Result<()> {
+    write!(dest, "${}*{:02X}\r\n", body, checksum(body))?;
+    Ok(())
+}
+
+/// The XOR of all the bytes between the `$` and the `*`.
+fn checksum(body: &str) -> u8 {
+    body.bytes().fold(0, |sum, b| sum ^ b)
+}
+
+/// Characters that can go in a text field: printable ASCII, except for the delimiters.
+fn is_field_char(c: char) -> bool {
+    (' ' ..= '~').contains(&c) && !matches!(c, '$' | '*' | ',' | '!' | '\\' | '^' | '~')
+}
+
+/// Latitude as `DDMM.MMMM,N` or `DDMM.MMMM,S`.
+fn latitude(lat: f64) -> String {
+    format!("{},{}", degrees_minutes(lat.abs(), 2), if lat < 0. { 'S' } else { 'N' })
+}
+
+/// Longitude as `DDDMM.MMMM,E` or `DDDMM.MMMM,W`.
+fn longitude(lon: f64) -> String {
+    format!("{},{}", degrees_minutes(lon.abs(), 3), if lon < 0. { 'W' } else { 'E' })
+}
+
+fn degrees_minutes(value: f64, degree_digits: usize) -> String {
+    // Round to the nearest ten-thousandth of a minute first, so minutes never come out as 60.
+    let total = (value * 60. * 10_000.).round() as u64;
+    let (degrees, minutes) = (total / 600_000, total % 600_000);
+    format!("{:0width$}{:02}.{:04}", degrees, minutes / 10_000, minutes % 10_000,
+        width = degree_digits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(checksum("GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,"), 0x47);
+    }
+
+    #[test]
+    fn coordinates() {
+        assert_eq!(latitude(48.1173), "4807.0380,N");
+        assert_eq!(longitude(11.516667), "01131.0000,E");
+        assert_eq!(latitude(-0.5), "0030.0000,S");
+        assert_eq!(longitude(-121.99999999), "12200.0000,W");
+    }
+
+    #[test]
+    fn sentences() {
+        let gpx = r#"<gpx>
+    <wpt lat="47.5" lon="-121.25"><name>Camp, lake*</name></wpt>
+    <trk><trkseg>
+        <trkpt lat="47.0" lon="-121.0"><ele>100</ele><time>2021-08-27T18:59:24.070Z</time>
+            <sat>8</sat><hdop>0.9</hdop></trkpt>
+        <trkpt lat="47.001" lon="-121.0"><time>2021-08-27T19:00:24.070Z</time></trkpt>
+        <trkpt lat="47.002" lon="-121.0"/>
+    </trkseg></trk>
+</gpx>"#;
+        let mut out = vec![];
+        write_nmea(gpx.as_bytes(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), [
+            "$GPWPL,4730.0000,N,12115.0000,W,Camp lake*43",
+            "$GPRMC,185924.070,A,4700.0000,N,12100.0000,W,,,270821,,,A*77",
+            "$GPGGA,185924.070,4700.0000,N,12100.0000,W,1,8,0.9,100.0,M,,,,*28",
+            "$GPRMC,190024.070,A,4700.0600,N,12100.0000,W,3.6,0.0,270821,,,A*79",
+            "$GPGGA,190024.070,4700.0600,N,12100.0000,W,1,,,,,,,,*5E",
+            "$GPRMC,,A,4700.1200,N,12100.0000,W,,,,,,A*60",
+            "$GPGGA,,4700.1200,N,12100.0000,W,1,,,,,,,,*4C",
+            "",
+        ].join("\r\n"));
+    }
+}
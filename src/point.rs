@@ -0,0 +1,66 @@
+use crate::{schema, Timestamp};
+use crate::tree::Element;
+
+/// The position and time of a `<trkpt>`, `<rtept>`, or `<wpt>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TrackPoint {
+    pub lat: f64,
+    pub lon: f64,
+    pub ele: Option<f64>,
+    pub time: Option<Timestamp>,
+}
+
+impl TrackPoint {
+    /// Read the point from its element. Returns None if the coordinates are missing or invalid.
+    pub fn from_element(el: &Element) -> Option<TrackPoint> {
+        Some(TrackPoint {
+            lat: el.attr(b"lat")?.trim().parse().ok()?,
+            lon: el.attr(b"lon")?.trim().parse().ok()?,
+            ele: el.child(b"ele").and_then(|e| e.text().trim().parse().ok()),
+            time: el.child(b"time").and_then(|t| Timestamp::parse(&t.text())),
+        })
+    }
+
+    /// Make a new element for the point.
+    pub fn to_element(self, name: &str) -> Element {
+        let mut el = Element::new(name);
+        el.set_attr(b"lat", &format_number(self.lat, 7));
+        el.set_attr(b"lon", &format_number(self.lon, 7));
+        if let Some(ele) = self.ele {
+            el.insert_child(Element::with_text("ele", &format_number(ele, 2)), schema::POINT);
+        }
+        if let Some(time) = self.time {
+            el.insert_child(Element::with_text("time", &time.to_string()), schema::POINT);
+        }
+        el
+    }
+
+    /// The point a fraction `t` of the way from this point to the other one, by linear
+    /// interpolation. Elevation and time are only interpolated if both points have them.
+    pub fn interpolate(&self, other: &TrackPoint, t: f64) -> TrackPoint {
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+        TrackPoint {
+            lat: lerp(self.lat, other.lat),
+            lon: lerp(self.lon, other.lon),
+            ele: match (self.ele, other.ele) {
+                (Some(a), Some(b)) => Some(lerp(a, b)),
+                _ => None,
+            },
+            time: match (self.time, other.time) {
+                (Some(a), Some(b)) => Some(Timestamp(a.0 + ((b.0 - a.0) as f64 * t).round() as i64)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Format a number with at most the given number of decimal places, without trailing zeroes.
+pub(crate) fn format_number(value: f64, max_decimals: usize) -> String {
+    let s = format!("{:.*}", max_decimals, value);
+    if s.contains('.') {
+        let s = s.trim_end_matches('0').trim_end_matches('.');
+        if s == "-0" { "0".to_owned() } else { s.to_owned() }
+    } else {
+        s
+    }
+}
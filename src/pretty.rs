@@ -0,0 +1,58 @@
+use crate::tree::{is_whitespace, read_event};
+use quick_xml::{Reader, Writer};
+use quick_xml::events::Event;
+use std::io::{BufRead, Write};
+
+/// Rewrite a GPX file with each element on its own line, indented by `indent` spaces for each
+/// level it's nested.
+///
+/// The whitespace between elements is replaced; text with anything else in it is kept as it is,
+/// and elements containing text stay on one line.
+pub fn pretty_print<R: BufRead, W: Write>(source: R, dest: W, indent: usize)
+    -> anyhow::Result<()>
+{
+    let mut reader = Reader::from_reader(source);
+    let mut writer = Writer::new_with_indent(dest, b' ', indent);
+    let mut buf = vec![];
+    loop {
+        match read_event(&mut reader, &mut buf)? {
+            Event::Eof => break,
+            Event::Text(ref text) if is_whitespace(text) => (),
+            evt => writer.write_event(evt)?,
+        }
+        buf.clear();
+    }
+    writer.inner().write_all(b"\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn pretty() {
+        let gpx = "<?xml version=\"1.0\"?><gpx version=\"1.1\"><metadata><name> a &amp; b </name>\
+            </metadata>\n\n  <trk><trkseg><trkpt lat=\"1\" lon=\"2\"><ele>3</ele></trkpt>\
+            <trkpt lat=\"1\" lon=\"2\"/></trkseg>\t</trk><!-- done --></gpx>\n";
+        let mut out = vec![];
+        pretty_print(gpx.as_bytes(), &mut out, 2).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), r#"<?xml version="1.0"?>
+<gpx version="1.1">
+  <metadata>
+    <name> a &amp; b </name>
+  </metadata>
+  <trk>
+    <trkseg>
+      <trkpt lat="1" lon="2">
+        <ele>3</ele>
+      </trkpt>
+      <trkpt lat="1" lon="2"/>
+    </trkseg>
+  </trk>
+  <!-- done -->
+</gpx>
+"#);
+    }
+}
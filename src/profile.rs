@@ -0,0 +1,170 @@
+use crate::geo::haversine_distance;
+use crate::point::TrackPoint;
+use crate::tree::Document;
+use std::fmt::Write as _;
+use std::io::BufRead;
+
+/// Line colors for the tracks, used in turn.
+const COLORS: [&str; 8] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+];
+
+/// Space around the plot for the axis labels: left, right, top, bottom.
+const MARGIN: (f64, f64, f64, f64) = (60., 10., 10., 30.);
+
+/// Draw an SVG chart of elevation against distance travelled for the tracks in a GPX document.
+///
+/// Distance is accumulated along each track segment, continuing from one segment and track to the
+/// next without counting the gaps between them. Points without an elevation still count towards
+/// the distance. Each track is drawn in its own color.
+pub fn elevation_profile<R: BufRead>(source: R, width: u32, height: u32) -> anyhow::Result<String> {
+    let doc = Document::read(source)?;
+
+    // For each track: its name, and lines of (distance, elevation) pairs. Lines are broken at
+    // segment boundaries and at points without an elevation.
+    let mut tracks = vec![];
+    let mut distance = 0.;
+    for trk in doc.root.elements().filter(|el| el.name() == b"trk") {
+        let mut lines = vec![];
+        for seg in trk.elements().filter(|el| el.name() == b"trkseg") {
+            let mut line = vec![];
+            let mut prev = None::<TrackPoint>;
+            for point in seg.elements()
+                .filter(|el| el.name() == b"trkpt")
+                .filter_map(TrackPoint::from_element)
+            {
+                if let Some(prev) = prev {
+                    distance += haversine_distance(&prev, &point);
+                }
+                prev = Some(point);
+                match point.ele {
+                    Some(ele) => line.push((distance, ele)),
+                    None => lines.push(std::mem::take(&mut line)),
+                }
+            }
+            lines.push(line);
+        }
+        lines.retain(|line| !line.is_empty());
+        tracks.push((trk.track_name(), lines));
+    }
+
+    let elevations = || tracks.iter().flat_map(|(_, lines)| lines.iter().flatten().map(|p| p.1));
+    let (min_ele, max_ele) = elevations()
+        .fold(None, |range, ele| match range {
+            None => Some((ele, ele)),
+            Some((lo, hi)) => Some((ele.min(lo), ele.max(hi))),
+        })
+        .ok_or_else(|| anyhow::anyhow!("no track points have an elevation to make a profile of"))?;
+    // Avoid dividing by zero for flat or very short tracks.
+    let ele_range = (max_ele - min_ele).max(1.);
+    let total_distance = distance.max(1.);
+
+    let (left, right, top, bottom) = MARGIN;
+    let plot_width = (f64::from(width) - left - right).max(1.);
+    let plot_height = (f64::from(height) - top - bottom).max(1.);
+    let x = |d: f64| left + d / total_distance * plot_width;
+    let y = |ele: f64| top + (max_ele - ele) / ele_range * plot_height;
+
+    let mut svg = String::new();
+    writeln!(svg,
+        concat!(r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" "#,
+            r#"viewBox="0 0 {w} {h}">"#),
+        w = width, h = height)?;
+    svg.push_str("<style>\n");
+    svg.push_str("  svg { background: #fff; font: 12px sans-serif; }\n");
+    svg.push_str("  .axis { stroke: #000; stroke-width: 1; fill: none; }\n");
+    svg.push_str("  .track { stroke-width: 1.5; fill: none; stroke-linejoin: round; }\n");
+    for (i, color) in COLORS.iter().enumerate() {
+        writeln!(svg, "  .track{} {{ stroke: {}; }}", i, color)?;
+    }
+    svg.push_str("</style>\n");
+
+    writeln!(svg, r#"<path class="axis" d="M{l},{t} V{b} H{r}"/>"#,
+        l = left, t = top, b = top + plot_height, r = left + plot_width)?;
+    writeln!(svg,
+        r#"<text x="{}" y="{}" text-anchor="end" dominant-baseline="hanging">{} m</text>"#, left - 4., top, format_label(max_ele))?;
+    writeln!(svg, r#"<text x="{}" y="{}" text-anchor="end">{} m</text>"#,
+        left - 4., top + plot_height, format_label(min_ele))?;
+    writeln!(svg, r#"<text x="{}" y="{}" text-anchor="start">0 km</text>"#,
+        left, top + plot_height + 16.)?;
+    writeln!(svg, r#"<text x="{}" y="{}" text-anchor="end">{} km</text>"#,
+        left + plot_width, top + plot_height + 16., format_label(distance / 1000.))?;
+
+    for (i, (name, lines)) in tracks.iter().enumerate() {
+        if lines.is_empty() {
+            continue;
+        }
+        writeln!(svg, r#"<g class="track track{}">"#, i % COLORS.len())?;
+        if let Some(name) = name {
+            writeln!(svg, "<title>{}</title>", xml_escape(name))?;
+        }
+        for line in lines {
+            svg.push_str(r#"<polyline points=""#);
+            for (j, &(d, ele)) in line.iter().enumerate() {
+                if j > 0 {
+                    svg.push(' ');
+                }
+                write!(svg, "{:.1},{:.1}", x(d), y(ele))?;
+            }
+            svg.push_str("\"/>\n");
+        }
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+fn format_label(value: f64) -> String {
+    crate::point::format_number(value, 1)
+}
+
+fn xml_escape(s: &str) -> String {
+    String::from_utf8_lossy(&quick_xml::escape::escape(s.as_bytes())).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile() {
+        let gpx = r#"<gpx>
+    <trk>
+        <name>up &amp; over</name>
+        <trkseg>
+            <trkpt lat="47.0" lon="-121.0"><ele>100</ele></trkpt>
+            <trkpt lat="47.001" lon="-121.0"><ele>200</ele></trkpt>
+            <trkpt lat="47.002" lon="-121.0"/>
+            <trkpt lat="47.003" lon="-121.0"><ele>150</ele></trkpt>
+        </trkseg>
+    </trk>
+    <trk>
+        <trkseg>
+            <trkpt lat="47.003" lon="-121.0"><ele>150</ele></trkpt>
+            <trkpt lat="47.004" lon="-121.0"><ele>100</ele></trkpt>
+        </trkseg>
+    </trk>
+</gpx>"#;
+        let svg = elevation_profile(gpx.as_bytes(), 160, 140).unwrap();
+        assert!(svg.starts_with(concat!(r#"<svg xmlns="http://www.w3.org/2000/svg" "#,
+            r#"width="160" height="140" viewBox="0 0 160 140">"#)), "{}", svg);
+        let body = &svg[svg.find("</style>").unwrap() ..];
+        // The plot is 90x100 pixels; each point is a quarter of the way along.
+        assert_eq!(body, r#"</style>
+<path class="axis" d="M60,10 V110 H150"/>
+<text x="56" y="10" text-anchor="end" dominant-baseline="hanging">200 m</text>
+<text x="56" y="110" text-anchor="end">100 m</text>
+<text x="60" y="126" text-anchor="start">0 km</text>
+<text x="150" y="126" text-anchor="end">0.4 km</text>
+<g class="track track0">
+<title>up &amp; over</title>
+<polyline points="60.0,110.0 82.5,10.0"/>
+<polyline points="127.5,60.0"/>
+</g>
+<g class="track track1">
+<polyline points="127.5,60.0 150.0,110.0"/>
+</g>
+</svg>
+"#);
+    }
+}
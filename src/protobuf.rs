@@ -0,0 +1,102 @@
+//! Encoding of tracks as Protocol Buffers, following the schema in `proto/gpx.proto`.
+//!
+//! The messages are simple enough that they're encoded by hand here instead of with generated
+//! code.
+
+use crate::point::TrackPoint;
+use crate::tree::Document;
+use std::io::{BufRead, Write};
+
+/// Convert the tracks in a GPX document to a `gpx.Gpx` message.
+pub fn write_protobuf<R: BufRead, W: Write>(source: R, mut dest: W) -> anyhow::Result<()> {
+    let doc = Document::read(source)?;
+    let mut gpx = vec![];
+    for trk in doc.root.elements().filter(|el| el.name() == b"trk") {
+        let mut track = vec![];
+        if let Some(name) = trk.track_name().filter(|name| !name.is_empty()) {
+            bytes_field(&mut track, 1, name.as_bytes());
+        }
+        for seg in trk.elements().filter(|el| el.name() == b"trkseg") {
+            let mut segment = vec![];
+            for point in seg.elements()
+                .filter(|el| el.name() == b"trkpt")
+                .filter_map(TrackPoint::from_element)
+            {
+                bytes_field(&mut segment, 1, &encode_point(&point));
+            }
+            bytes_field(&mut track, 2, &segment);
+        }
+        bytes_field(&mut gpx, 1, &track);
+    }
+    dest.write_all(&gpx)?;
+    Ok(())
+}
+
+fn encode_point(point: &TrackPoint) -> Vec<u8> {
+    let mut msg = vec![];
+    let lat = (point.lat * 1e7).round() as i32;
+    let lon = (point.lon * 1e7).round() as i32;
+    // Fields holding their default value are left out, except for the optional ones.
+    if lat != 0 {
+        varint_field(&mut msg, 1, zigzag(lat));
+    }
+    if lon != 0 {
+        varint_field(&mut msg, 2, zigzag(lon));
+    }
+    if let Some(ele) = point.ele {
+        varint_field(&mut msg, 3, zigzag((ele * 100.).round() as i32));
+    }
+    if let Some(time) = point.time {
+        varint_field(&mut msg, 4, time.0 as u64);
+    }
+    msg
+}
+
+fn zigzag(n: i32) -> u64 {
+    u64::from(((n << 1) ^ (n >> 31)) as u32)
+}
+
+fn varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn varint_field(buf: &mut Vec<u8>, field: u32, value: u64) {
+    varint(buf, u64::from(field) << 3);
+    varint(buf, value);
+}
+
+fn bytes_field(buf: &mut Vec<u8>, field: u32, value: &[u8]) {
+    varint(buf, u64::from(field) << 3 | 2);
+    varint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        let gpx = r#"<gpx><trk><name>A</name><trkseg>
+            <trkpt lat="1" lon="-0.0000001"><ele>-2.5</ele><time>1970-01-01T00:00:00.300Z</time></trkpt>
+            <trkpt lat="0" lon="0"/>
+        </trkseg></trk></gpx>"#;
+        let mut out = vec![];
+        write_protobuf(gpx.as_bytes(), &mut out).unwrap();
+        assert_eq!(out, [
+            0x0a, 22, // Gpx.tracks
+                0x0a, 1, b'A', // Track.name
+                0x12, 17, // Track.segments
+                    0x0a, 13, // TrackSegment.points
+                        0x08, 0x80, 0xda, 0xc4, 0x09, // lat_e7 = 10000000
+                        0x10, 0x01, // lon_e7 = -1
+                        0x18, 0xf3, 0x03, // ele_cm = -250
+                        0x20, 0xac, 0x02, // timestamp_ms = 300
+                    0x0a, 0, // an empty point at 0, 0
+        ]);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::geo::haversine_distance;
+use crate::point::TrackPoint;
+use crate::tree::Element;
+
+/// How to space out the points of resampled track segments.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resample {
+    /// A point every so many meters along the segment.
+    Distance(f64),
+    /// A point every so many seconds. Segments with points that don't have a time are left as
+    /// they are.
+    Time(f64),
+}
+
+/// Replace the points of each segment of a `<trk>` element with evenly spaced ones, interpolated
+/// between the original points. The first and last points of a segment stay where they are.
+///
+/// The new points only have a position, elevation, and time; anything else the original points
+/// had is dropped, as are points with missing or invalid coordinates.
+pub(crate) fn resample_track(trk: &mut Element, resample: Resample) {
+    let trk_name = trk.track_name();
+    for seg in trk.elements_mut().filter(|el| el.name() == b"trkseg") {
+        let points = seg.elements()
+            .filter(|el| el.name() == b"trkpt")
+            .filter_map(TrackPoint::from_element)
+            .collect::<Vec<_>>();
+        if points.len() < 2 {
+            continue;
+        }
+        let resampled = match resample {
+            Resample::Distance(meters) => resample_points(&points, meters, haversine_distance),
+            Resample::Time(_) if points.iter().any(|p| p.time.is_none()) => {
+                warn!("not resampling a segment of track {:?} by time, because some of its \
+                    points don't have times", trk_name.as_deref().unwrap_or_default());
+                continue;
+            }
+            Resample::Time(seconds) => resample_points(&points, seconds, |a, b| {
+                (b.time.unwrap().0 - a.time.unwrap().0) as f64 / 1000.
+            }),
+        };
+        debug!("resample: {} points in a segment became {}", points.len(), resampled.len());
+        seg.replace_children(b"trkpt",
+            resampled.into_iter().map(|p| p.to_element("trkpt")).collect());
+    }
+}
+
+/// Points every `step` along the line through the given points, as measured by `measure`, and
+/// the last point.
+fn resample_points(
+    points: &[TrackPoint],
+    step: f64,
+    measure: impl Fn(&TrackPoint, &TrackPoint) -> f64,
+) -> Vec<TrackPoint> {
+    let mut result = vec![points[0]];
+    // How far along the line the last point in the result is from the start of the current leg.
+    let mut behind = 0.;
+    for pair in points.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let length = measure(a, b).max(0.);
+        let mut along = step - behind;
+        while along <= length {
+            result.push(a.interpolate(b, along / length));
+            along += step;
+        }
+        behind = length - (along - step);
+    }
+    // Make sure the line ends where it did, without a point next to the last one due to rounding.
+    let last = points[points.len() - 1];
+    if measure(&result[result.len() - 1], &last) < step * 1e-6 {
+        result.pop();
+    }
+    result.push(last);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Timestamp;
+    use crate::tree::Document;
+
+    #[test]
+    fn distance() {
+        let gpx = r#"<gpx><trk><trkseg>
+            <trkpt lat="47.000" lon="-121.0"><ele>100</ele><time>2021-08-27T10:00:00Z</time></trkpt>
+            <trkpt lat="bogus" lon="-121.0"/>
+            <trkpt lat="47.002" lon="-121.0"><ele>300</ele><time>2021-08-27T10:02:00Z</time></trkpt>
+        </trkseg><trkseg>
+            <trkpt lat="47.000" lon="-121.0"/>
+        </trkseg></trk></gpx>"#;
+        let mut doc = Document::read(gpx.as_bytes()).unwrap();
+        let trk = doc.root.child_mut(b"trk").unwrap();
+        resample_track(trk, Resample::Distance(50.));
+
+        let segments = trk.elements().map(|seg| {
+            seg.elements().filter_map(TrackPoint::from_element).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+        // The segment is about 222 meters long.
+        let points = &segments[0];
+        assert_eq!(points.len(), 6);
+        for (i, p) in points.iter().enumerate().skip(1) {
+            let distance = haversine_distance(&points[i - 1], p);
+            if i < 5 {
+                assert!((distance - 50.).abs() < 0.01, "{}: {}", i, distance);
+            } else {
+                assert!(distance < 50., "{}: {}", i, distance);
+            }
+        }
+        let fraction = 50. / haversine_distance(&points[0], &points[5]);
+        assert!((points[1].ele.unwrap() - (100. + 200. * fraction)).abs() < 0.01);
+        let start = Timestamp::parse("2021-08-27T10:00:00Z").unwrap();
+        assert_eq!(points[1].time, Some(Timestamp(start.0 + (120_000. * fraction).round() as i64)));
+        assert_eq!(points[5].time, Timestamp::parse("2021-08-27T10:02:00Z"));
+        assert_eq!(segments[1].len(), 1);
+
+        let mut out = vec![];
+        trk.write(&mut quick_xml::Writer::new(&mut out)).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(concat!("<trk><trkseg>\n",
+            r#"            <trkpt lat="47" lon="-121"><ele>100</ele>"#)), "{}", out);
+    }
+
+    #[test]
+    fn time() {
+        let gpx = r#"<gpx><trk><trkseg>
+            <trkpt lat="47.000" lon="-121.0"><time>2021-08-27T10:00:00Z</time></trkpt>
+            <trkpt lat="47.001" lon="-121.0"><time>2021-08-27T10:00:10Z</time></trkpt>
+            <trkpt lat="47.001" lon="-121.0"><time>2021-08-27T10:00:20Z</time></trkpt>
+        </trkseg><trkseg>
+            <trkpt lat="47.000" lon="-121.0"><time>2021-08-27T10:00:00Z</time></trkpt>
+            <trkpt lat="47.001" lon="-121.0"/>
+        </trkseg></trk></gpx>"#;
+        let mut doc = Document::read(gpx.as_bytes()).unwrap();
+        let trk = doc.root.child_mut(b"trk").unwrap();
+        resample_track(trk, Resample::Time(4.));
+
+        let segments = trk.elements().map(|seg| {
+            seg.elements().filter_map(TrackPoint::from_element).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+        let times = segments[0].iter().map(|p| p.time.unwrap().to_string()).collect::<Vec<_>>();
+        assert_eq!(times, ["2021-08-27T10:00:00Z", "2021-08-27T10:00:04Z", "2021-08-27T10:00:08Z",
+            "2021-08-27T10:00:12Z", "2021-08-27T10:00:16Z", "2021-08-27T10:00:20Z"]);
+        assert!((segments[0][1].lat - 47.0004).abs() < 1e-9);
+        assert_eq!(segments[0][4].lat, 47.001);
+        assert_eq!(segments[1].len(), 2);
+    }
+}
@@ -0,0 +1,28 @@
+//! Element orderings required by the GPX 1.1 schema.
+
+/// Children of `<gpx>`.
+pub(crate) const GPX: &[&[u8]] = &[b"metadata", b"wpt", b"rte", b"trk", b"extensions"];
+
+/// Children of `<metadata>`.
+pub(crate) const METADATA: &[&[u8]] = &[
+    b"name", b"desc", b"author", b"copyright", b"link", b"time", b"keywords", b"bounds",
+    b"extensions",
+];
+
+/// Children of `<author>`, and other elements of the `personType`.
+pub(crate) const PERSON: &[&[u8]] = &[b"name", b"email", b"link"];
+
+/// Children of `<wpt>`, `<trkpt>`, and `<rtept>`.
+pub(crate) const POINT: &[&[u8]] = &[
+    b"ele", b"time", b"magvar", b"geoidheight", b"name", b"cmt", b"desc", b"src", b"link", b"sym",
+    b"type", b"fix", b"sat", b"hdop", b"vdop", b"pdop", b"ageofdgpsdata", b"dgpsid",
+    b"extensions",
+];
+
+/// Children of `<trk>`.
+pub(crate) const TRK: &[&[u8]] = &[
+    b"name", b"cmt", b"desc", b"src", b"link", b"number", b"type", b"extensions", b"trkseg",
+];
+
+/// Children of `<trkseg>`.
+pub(crate) const TRKSEG: &[&[u8]] = &[b"trkpt", b"extensions"];
@@ -0,0 +1,132 @@
+use crate::geo::triangle_area;
+use crate::point::TrackPoint;
+use crate::tree::Element;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// How to reduce the number of points in track segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimplifyAlgorithm {
+    /// Visvalingam-Whyatt: repeatedly remove the point that makes the smallest triangle with its
+    /// neighbors, until each segment has at most `max_points` points. The first and last points
+    /// of a segment are always kept.
+    VisvalingamWhyatt { max_points: usize },
+}
+
+/// Simplify each segment of a `<trk>` element. Points with missing or invalid coordinates are
+/// left alone, and don't count towards the number of points.
+pub(crate) fn simplify_track(trk: &mut Element, algorithm: SimplifyAlgorithm) {
+    for seg in trk.elements_mut().filter(|el| el.name() == b"trkseg") {
+        let points = seg.elements()
+            .filter(|el| el.name() == b"trkpt")
+            .filter_map(TrackPoint::from_element)
+            .collect::<Vec<_>>();
+        let keep = match algorithm {
+            SimplifyAlgorithm::VisvalingamWhyatt { max_points } => {
+                visvalingam_whyatt(&points, max_points)
+            }
+        };
+        let removed = keep.iter().filter(|&&k| !k).count();
+        if removed == 0 {
+            continue;
+        }
+        debug!("simplify: removed {} of {} points from a segment", removed, points.len());
+        let mut keep = keep.into_iter();
+        seg.retain_elements(|el| {
+            if el.name() != b"trkpt" || TrackPoint::from_element(el).is_none() {
+                return true;
+            }
+            keep.next().unwrap_or(true)
+        });
+    }
+}
+
+/// A point's effective area, ordered for the heap.
+#[derive(PartialEq)]
+struct Candidate(f64, usize);
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+/// Which points to keep, by the Visvalingam-Whyatt algorithm.
+fn visvalingam_whyatt(points: &[TrackPoint], max_points: usize) -> Vec<bool> {
+    let n = points.len();
+    let mut keep = vec![true; n];
+    if n <= max_points.max(2) {
+        return keep;
+    }
+
+    // The remaining points form a linked list, so each one's neighbors can be found as points
+    // are removed.
+    let mut prev = (0 .. n).map(|i| i.saturating_sub(1)).collect::<Vec<_>>();
+    let mut next = (1 ..= n).collect::<Vec<_>>();
+    let mut areas = vec![f64::INFINITY; n];
+    let mut heap = BinaryHeap::new();
+    for i in 1 .. n - 1 {
+        areas[i] = triangle_area(&points[i - 1], &points[i], &points[i + 1]);
+        heap.push(Reverse(Candidate(areas[i], i)));
+    }
+
+    let mut remaining = n;
+    while remaining > max_points.max(2) {
+        let Some(Reverse(Candidate(area, i))) = heap.pop() else { break };
+        // Skip entries left over from before a point's area was updated.
+        if !keep[i] || area != areas[i] {
+            continue;
+        }
+        keep[i] = false;
+        remaining -= 1;
+        let (p, q) = (prev[i], next[i]);
+        next[p] = q;
+        prev[q] = p;
+        for j in [p, q] {
+            if j != 0 && j != n - 1 {
+                // A neighbor's area can't be less than that of the point just removed, so that
+                // points are removed in order of how much they matter to the shape.
+                areas[j] = triangle_area(&points[prev[j]], &points[j], &points[next[j]]).max(area);
+                heap.push(Reverse(Candidate(areas[j], j)));
+            }
+        }
+    }
+    keep
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Document;
+
+    #[test]
+    fn visvalingam_whyatt() {
+        // A straight line north with one small and one large detour east.
+        let gpx = r#"<gpx><trk><trkseg>
+            <trkpt lat="47.000" lon="-121.0"/>
+            <trkpt lat="47.001" lon="-120.9999"/>
+            <trkpt lat="47.002" lon="-120.99"/>
+            <trkpt lat="bogus" lon="-121.0"/>
+            <trkpt lat="47.003" lon="-121.0"/>
+            <trkpt lat="47.004" lon="-121.0"/>
+        </trkseg></trk></gpx>"#;
+        let simplify = |max_points| {
+            let mut doc = Document::read(gpx.as_bytes()).unwrap();
+            let trk = doc.root.child_mut(b"trk").unwrap();
+            simplify_track(trk, SimplifyAlgorithm::VisvalingamWhyatt { max_points });
+            trk.track_points().map(|pt| pt.attr(b"lat").unwrap()).collect::<Vec<_>>()
+        };
+        assert_eq!(simplify(5), ["47.000", "47.001", "47.002", "bogus", "47.003", "47.004"]);
+        assert_eq!(simplify(4), ["47.000", "47.002", "bogus", "47.003", "47.004"]);
+        assert_eq!(simplify(3), ["47.000", "47.002", "bogus", "47.004"]);
+        assert_eq!(simplify(0), ["47.000", "bogus", "47.004"]);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::geo::haversine_distance;
+use crate::point::TrackPoint;
+use crate::tree::Document;
+use quick_xml::Writer;
+use std::io::{BufRead, Write};
+
+/// Move each waypoint onto the nearest track point, if there's one within `max_distance` meters.
+/// Waypoints further than that from every track point stay where they are.
+///
+/// Waypoints come before tracks in a GPX file, so this reads the whole document first.
+pub fn snap_waypoints<R: BufRead, W: Write>(
+    source: R,
+    dest: W,
+    max_distance: f64,
+) -> anyhow::Result<()> {
+    let mut doc = Document::read(source)?;
+
+    // The original coordinate strings are kept, so snapped waypoints get exactly the same
+    // position as the track point.
+    let mut track_points = vec![];
+    for trk in doc.root.elements().filter(|el| el.name() == b"trk") {
+        for el in trk.track_points() {
+            if let (Some(point), Some(lat), Some(lon)) =
+                (TrackPoint::from_element(el), el.attr(b"lat"), el.attr(b"lon"))
+            {
+                track_points.push((point, lat, lon));
+            }
+        }
+    }
+
+    for wpt in doc.root.elements_mut().filter(|el| el.name() == b"wpt") {
+        let point = match TrackPoint::from_element(wpt) {
+            Some(point) => point,
+            None => continue,
+        };
+        let nearest = track_points.iter()
+            .map(|(p, lat, lon)| (haversine_distance(&point, p), lat, lon))
+            .filter(|(d, ..)| *d <= max_distance)
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some((distance, lat, lon)) = nearest {
+            if distance > 0. {
+                info!("snapped waypoint at lat={} lon={} {:.1} m to the track",
+                    point.lat, point.lon, distance);
+                wpt.set_attr(b"lat", lat);
+                wpt.set_attr(b"lon", lon);
+            }
+        }
+    }
+
+    doc.write(&mut Writer::new(dest))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snap(gpx: &str, max_distance: f64) -> String {
+        let mut out = vec![];
+        snap_waypoints(gpx.as_bytes(), &mut out, max_distance).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn snap_within_threshold() {
+        // The waypoint is about 3 m north of the first track point.
+        let gpx = r#"<gpx>
+    <wpt lat="47.000027" lon="-121.0"><name>viewpoint</name></wpt>
+    <trk><trkseg>
+        <trkpt lat="47.0" lon="-121.0"/>
+        <trkpt lat="47.0" lon="-121.001"/>
+    </trkseg></trk>
+</gpx>"#;
+        assert_eq!(snap(gpx, 5.), gpx.replace(r#"lat="47.000027""#, r#"lat="47.0""#));
+        assert_eq!(snap(gpx, 2.), gpx);
+    }
+}
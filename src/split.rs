@@ -0,0 +1,269 @@
+use crate::{schema, Timestamp};
+use crate::point::TrackPoint;
+use crate::tree::{Document, Element, Node};
+use quick_xml::Writer;
+use quick_xml::events::Event;
+use std::collections::BTreeMap;
+use std::io::BufRead;
+
+const DAY_MS: i64 = 86_400_000;
+
+/// Split a GPX document into one document per UTC calendar date, going by the `<time>` of each
+/// track point.
+///
+/// Track segments that span midnight are split there, with a point interpolated at midnight
+/// ending one day's segment and starting the next. Track points without a time are kept with the
+/// points around them; segments with no times at all are dropped. The metadata `<time>` of each
+/// document is set to midnight of its date. Waypoints with a time only go in the document for
+/// their date, and everything else is copied into all of them.
+///
+/// Returns pairs of the date (as `YYYY-MM-DD`) and the document, in date order.
+pub fn split_by_date<R: BufRead>(source: R) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let doc = Document::read(source)?;
+
+    // For each track, the parts of it that fall on each day.
+    let mut tracks = vec![];
+    for el in doc.root.elements().filter(|el| el.name() == b"trk") {
+        tracks.push(split_track(el));
+    }
+    let days = tracks.iter()
+        .flat_map(|t| t.keys().copied())
+        .collect::<std::collections::BTreeSet<i64>>();
+
+    let mut result = vec![];
+    for day in days {
+        let midnight = Timestamp(day * DAY_MS);
+        let mut root = doc.root.clone();
+        let mut track_index = 0;
+        root.children = filter_children(&doc.root.children, |el| {
+            match el.name() {
+                b"trk" => {
+                    let part = tracks[track_index].get(&day).cloned();
+                    track_index += 1;
+                    part
+                }
+                b"wpt" => match TrackPoint::from_element(el).and_then(|p| p.time) {
+                    Some(t) if t.0.div_euclid(DAY_MS) != day => None,
+                    _ => Some(el.clone()),
+                },
+                _ => Some(el.clone()),
+            }
+        });
+
+        let time = midnight.to_string();
+        match root.child_mut(b"metadata") {
+            Some(metadata) => metadata.set_child_text("time", &time, schema::METADATA),
+            None => {
+                let mut metadata = Element::new("metadata");
+                metadata.insert_child(Element::with_text("time", &time), schema::METADATA);
+                root.insert_child(metadata, schema::GPX);
+            }
+        }
+
+        let mut writer = Writer::new(vec![]);
+        Document { prolog: doc.prolog.clone(), root, epilog: doc.epilog.clone() }
+            .write(&mut writer)?;
+        let (year, month, day) = midnight.date();
+        result.push((format!("{:04}-{:02}-{:02}", year, month, day), writer.into_inner()));
+    }
+    Ok(result)
+}
+
+/// Make a new list of nodes, replacing or removing each element as `f` returns. Whitespace before
+/// removed elements is removed too.
+fn filter_children(nodes: &[Node], mut f: impl FnMut(&Element) -> Option<Element>) -> Vec<Node> {
+    let mut result = vec![];
+    for node in nodes {
+        match node {
+            Node::Element(el) => match f(el) {
+                Some(el) => result.push(Node::Element(el)),
+                None => {
+                    if let Some(Node::Other(Event::Text(t))) = result.last() {
+                        if t.iter().all(u8::is_ascii_whitespace) {
+                            result.pop();
+                        }
+                    }
+                }
+            },
+            other => result.push(other.clone()),
+        }
+    }
+    result
+}
+
+fn split_track(trk: &Element) -> BTreeMap<i64, Element> {
+    let segments = trk.elements()
+        .filter(|el| el.name() == b"trkseg")
+        .map(split_segment)
+        .collect::<Vec<_>>();
+    let days = segments.iter()
+        .flat_map(|s| s.keys().copied())
+        .collect::<std::collections::BTreeSet<i64>>();
+
+    let mut result = BTreeMap::new();
+    for day in days {
+        let mut seg_index = 0;
+        let mut part = trk.clone();
+        part.children = filter_children(&trk.children, |el| {
+            if el.name() != b"trkseg" {
+                return Some(el.clone());
+            }
+            seg_index += 1;
+            segments[seg_index - 1].get(&day).cloned()
+        });
+        result.insert(day, part);
+    }
+    result
+}
+
+fn split_segment(seg: &Element) -> BTreeMap<i64, Element> {
+    let mut days = BTreeMap::<i64, Vec<Node>>::new();
+    // Nodes seen since the last point, and points seen before the first one with a time.
+    let mut pending = vec![];
+    let mut leading = vec![];
+    let mut current_day = None;
+    let mut prev = None::<TrackPoint>;
+
+    for node in &seg.children {
+        let el = match node {
+            Node::Element(el) if el.name() == b"trkpt" => el,
+            other => {
+                pending.push(other.clone());
+                continue;
+            }
+        };
+        let point = TrackPoint::from_element(el);
+        let time = point.and_then(|p| p.time);
+        let day = time.map(|t| t.0.div_euclid(DAY_MS));
+        match (current_day, day) {
+            (None, Some(day)) => {
+                days.entry(day).or_default().append(&mut leading);
+            }
+            (Some(current), Some(day)) if current != day => {
+                // Crossed midnight: record a point at midnight at the end of this day's segment
+                // and the start of the next one. A point that's at midnight already starts the
+                // next one below, like any other point.
+                let midnight = Timestamp(day * DAY_MS);
+                let at_midnight = time == Some(midnight);
+                let (prev, point) = (prev.unwrap(), point.unwrap());
+                let split = if at_midnight {
+                    Some(el.clone())
+                } else if day == current + 1 {
+                    let t = (midnight.0 - prev.time.unwrap().0) as f64
+                        / (time.unwrap().0 - prev.time.unwrap().0) as f64;
+                    let mut split = prev.interpolate(&point, t);
+                    split.time = Some(midnight);
+                    Some(split.to_element("trkpt"))
+                } else {
+                    None
+                };
+                if let Some(split) = split {
+                    let indent = pending.iter()
+                        .find(|n| matches!(n, Node::Other(Event::Text(_))))
+                        .cloned();
+                    let split_days = if at_midnight { &[current][..] } else { &[current, day] };
+                    for d in split_days {
+                        let nodes = days.entry(*d).or_default();
+                        nodes.extend(indent.clone());
+                        nodes.push(Node::Element(split.clone()));
+                    }
+                }
+            }
+            _ => (),
+        }
+        if day.is_some() {
+            current_day = day;
+            prev = point;
+        }
+        let nodes = match current_day {
+            Some(day) => days.entry(day).or_default(),
+            None => &mut leading,
+        };
+        nodes.append(&mut pending);
+        nodes.push(Node::Element(el.clone()));
+    }
+
+    days.into_iter()
+        .map(|(day, mut nodes)| {
+            nodes.extend(pending.iter().cloned());
+            let mut part = seg.clone();
+            part.children = nodes;
+            (day, part)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn split_at_midnight() {
+        let gpx = r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1">
+    <metadata>
+        <name>overnight</name>
+    </metadata>
+    <trk>
+        <name>night hike</name>
+        <trkseg>
+            <trkpt lat="47.0" lon="-121.0"><time>2021-08-27T23:59:00Z</time></trkpt>
+            <trkpt lat="47.0" lon="-121.2"><time>2021-08-28T00:01:00Z</time></trkpt>
+            <trkpt lat="47.2" lon="-121.2"><time>2021-08-28T00:02:00Z</time></trkpt>
+        </trkseg>
+        <trkseg>
+            <trkpt lat="47.2" lon="-121.2"><time>2021-08-27T23:58:00Z</time></trkpt>
+            <trkpt lat="47.3" lon="-121.2"><time>2021-08-28T00:00:00Z</time></trkpt>
+            <trkpt lat="47.4" lon="-121.2"><time>2021-08-28T00:03:00Z</time></trkpt>
+        </trkseg>
+    </trk>
+</gpx>
+"#;
+        let days = split_by_date(gpx.as_bytes()).unwrap()
+            .into_iter()
+            .map(|(date, doc)| (date, String::from_utf8(doc).unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(days, vec![
+            ("2021-08-27".to_owned(), r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1">
+    <metadata>
+        <name>overnight</name>
+        <time>2021-08-27T00:00:00Z</time>
+    </metadata>
+    <trk>
+        <name>night hike</name>
+        <trkseg>
+            <trkpt lat="47.0" lon="-121.0"><time>2021-08-27T23:59:00Z</time></trkpt>
+            <trkpt lat="47" lon="-121.1"><time>2021-08-28T00:00:00Z</time></trkpt>
+        </trkseg>
+        <trkseg>
+            <trkpt lat="47.2" lon="-121.2"><time>2021-08-27T23:58:00Z</time></trkpt>
+            <trkpt lat="47.3" lon="-121.2"><time>2021-08-28T00:00:00Z</time></trkpt>
+        </trkseg>
+    </trk>
+</gpx>
+"#.to_owned()),
+            ("2021-08-28".to_owned(), r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1">
+    <metadata>
+        <name>overnight</name>
+        <time>2021-08-28T00:00:00Z</time>
+    </metadata>
+    <trk>
+        <name>night hike</name>
+        <trkseg>
+            <trkpt lat="47" lon="-121.1"><time>2021-08-28T00:00:00Z</time></trkpt>
+            <trkpt lat="47.0" lon="-121.2"><time>2021-08-28T00:01:00Z</time></trkpt>
+            <trkpt lat="47.2" lon="-121.2"><time>2021-08-28T00:02:00Z</time></trkpt>
+        </trkseg>
+        <trkseg>
+            <trkpt lat="47.3" lon="-121.2"><time>2021-08-28T00:00:00Z</time></trkpt>
+            <trkpt lat="47.4" lon="-121.2"><time>2021-08-28T00:03:00Z</time></trkpt>
+        </trkseg>
+    </trk>
+</gpx>
+"#.to_owned()),
+        ]);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::geo::haversine_distance;
+use crate::point::TrackPoint;
+use crate::tree::Element;
+
+/// How to find the clusters of points a GPS logs while standing still.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrimStationary {
+    /// How far, in meters, the points of a cluster can be from their centroid.
+    pub radius: f64,
+    /// How many seconds a cluster has to last to be trimmed.
+    pub window: f64,
+}
+
+impl TrimStationary {
+    pub const DEFAULT_WINDOW: f64 = 60.;
+}
+
+/// Remove all but the first and last points of each stationary cluster in the segments of a
+/// `<trk>` element: a run of points lasting at least `window` seconds that are all within
+/// `radius` meters of their centroid.
+///
+/// Points without a time or valid coordinates are kept, and end any cluster they're in.
+pub(crate) fn trim_track(trk: &mut Element, trim: TrimStationary) {
+    let trk_name = trk.track_name();
+    for seg in trk.elements_mut().filter(|el| el.name() == b"trkseg") {
+        let points = seg.elements()
+            .filter(|el| el.name() == b"trkpt")
+            .map(|pt| TrackPoint::from_element(pt).filter(|p| p.time.is_some()))
+            .collect::<Vec<_>>();
+        let remove = stationary_points(&points, trim);
+        let count = remove.iter().filter(|&&r| r).count();
+        if count == 0 {
+            continue;
+        }
+        info!("removed {} stationary points from track {:?}", count,
+            trk_name.as_deref().unwrap_or_default());
+        let mut i = 0;
+        seg.retain_elements(|pt| {
+            if pt.name() != b"trkpt" {
+                return true;
+            }
+            i += 1;
+            !remove[i - 1]
+        });
+    }
+}
+
+/// Which of the points are in the middle of a stationary cluster.
+fn stationary_points(points: &[Option<TrackPoint>], trim: TrimStationary) -> Vec<bool> {
+    let mut remove = vec![false; points.len()];
+    let mut start = 0;
+    while start < points.len() {
+        let first = match points[start] {
+            Some(p) => p,
+            None => {
+                start += 1;
+                continue;
+            }
+        };
+        // Grow the cluster for as long as all its points stay close to its centroid.
+        let mut end = start;
+        let (mut lat_sum, mut lon_sum) = (first.lat, first.lon);
+        while let Some(&Some(next)) = points.get(end + 1) {
+            let n = (end - start + 2) as f64;
+            let centroid = TrackPoint {
+                lat: (lat_sum + next.lat) / n,
+                lon: (lon_sum + next.lon) / n,
+                ele: None,
+                time: None,
+            };
+            let close = points[start ..= end + 1].iter()
+                .flatten()
+                .all(|p| haversine_distance(p, &centroid) <= trim.radius);
+            if !close {
+                break;
+            }
+            lat_sum += next.lat;
+            lon_sum += next.lon;
+            end += 1;
+        }
+        let last = points[end].unwrap();
+        let seconds = (last.time.unwrap().0 - first.time.unwrap().0) as f64 / 1000.;
+        if end > start && seconds >= trim.window {
+            remove[start + 1 .. end].fill(true);
+            start = end + 1;
+        } else {
+            // A cluster might still start at the next point.
+            start += 1;
+        }
+    }
+    remove
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Document;
+
+    #[test]
+    fn trim() {
+        // 0.0001 degrees of latitude is about 11 meters.
+        let gpx = r#"<gpx><trk><trkseg>
+            <trkpt lat="47.0000" lon="-121.0"><time>2021-08-27T10:00:00Z</time></trkpt>
+            <trkpt lat="47.0010" lon="-121.0"><time>2021-08-27T10:00:10Z</time></trkpt>
+            <trkpt lat="47.00101" lon="-121.0"><time>2021-08-27T10:00:40Z</time></trkpt>
+            <trkpt lat="47.00099" lon="-121.0"><time>2021-08-27T10:01:00Z</time></trkpt>
+            <trkpt lat="47.0010" lon="-121.0"><time>2021-08-27T10:01:20Z</time></trkpt>
+            <trkpt lat="47.0020" lon="-121.0"><time>2021-08-27T10:01:30Z</time></trkpt>
+            <trkpt lat="47.00201" lon="-121.0"><time>2021-08-27T10:01:40Z</time></trkpt>
+            <trkpt lat="47.00201" lon="-121.0"><time>2021-08-27T10:01:50Z</time></trkpt>
+            <trkpt lat="47.0030" lon="-121.0"><time>2021-08-27T10:02:00Z</time></trkpt>
+        </trkseg></trk></gpx>"#;
+        let mut doc = Document::read(gpx.as_bytes()).unwrap();
+        let trk = doc.root.child_mut(b"trk").unwrap();
+        trim_track(trk, TrimStationary { radius: 5., window: TrimStationary::DEFAULT_WINDOW });
+        // The first cluster lasts 70 seconds and is trimmed; the second only lasts 20.
+        let lats = trk.track_points().map(|pt| pt.attr(b"lat").unwrap()).collect::<Vec<_>>();
+        assert_eq!(lats,
+            ["47.0000", "47.0010", "47.0010", "47.0020", "47.00201", "47.00201", "47.0030"]);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{BoundingBox, Timestamp};
+use crate::geo::haversine_distance;
+use crate::point::TrackPoint;
+use crate::tree::Element;
+
+/// Information about the joined output, from [`join_gpx_with_stats`](crate::join_gpx_with_stats).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JoinStats {
+    /// Number of tracks written.
+    pub tracks: usize,
+    /// Every track segment written, in order.
+    pub segments: Vec<SegmentStats>,
+    /// What each source contributed to the output, in the same order as the sources.
+    pub files: Vec<FileStats>,
+}
+
+/// What one of the sources contributed to the joined output.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileStats {
+    /// Number of the file's tracks written.
+    pub track_count: usize,
+    /// Number of track points in those tracks.
+    pub point_count: usize,
+    /// The bounding box of those track points.
+    pub bounds: Option<BoundingBox>,
+    /// SHA-256 hash of the whole file.
+    pub sha256: [u8; 32],
+}
+
+/// A track segment in the joined output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentStats {
+    /// Index of the segment's track among all the tracks written.
+    pub track_index: usize,
+    pub segment_index_in_track: usize,
+    pub point_count: usize,
+    /// Times of the first and last points that have one.
+    pub start_time: Option<Timestamp>,
+    pub end_time: Option<Timestamp>,
+    /// Length of the segment, from the great-circle distances between consecutive points.
+    pub distance_m: f64,
+}
+
+impl JoinStats {
+    /// Total number of track points written.
+    pub fn points(&self) -> usize {
+        self.files.iter().map(|file| file.point_count).sum()
+    }
+
+    /// Total length of the track segments written, in meters.
+    pub fn distance_m(&self) -> f64 {
+        self.segments.iter().fold(0., |total, seg| total + seg.distance_m)
+    }
+
+    /// The bounding box of all the track points written.
+    pub fn bounds(&self) -> Option<BoundingBox> {
+        self.files.iter().filter_map(|file| file.bounds).reduce(|a, b| BoundingBox {
+            min_lat: a.min_lat.min(b.min_lat),
+            min_lon: a.min_lon.min(b.min_lon),
+            max_lat: a.max_lat.max(b.max_lat),
+            max_lon: a.max_lon.max(b.max_lon),
+        })
+    }
+
+    /// The earliest and latest times of the track segments written.
+    pub fn time_range(&self) -> Option<(Timestamp, Timestamp)> {
+        let start = self.segments.iter().filter_map(|seg| seg.start_time).min()?;
+        let end = self.segments.iter().filter_map(|seg| seg.end_time).max()?;
+        Some((start, end))
+    }
+
+    /// Add the segments of a track that's been written, from the given source.
+    pub(crate) fn record_track(&mut self, trk: &Element, source: usize) {
+        let mut track = TrackRecorder::start(self, source);
+        for seg in trk.elements().filter(|el| el.name() == b"trkseg") {
+            track.segment(self);
+            for el in seg.elements().filter(|el| el.name() == b"trkpt") {
+                track.point(self, el);
+            }
+        }
+    }
+}
+
+/// Records the stats of a track a piece at a time, for tracks written out as they're read.
+pub(crate) struct TrackRecorder {
+    source: usize,
+    track_index: usize,
+    segments: usize,
+    /// The last valid point of the current segment, for the distance to the next one.
+    prev: Option<TrackPoint>,
+}
+
+impl TrackRecorder {
+    pub(crate) fn start(stats: &mut JoinStats, source: usize) -> TrackRecorder {
+        let track_index = stats.tracks;
+        stats.tracks += 1;
+        if stats.files.len() <= source {
+            stats.files.resize(source + 1, FileStats::default());
+        }
+        stats.files[source].track_count += 1;
+        TrackRecorder { source, track_index, segments: 0, prev: None }
+    }
+
+    /// Start a new segment of the track.
+    pub(crate) fn segment(&mut self, stats: &mut JoinStats) {
+        stats.segments.push(SegmentStats {
+            track_index: self.track_index,
+            segment_index_in_track: self.segments,
+            point_count: 0,
+            start_time: None,
+            end_time: None,
+            distance_m: 0.,
+        });
+        self.segments += 1;
+        self.prev = None;
+    }
+
+    /// Add a `<trkpt>` to the current segment.
+    pub(crate) fn point(&mut self, stats: &mut JoinStats, el: &Element) {
+        let file = &mut stats.files[self.source];
+        let seg = stats.segments.last_mut().expect("a point outside of any segment");
+        seg.point_count += 1;
+        file.point_count += 1;
+        let point = match TrackPoint::from_element(el) {
+            Some(point) => point,
+            None => return,
+        };
+        BoundingBox::extend(&mut file.bounds, &point);
+        if let Some(prev) = self.prev {
+            seg.distance_m += haversine_distance(&prev, &point);
+        }
+        if point.time.is_some() {
+            seg.start_time = seg.start_time.or(point.time);
+            seg.end_time = point.time;
+        }
+        self.prev = Some(point);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::{JoinConfig, Timestamp};
+use crate::{gaps, schema};
+use crate::tree::Element;
+use std::collections::HashMap;
+
+/// How tracks from the files being joined are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinStrategy {
+    /// Tracks from each file are appended after those from the previous files.
+    #[default]
+    Append,
+    /// Tracks from all files are ordered by the time of their first point. Tracks without any
+    /// times go after the others, in file order.
+    Interleave,
+    /// Tracks from subsequent files replace tracks from the first file with the same name, or are
+    /// appended if there's no track by that name. Names are compared case-insensitively unless
+    /// `JoinConfig::case_sensitive_names` is set.
+    Replace,
+    /// The first tracks of all the files are combined into one track, then the second tracks,
+    /// and so on: each combined track is the one from the earliest file, with the segments of
+    /// the others' added to it in file order.
+    Zip,
+}
+
+/// Which track to keep when several have the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Keep the first track with each name.
+    FirstWins,
+    /// Keep the last track with each name.
+    LastWins,
+    /// Keep the track with each name that has the latest start time. Tracks without any times
+    /// only win if none of the others have them.
+    Newest,
+}
+
+/// Which `<name>` to keep when a track has several, which isn't valid GPX but which some loggers
+/// write anyway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateNames {
+    FirstWins,
+    LastWins,
+}
+
+/// Warn about a track with more than one `<name>`, and remove all but one of them if asked to.
+pub(crate) fn dedupe_names(trk: &mut Element, keep: Option<DuplicateNames>) {
+    let count = trk.elements().filter(|el| el.name() == b"name").count();
+    if count < 2 {
+        return;
+    }
+    warn_duplicate_names(&trk.track_name().unwrap_or_default(), count, keep);
+    let keep_index = match keep {
+        Some(DuplicateNames::FirstWins) => 0,
+        Some(DuplicateNames::LastWins) => count - 1,
+        None => return,
+    };
+    let mut index = 0;
+    trk.retain_elements(|el| {
+        if el.name() != b"name" {
+            return true;
+        }
+        index += 1;
+        index - 1 == keep_index
+    });
+}
+
+/// Warn that a track has more than one `<name>`, saying which are kept.
+pub(crate) fn warn_duplicate_names(name: &str, count: usize, keep: Option<DuplicateNames>) {
+    let which = match keep {
+        Some(DuplicateNames::FirstWins) => "keeping the first",
+        Some(DuplicateNames::LastWins) => "keeping the last",
+        None => "keeping them all",
+    };
+    warn!("track {:?} has {} <name> elements; {}", name, count, which);
+}
+
+/// A track, along with the index of the source it came from.
+pub(crate) type SourceTrack = (usize, Element);
+
+/// Put buffered tracks in the order the strategy calls for.
+pub(crate) fn order_tracks(mut tracks: Vec<SourceTrack>, config: &JoinConfig)
+    -> Vec<SourceTrack>
+{
+    if config.flatten_tracks {
+        tracks = flatten_tracks(tracks);
+    }
+    let tracks = match config.strategy {
+        JoinStrategy::Append => tracks,
+        JoinStrategy::Interleave => {
+            // Sort is stable, so ties (including tracks without times) stay in file order.
+            tracks.sort_by_key(|(_, trk)| match trk.track_start_time() {
+                Some(time) => (false, time),
+                None => (true, Timestamp(0)),
+            });
+            tracks
+        }
+        JoinStrategy::Replace => {
+            let mut result: Vec<SourceTrack> = vec![];
+            // Where the first of the first file's tracks with each name is.
+            let mut first_file_names = HashMap::new();
+            for (source, trk) in tracks {
+                if source == 0 {
+                    if let Some(name) = name_key(&trk, config) {
+                        first_file_names.entry(name).or_insert(result.len());
+                    }
+                    result.push((source, trk));
+                    continue;
+                }
+                // Replacements are matched against the first file's tracks as they originally were.
+                let existing = name_key(&trk, config)
+                    .and_then(|name| first_file_names.get(&name).copied());
+                match existing {
+                    Some(i) => result[i] = (source, trk),
+                    None => result.push((source, trk)),
+                }
+            }
+            result
+        }
+        JoinStrategy::Zip => zip_tracks(tracks),
+    };
+    let mut tracks = match config.merge_strategy {
+        Some(merge) => merge_tracks(tracks, merge, config),
+        None => tracks,
+    };
+    if let Some(max_gap) = config.max_gap_interpolate {
+        gaps::fill_track_gaps(&mut tracks, max_gap);
+    }
+    if config.reverse_tracks {
+        tracks.reverse();
+    }
+    tracks
+}
+
+/// Move the segments of each source's tracks into its first track.
+fn flatten_tracks(tracks: Vec<SourceTrack>) -> Vec<SourceTrack> {
+    let mut result: Vec<SourceTrack> = vec![];
+    for (source, trk) in tracks {
+        match result.last_mut() {
+            Some((last_source, first)) if *last_source == source => {
+                for seg in trk.elements().filter(|el| el.name() == b"trkseg") {
+                    first.insert_child(seg.clone(), schema::TRK);
+                }
+            }
+            _ => result.push((source, trk)),
+        }
+    }
+    result
+}
+
+/// Combine the nth tracks of each source. Sources with fewer tracks than the others are padded
+/// with empty ones, which add nothing to the combined tracks.
+fn zip_tracks(tracks: Vec<SourceTrack>) -> Vec<SourceTrack> {
+    let mut by_source: Vec<(usize, Vec<Element>)> = vec![];
+    for (source, trk) in tracks {
+        match by_source.last_mut() {
+            Some((last, tracks)) if *last == source => tracks.push(trk),
+            _ => by_source.push((source, vec![trk])),
+        }
+    }
+    let counts = by_source.iter().map(|(_, tracks)| tracks.len()).collect::<Vec<_>>();
+    if counts.windows(2).any(|pair| pair[0] != pair[1]) {
+        warn!("the files have different numbers of tracks ({}); the ones with fewer are padded \
+            with empty tracks",
+            counts.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(", "));
+    }
+    let count = counts.iter().copied().max().unwrap_or(0);
+    let mut by_source = by_source.into_iter()
+        .map(|(source, tracks)| (source, tracks.into_iter()))
+        .collect::<Vec<_>>();
+    let mut result: Vec<SourceTrack> = vec![];
+    for _ in 0 .. count {
+        let mut combined: Option<SourceTrack> = None;
+        for (source, tracks) in &mut by_source {
+            // A source that's run out of tracks is padded with an empty one.
+            let trk = match tracks.next() {
+                Some(trk) => trk,
+                None => continue,
+            };
+            match combined {
+                None => combined = Some((*source, trk)),
+                Some((_, ref mut combined)) => {
+                    for seg in trk.elements().filter(|el| el.name() == b"trkseg") {
+                        combined.insert_child(seg.clone(), schema::TRK);
+                    }
+                }
+            }
+        }
+        result.extend(combined);
+    }
+    result
+}
+
+/// Keep only one track with each name. The one kept goes where the first one with that name was.
+fn merge_tracks(tracks: Vec<SourceTrack>, merge: MergeStrategy, config: &JoinConfig)
+    -> Vec<SourceTrack>
+{
+    let mut result: Vec<SourceTrack> = vec![];
+    // Where the track kept with each name is.
+    let mut names = HashMap::new();
+    for (source, trk) in tracks {
+        let name = name_key(&trk, config);
+        let existing = name.as_ref().and_then(|name| names.get(name).copied());
+        match existing {
+            None => {
+                if let Some(name) = name {
+                    names.insert(name, result.len());
+                }
+                result.push((source, trk));
+            }
+            Some(i) => {
+                let replace = match merge {
+                    MergeStrategy::FirstWins => false,
+                    MergeStrategy::LastWins => true,
+                    MergeStrategy::Newest => trk.track_start_time() > result[i].1.track_start_time(),
+                };
+                if replace {
+                    result[i] = (source, trk);
+                }
+            }
+        }
+    }
+    result
+}
+
+/// The name to match tracks up by.
+fn name_key(trk: &Element, config: &JoinConfig) -> Option<String> {
+    trk.track_name().map(|name| {
+        if config.case_sensitive_names { name } else { name.to_lowercase() }
+    })
+}
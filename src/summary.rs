@@ -0,0 +1,166 @@
+use crate::Timestamp;
+use crate::geo::haversine_distance;
+use crate::point::TrackPoint;
+use crate::tree::{Document, Element};
+use std::fmt;
+use std::io::BufRead;
+
+/// Totals for the tracks in a GPX file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub tracks: usize,
+    pub points: usize,
+    /// Total length of the track segments, in meters.
+    pub distance: f64,
+    /// The earliest and latest track point times.
+    pub time_range: Option<(Timestamp, Timestamp)>,
+}
+
+impl Summary {
+    pub fn of<R: BufRead>(source: R) -> anyhow::Result<Summary> {
+        let mut summary = Summary { tracks: 0, points: 0, distance: 0., time_range: None };
+        for track in TrackSummary::all(source)? {
+            summary.tracks += 1;
+            summary.points += track.points;
+            summary.distance += track.distance;
+            summary.time_range = match (summary.time_range, track.time_range) {
+                (Some((start, end)), Some((track_start, track_end))) => {
+                    Some((start.min(track_start), end.max(track_end)))
+                }
+                (range, track_range) => range.or(track_range),
+            };
+        }
+        Ok(summary)
+    }
+}
+
+/// Formats as, for example, `3 tracks, 2847 pts, 42.3km, 2021-08-27`. The date is a range if the
+/// track points span more than one day, and is left out if they have no times.
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = if self.tracks == 1 { "" } else { "s" };
+        write!(f, "{} track{}, {} pts, {:.1}km", self.tracks, plural, self.points,
+            self.distance / 1000.)?;
+        if let Some(range) = self.time_range {
+            write!(f, ", {}", DateRange(range))?;
+        }
+        Ok(())
+    }
+}
+
+/// Totals for one track of a GPX file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackSummary {
+    pub name: Option<String>,
+    pub points: usize,
+    /// Total length of the track's segments, in meters.
+    pub distance: f64,
+    /// The earliest and latest track point times.
+    pub time_range: Option<(Timestamp, Timestamp)>,
+}
+
+impl TrackSummary {
+    /// Summarize each of the tracks in a GPX file, in order.
+    pub fn all<R: BufRead>(source: R) -> anyhow::Result<Vec<TrackSummary>> {
+        let doc = Document::read(source)?;
+        Ok(doc.root.elements().filter(|el| el.name() == b"trk").map(TrackSummary::of).collect())
+    }
+
+    pub(crate) fn of(trk: &Element) -> TrackSummary {
+        let mut summary = TrackSummary {
+            name: trk.track_name(),
+            points: 0,
+            distance: 0.,
+            time_range: None,
+        };
+        for seg in trk.elements().filter(|el| el.name() == b"trkseg") {
+            let mut prev = None::<TrackPoint>;
+            for el in seg.elements().filter(|el| el.name() == b"trkpt") {
+                summary.points += 1;
+                let point = match TrackPoint::from_element(el) {
+                    Some(point) => point,
+                    None => continue,
+                };
+                if let Some(prev) = prev {
+                    summary.distance += haversine_distance(&prev, &point);
+                }
+                if let Some(t) = point.time {
+                    summary.time_range = Some(match summary.time_range {
+                        None => (t, t),
+                        Some((start, end)) => (start.min(t), end.max(t)),
+                    });
+                }
+                prev = Some(point);
+            }
+        }
+        summary
+    }
+}
+
+/// Formats as, for example, `"Morning Ride" 2021-08-27 42.3km`, with the date left out if the
+/// track has no times, and a range if it spans more than one day.
+impl fmt::Display for TrackSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.name.as_deref().unwrap_or_default())?;
+        if let Some(range) = self.time_range {
+            write!(f, " {}", DateRange(range))?;
+        }
+        write!(f, " {:.1}km", self.distance / 1000.)
+    }
+}
+
+/// The dates of a time range, as one date if it's all on the same day.
+struct DateRange((Timestamp, Timestamp));
+
+impl fmt::Display for DateRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let date = |t: Timestamp| {
+            let (year, month, day) = t.date();
+            format!("{:04}-{:02}-{:02}", year, month, day)
+        };
+        let (start, end) = (date((self.0).0), date((self.0).1));
+        if start == end {
+            write!(f, "{}", start)
+        } else {
+            write!(f, "{} to {}", start, end)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let gpx = r#"<gpx>
+    <trk><trkseg>
+        <trkpt lat="47.0" lon="-121.0"><time>2021-08-27T23:00:00Z</time></trkpt>
+        <trkpt lat="47.1" lon="-121.0"><time>2021-08-27T23:30:00Z</time></trkpt>
+    </trkseg><trkseg>
+        <trkpt lat="48.0" lon="-121.0"/>
+        <trkpt lat="48.1" lon="-121.0"/>
+    </trkseg></trk>
+</gpx>"#;
+        assert_eq!(Summary::of(gpx.as_bytes()).unwrap().to_string(),
+            "1 track, 4 pts, 22.2km, 2021-08-27");
+        let gpx = gpx.replace("2021-08-27T23:30", "2021-08-28T00:30");
+        assert_eq!(Summary::of(gpx.as_bytes()).unwrap().to_string(),
+            "1 track, 4 pts, 22.2km, 2021-08-27 to 2021-08-28");
+        assert_eq!(Summary::of("<gpx/>".as_bytes()).unwrap().to_string(), "0 tracks, 0 pts, 0.0km");
+    }
+
+    #[test]
+    fn track_summaries() {
+        let gpx = r#"<gpx>
+    <trk><name>Morning "Ride"</name><trkseg>
+        <trkpt lat="47.0" lon="-121.0"><time>2021-08-27T23:00:00Z</time></trkpt>
+        <trkpt lat="47.1" lon="-121.0"><time>2021-08-27T23:30:00Z</time></trkpt>
+    </trkseg></trk>
+    <trk><trkseg><trkpt lat="48.0" lon="-121.0"/></trkseg></trk>
+</gpx>"#;
+        let tracks = TrackSummary::all(gpx.as_bytes()).unwrap();
+        let tracks = tracks.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert_eq!(tracks, [r#""Morning \"Ride\"" 2021-08-27 11.1km"#, r#""" 0.0km"#]);
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+
+/// A point in time, as milliseconds since the Unix epoch (UTC).
+///
+/// GPX timestamps are ISO 8601 / XML Schema `dateTime` values like `2021-08-27T18:59:24.070Z`;
+/// this is just enough date handling to parse, compare, and print those.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub i64);
+
+impl Timestamp {
+    /// Parse an ISO 8601 date and time. A missing time zone is taken to mean UTC.
+    pub fn parse(s: &str) -> Option<Timestamp> {
+        let s = s.trim();
+        let b = s.as_bytes();
+        if b.len() < 19 || !matches!(b[10], b'T' | b't') {
+            return None;
+        }
+        let (year, month, day) = parse_date(&s[0 .. 10])?;
+        if b[13] != b':' || b[16] != b':' {
+            return None;
+        }
+        let hour = digits(&b[11 .. 13])?;
+        let minute = digits(&b[14 .. 16])?;
+        let second = digits(&b[17 .. 19])?;
+        if hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+
+        let mut rest = &b[19 ..];
+        let mut millis = 0;
+        if let Some(b'.') = rest.first() {
+            let frac_len = rest[1 ..].iter().take_while(|c| c.is_ascii_digit()).count();
+            if frac_len == 0 {
+                return None;
+            }
+            let mut scale = 100;
+            for c in &rest[1 ..= frac_len.min(3)] {
+                millis += i64::from(c - b'0') * scale;
+                scale /= 10;
+            }
+            rest = &rest[1 + frac_len ..];
+        }
+
+        let offset_minutes = match rest {
+            [] | [b'Z'] | [b'z'] => 0,
+            [sign @ b'+', h1, h2, b':', m1, m2] | [sign @ b'-', h1, h2, b':', m1, m2]
+                | [sign @ b'+', h1, h2, m1, m2] | [sign @ b'-', h1, h2, m1, m2] =>
+            {
+                let h = digits(&[*h1, *h2])?;
+                let m = digits(&[*m1, *m2])?;
+                if h > 23 || m > 59 {
+                    return None;
+                }
+                let offset = h * 60 + m;
+                if *sign == b'-' { -offset } else { offset }
+            }
+            _ => return None,
+        };
+
+        let days = days_from_civil(year, month, day);
+        let seconds = days * 86400 + hour * 3600 + minute * 60 + second.min(59) - offset_minutes * 60;
+        Some(Timestamp(seconds * 1000 + millis))
+    }
+
+    /// Parse a `YYYY-MM-DD` date, giving midnight UTC at its start.
+    pub fn parse_date(s: &str) -> Option<Timestamp> {
+        let (year, month, day) = parse_date(s.trim())?;
+        Some(Timestamp::from_date(year, month, day))
+    }
+
+    /// Midnight UTC at the start of the given date.
+    pub fn from_date(year: i64, month: i64, day: i64) -> Timestamp {
+        Timestamp(days_from_civil(year, month, day) * 86_400_000)
+    }
+
+    /// The UTC calendar date as (year, month, day).
+    pub fn date(self) -> (i64, i64, i64) {
+        civil_from_days(self.0.div_euclid(86_400_000))
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (year, month, day) = self.date();
+        let ms_of_day = self.0.rem_euclid(86_400_000);
+        let secs = ms_of_day / 1000;
+        write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year, month, day, secs / 3600, secs / 60 % 60, secs % 60)?;
+        if ms_of_day % 1000 != 0 {
+            write!(f, ".{:03}", ms_of_day % 1000)?;
+        }
+        f.write_str("Z")
+    }
+}
+
+/// Parse a `YYYY-MM-DD` date.
+fn parse_date(s: &str) -> Option<(i64, i64, i64)> {
+    let b = s.as_bytes();
+    if b.len() != 10 || b[4] != b'-' || b[7] != b'-' {
+        return None;
+    }
+    let year = digits(&b[0 .. 4])?;
+    let month = digits(&b[5 .. 7])?;
+    let day = digits(&b[8 .. 10])?;
+    if !(1 ..= 12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some((year, month, day))
+}
+
+fn digits(b: &[u8]) -> Option<i64> {
+    let mut n = 0;
+    for c in b {
+        if !c.is_ascii_digit() {
+            return None;
+        }
+        n = n * 10 + i64::from(c - b'0');
+    }
+    Some(n)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// These two are Howard Hinnant's algorithms for converting between the proleptic Gregorian
+// calendar and a count of days since 1970-01-01.
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format() {
+        let t = Timestamp::parse("2021-08-27T18:59:24.070Z").unwrap();
+        assert_eq!(t.0, 1_630_090_764_070);
+        assert_eq!(t.to_string(), "2021-08-27T18:59:24.070Z");
+        assert_eq!(Timestamp::parse("2021-08-27T11:59:24.07-07:00"), Some(t));
+        assert_eq!(Timestamp::parse("2021-08-27T18:59:24").unwrap().to_string(),
+            "2021-08-27T18:59:24Z");
+        assert_eq!(Timestamp::parse("1969-12-31T23:59:59Z"), Some(Timestamp(-1000)));
+        assert_eq!(Timestamp::from_date(2020, 2, 29).date(), (2020, 2, 29));
+        assert_eq!(Timestamp::parse_date("2021-08-27"), Timestamp::parse("2021-08-27T00:00:00Z"));
+        assert_eq!(Timestamp::parse_date("2021-08-32"), None);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for s in &["", "2021-08-27", "2021-08-27 18:59:24Z", "2021-02-29T00:00:00Z",
+            "2021-08-27T24:00:00Z", "2021-08-27T18:59:24.Z", "2021-08-27T18:59:24+7"]
+        {
+            assert_eq!(Timestamp::parse(s), None, "{:?}", s);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::Timestamp;
+use crate::point::TrackPoint;
+use crate::schema;
+use crate::tree::Element;
+
+/// Helpers for looking at a buffered `<trk>` element.
+impl Element {
+    /// The track's name, with surrounding whitespace removed.
+    pub fn track_name(&self) -> Option<String> {
+        self.child(b"name").map(|name| name.text().trim().to_owned())
+    }
+
+    /// All the `<trkpt>` elements in the track, across all segments.
+    pub fn track_points(&self) -> impl Iterator<Item = &Element> {
+        self.elements()
+            .filter(|el| el.name() == b"trkseg")
+            .flat_map(|seg| seg.elements().filter(|el| el.name() == b"trkpt"))
+    }
+
+    /// The time of the first track point that has one.
+    pub fn track_start_time(&self) -> Option<Timestamp> {
+        self.track_points().find_map(|pt| TrackPoint::from_element(pt).and_then(|p| p.time))
+    }
+
+    /// The time of the last track point that has one.
+    pub fn track_end_time(&self) -> Option<Timestamp> {
+        self.track_points()
+            .filter_map(|pt| TrackPoint::from_element(pt).and_then(|p| p.time))
+            .last()
+    }
+
+    /// Shorten the track's names to at most `max_chars` characters, not counting the whitespace
+    /// around them, adding an ellipsis to the ones that were shortened if `ellipsis` is set.
+    pub fn truncate_names(&mut self, max_chars: usize, ellipsis: bool) {
+        for name in self.elements_mut().filter(|el| el.name() == b"name") {
+            let text = name.text();
+            if text.trim().chars().count() <= max_chars {
+                continue;
+            }
+            let mut short = text.trim().chars().take(max_chars).collect::<String>();
+            if ellipsis {
+                short.push('…');
+            }
+            name.replace_text(&short);
+        }
+    }
+
+    /// Keep only the first `n` points of each of the track's segments.
+    pub fn keep_first_points(&mut self, n: usize) {
+        for seg in self.elements_mut().filter(|el| el.name() == b"trkseg") {
+            let mut count = 0;
+            seg.retain_elements(|el| {
+                if el.name() != b"trkpt" {
+                    return true;
+                }
+                count += 1;
+                count <= n
+            });
+        }
+    }
+
+    /// Leave out the points at the start of each of the track's segments that are before `start`:
+    /// all of them up to the first at or after it. Segments without any times are left alone.
+    pub fn drop_points_before(&mut self, start: Timestamp) {
+        for seg in self.elements_mut().filter(|el| el.name() == b"trkseg") {
+            let times = seg.elements()
+                .filter(|el| el.name() == b"trkpt")
+                .map(|pt| TrackPoint::from_element(pt).and_then(|p| p.time))
+                .collect::<Vec<_>>();
+            if times.iter().all(Option::is_none) {
+                continue;
+            }
+            let keep_from = times.iter().position(|t| t.is_some_and(|t| t >= start))
+                .unwrap_or(times.len());
+            let mut index = 0;
+            seg.retain_elements(|el| {
+                if el.name() != b"trkpt" {
+                    return true;
+                }
+                index += 1;
+                index > keep_from
+            });
+        }
+    }
+
+    /// Reverse the order of the points in each of the track's segments, leaving everything else
+    /// in them where it is.
+    pub fn reverse_points(&mut self) {
+        for seg in self.elements_mut().filter(|el| el.name() == b"trkseg") {
+            let mut points = seg.elements()
+                .filter(|el| el.name() == b"trkpt")
+                .cloned()
+                .collect::<Vec<_>>();
+            for pt in seg.elements_mut().filter(|el| el.name() == b"trkpt") {
+                *pt = points.pop().unwrap();
+            }
+        }
+    }
+
+    /// Move the points of all the track's segments into the first one, in order, and remove the
+    /// others. Their `<extensions>`, if any, are dropped.
+    pub fn merge_segments(&mut self) {
+        let mut segments = self.elements().filter(|el| el.name() == b"trkseg");
+        let mut merged = match segments.next() {
+            Some(first) => first.clone(),
+            None => return,
+        };
+        let mut count = 1;
+        for seg in segments {
+            count += 1;
+            for pt in seg.elements().filter(|el| el.name() == b"trkpt") {
+                merged.insert_child(pt.clone(), schema::TRKSEG);
+            }
+        }
+        if count > 1 {
+            self.replace_children(b"trkseg", vec![merged]);
+        }
+    }
+
+    /// Remove the track's segments that have fewer than `min_points` points. Returns how many were
+    /// removed.
+    pub fn drop_sparse_segments(&mut self, min_points: usize) -> usize {
+        let mut dropped = 0;
+        self.retain_elements(|el| {
+            let keep = el.name() != b"trkseg"
+                || el.elements().filter(|pt| pt.name() == b"trkpt").count() >= min_points;
+            dropped += !keep as usize;
+            keep
+        });
+        dropped
+    }
+}
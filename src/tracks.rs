@@ -0,0 +1,217 @@
+use crate::tree::{is_whitespace, read_event};
+use anyhow::bail;
+use quick_xml::{Reader, Writer};
+use quick_xml::events::{BytesEnd, BytesText, Event};
+use std::io::{BufRead, Write};
+
+/// The XML of a complete `<trk>` element, from its start tag to its end tag, for working with
+/// tracks without parsing them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackXml(pub Vec<u8>);
+
+impl AsRef<[u8]> for TrackXml {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Read the `<trk>` elements of a GPX document, in order, leaving out everything else.
+///
+/// Each track's XML is as it was in the document, including the whitespace inside it, except for
+/// CDATA sections, which are kept but may have their content escaped differently.
+///
+/// ```
+/// let gpx = "<gpx>\n  <wpt lat=\"1\" lon=\"2\"/>\n  <trk><name>a</name></trk>\n  <trk/>\n</gpx>";
+/// let tracks = gpxjoin::read_gpx_tracks(gpx.as_bytes()).unwrap();
+/// assert_eq!(tracks.len(), 2);
+/// assert_eq!(tracks[0].0, b"<trk><name>a</name></trk>");
+/// assert_eq!(tracks[1].0, b"<trk/>");
+/// ```
+pub fn read_gpx_tracks<R: BufRead>(source: R) -> anyhow::Result<Vec<TrackXml>> {
+    let mut reader = Reader::from_reader(source);
+    let mut buf = vec![];
+    let mut tracks = vec![];
+    let mut depth = 0;
+    // The XML of the track being read.
+    let mut track: Option<Writer<Vec<u8>>> = None;
+    loop {
+        let evt = read_event(&mut reader, &mut buf)?;
+        match evt {
+            Event::Eof => break,
+            Event::Start(ref start) if depth == 1 && start.name() == b"trk" => {
+                track = Some(Writer::new(vec![]));
+            }
+            Event::Empty(ref empty) if depth == 1 && empty.name() == b"trk" => {
+                let mut writer = Writer::new(vec![]);
+                writer.write_event(&evt)?;
+                tracks.push(TrackXml(writer.into_inner()));
+            }
+            _ => (),
+        }
+        match evt {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => (),
+        }
+        if let Some(ref mut writer) = track {
+            writer.write_event(&evt)?;
+            if depth == 1 {
+                tracks.push(TrackXml(track.take().unwrap().into_inner()));
+            }
+        }
+        buf.clear();
+    }
+    Ok(tracks)
+}
+
+/// Write a GPX document made of a header and some tracks: the header's root element and
+/// everything in it, then the tracks, then the end of the root element.
+///
+/// The header can stop before the root element's end tag, or be a whole document, like one
+/// written by [`write_header`](crate::write_header), in which case anything after the end tag is
+/// copied too. The tracks are indented like the header's first top-level element.
+///
+/// ```
+/// let header = "<gpx>\n  <metadata/>\n";
+/// let tracks = gpxjoin::read_gpx_tracks("<gpx><trk/><trk/></gpx>".as_bytes()).unwrap();
+/// let mut out = vec![];
+/// gpxjoin::write_gpx_with_tracks(header.as_bytes(), tracks, &mut out).unwrap();
+/// assert_eq!(out, b"<gpx>\n  <metadata/>\n  <trk/>\n  <trk/>\n</gpx>\n");
+/// ```
+pub fn write_gpx_with_tracks<H: BufRead, W: Write>(
+    header: H,
+    tracks: impl IntoIterator<Item = TrackXml>,
+    dest: W,
+) -> anyhow::Result<()> {
+    let mut reader = Reader::from_reader(header);
+    let mut writer = Writer::new(dest);
+    let mut buf = vec![];
+    let mut depth = 0;
+    let mut root = None;
+    // The whitespace before the root element's first child, and whitespace in the root element
+    // that hasn't been written yet, which goes before its end tag if nothing else follows.
+    let mut indent = None;
+    let mut space = None;
+    // Whether the header has the root element's end tag, with the rest of the document after it.
+    let mut closed = false;
+    loop {
+        let evt = read_event(&mut reader, &mut buf)?;
+        match evt {
+            Event::Eof => break,
+            Event::Text(ref text) if depth == 1 && is_whitespace(text) => {
+                if let Some(space) = space.replace(text.to_vec()) {
+                    writer.write_event(Event::Text(BytesText::from_escaped(space)))?;
+                }
+                buf.clear();
+                continue;
+            }
+            Event::End(_) if depth == 1 => {
+                closed = true;
+                break;
+            }
+            Event::Empty(ref start) if depth == 0 => {
+                writer.write_event(Event::Start(start.to_owned()))?;
+                root = Some(start.name().to_vec());
+                depth = 1;
+                closed = true;
+                break;
+            }
+            Event::Start(ref start) if depth == 0 => root = Some(start.name().to_vec()),
+            Event::Start(_) | Event::Empty(_) | Event::Comment(_) if depth == 1 => {
+                indent.get_or_insert_with(|| space.clone().unwrap_or_default());
+            }
+            _ => (),
+        }
+        match evt {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => (),
+        }
+        if let Some(space) = space.take() {
+            writer.write_event(Event::Text(BytesText::from_escaped(space)))?;
+        }
+        writer.write_event(evt)?;
+        buf.clear();
+    }
+    let root = match root {
+        Some(root) if depth == 1 => root,
+        _ => bail!("the header doesn't have a root element for the tracks to go in"),
+    };
+    for track in tracks {
+        writer.write(indent.as_deref().unwrap_or_default())?;
+        writer.write(&track.0)?;
+    }
+    if let Some(space) = space.take() {
+        writer.write_event(Event::Text(BytesText::from_escaped(space)))?;
+    }
+    writer.write_event(Event::End(BytesEnd::owned(root)))?;
+    if !closed {
+        writer.write(b"\n")?;
+        return Ok(());
+    }
+    loop {
+        buf.clear();
+        match read_event(&mut reader, &mut buf)? {
+            Event::Eof => return Ok(()),
+            evt => writer.write_event(evt)?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn read_tracks() {
+        let gpx = r#"<?xml version="1.0"?>
+<gpx>
+    <metadata><name>not a track</name></metadata>
+    <trk>
+        <name>Tom &amp; <![CDATA[Jerry]]></name>
+        <trkseg><trkpt lat="1" lon="2"/></trkseg>
+        <extensions><trk/></extensions>
+    </trk>
+    <rte><name>also not a track</name></rte>
+</gpx>"#;
+        let tracks = read_gpx_tracks(gpx.as_bytes()).unwrap();
+        let tracks = tracks.iter().map(|t| String::from_utf8_lossy(&t.0)).collect::<Vec<_>>();
+        assert_eq!(tracks, [r#"<trk>
+        <name>Tom &amp; <![CDATA[Jerry]]></name>
+        <trkseg><trkpt lat="1" lon="2"/></trkseg>
+        <extensions><trk/></extensions>
+    </trk>"#]);
+        assert!(read_gpx_tracks("<gpx><trk></gpx>".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn write_tracks() {
+        let write = |header: &str, tracks: &[&str]| {
+            let mut out = vec![];
+            let tracks = tracks.iter().map(|t| TrackXml(t.as_bytes().to_vec()));
+            write_gpx_with_tracks(header.as_bytes(), tracks, &mut out).map(|_| {
+                String::from_utf8(out).unwrap()
+            })
+        };
+        let gpx = "<?xml version=\"1.0\"?>\n<gpx version=\"1.1\">\n    <metadata/>\n    \
+            <trk><name>a</name></trk>\n    <trk/>\n</gpx>\n<!-- trailing -->\n";
+
+        // Splitting a document into its header and tracks and putting them back together gives
+        // the same document.
+        let mut header = vec![];
+        crate::write_header(gpx.as_bytes(), &mut header).unwrap();
+        let tracks = read_gpx_tracks(gpx.as_bytes()).unwrap();
+        let mut out = vec![];
+        write_gpx_with_tracks(&header[..], tracks, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), gpx.replace("<!-- trailing -->\n", ""));
+
+        assert_eq!(write(gpx, &["<trk/>"]).unwrap(),
+            gpx.replace("<trk/>\n", "<trk/>\n    <trk/>\n"));
+        assert_eq!(write("<gpx version=\"1.1\">", &["<trk/>", "<trk/>"]).unwrap(),
+            "<gpx version=\"1.1\"><trk/><trk/></gpx>\n");
+        assert_eq!(write("<gpx/>\n", &["<trk/>"]).unwrap(), "<gpx><trk/></gpx>\n");
+        assert!(write("<?xml version=\"1.0\"?>\n", &["<trk/>"]).is_err());
+        assert!(write("<gpx><metadata>", &["<trk/>"]).is_err());
+    }
+}
@@ -0,0 +1,410 @@
+use quick_xml::{Reader, Writer};
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+use std::io::{BufRead, Write};
+
+/// An XML element held in memory along with everything inside it, for when an element needs to be
+/// looked at as a whole before it can be written out.
+///
+/// The original events are kept as-is, so writing out an unmodified element reproduces the input
+/// exactly.
+#[derive(Debug, Clone)]
+pub(crate) struct Element {
+    pub start: BytesStart<'static>,
+    pub children: Vec<Node>,
+    /// None if this is a self-closing element.
+    pub end: Option<BytesEnd<'static>>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Node {
+    Element(Element),
+    /// Anything else: text, CDATA, comments, processing instructions.
+    Other(Event<'static>),
+}
+
+impl Element {
+    pub fn new(name: &str) -> Element {
+        Element {
+            start: BytesStart::owned_name(name.as_bytes().to_vec()),
+            children: vec![],
+            end: Some(BytesEnd::owned(name.as_bytes().to_vec())),
+        }
+    }
+
+    pub fn with_text(name: &str, text: &str) -> Element {
+        let mut el = Element::new(name);
+        el.set_text(text);
+        el
+    }
+
+    pub fn name(&self) -> &[u8] {
+        self.start.name()
+    }
+
+    pub fn rename(&mut self, name: &str) {
+        self.start.set_name(name.as_bytes());
+        if self.end.is_some() {
+            self.end = Some(BytesEnd::owned(name.as_bytes().to_vec()));
+        }
+    }
+
+    /// Get the unescaped value of an attribute.
+    pub fn attr(&self, key: &[u8]) -> Option<String> {
+        self.start.attributes()
+            .filter_map(Result::ok)
+            .find(|a| a.key == key)
+            .map(|a| {
+                let value = a.unescaped_value()
+                    .map(|v| v.into_owned())
+                    .unwrap_or_else(|_| a.value.into_owned());
+                String::from_utf8_lossy(&value).into_owned()
+            })
+    }
+
+    /// Set the value of an attribute, keeping its position if it's already present, or adding it
+    /// to the end otherwise.
+    pub fn set_attr(&mut self, key: &[u8], value: &str) {
+        let escaped = quick_xml::escape::escape(value.as_bytes());
+        let mut start = BytesStart::owned_name(self.name().to_vec());
+        let mut found = false;
+        for attr in self.start.attributes().filter_map(Result::ok) {
+            if attr.key == key {
+                start.push_attribute((key, &*escaped));
+                found = true;
+            } else {
+                start.push_attribute((attr.key, &*attr.value));
+            }
+        }
+        if !found {
+            start.push_attribute((key, &*escaped));
+        }
+        self.start = start;
+    }
+
+    /// Iterate over the child elements.
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(el) => Some(el),
+            Node::Other(_) => None,
+        })
+    }
+
+    pub fn elements_mut(&mut self) -> impl Iterator<Item = &mut Element> {
+        self.children.iter_mut().filter_map(|node| match node {
+            Node::Element(el) => Some(el),
+            Node::Other(_) => None,
+        })
+    }
+
+    /// Get the first child element with the given name.
+    pub fn child(&self, name: &[u8]) -> Option<&Element> {
+        self.elements().find(|el| el.name() == name)
+    }
+
+    pub fn child_mut(&mut self, name: &[u8]) -> Option<&mut Element> {
+        self.elements_mut().find(|el| el.name() == name)
+    }
+
+    /// Insert a child element at the position given by `order`, a list of element names in the
+    /// order they should appear (such as the order the GPX schema requires). Elements with names
+    /// not in the list are treated as coming after all the others.
+    ///
+    /// The new element is indented the same way as the existing ones.
+    pub fn insert_child(&mut self, child: Element, order: &[&[u8]]) {
+        let rank = |name: &[u8]| order.iter().position(|n| *n == name).unwrap_or(order.len());
+        let child_rank = rank(child.name());
+        let indent = self.indent();
+
+        // Insert before the first element that should come after (and the whitespace preceding
+        // it), or else after the last element.
+        let later = self.children.iter()
+            .position(|n| matches!(n, Node::Element(el) if rank(el.name()) > child_rank));
+        let index = match later {
+            Some(i) if i > 0 && matches!(&self.children[i - 1],
+                Node::Other(Event::Text(t)) if is_whitespace(t)) => i - 1,
+            Some(i) => i,
+            None => self.children.iter()
+                .rposition(|n| matches!(n, Node::Element(_)))
+                .map(|i| i + 1)
+                .unwrap_or(self.children.len()),
+        };
+
+        let mut new = vec![Node::Element(child)];
+        if let Some(indent) = indent {
+            new.insert(0, Node::Other(Event::Text(indent)));
+        }
+        self.children.splice(index .. index, new);
+        if self.end.is_none() {
+            self.end = Some(BytesEnd::owned(self.name().to_vec()));
+        }
+    }
+
+    /// Put the child elements in the order given by `order`, like
+    /// [`insert_child`](Element::insert_child) does, keeping elements with the same rank in the
+    /// order they were in. Each element takes the whitespace and comments before it along with
+    /// it. Returns whether anything moved.
+    pub fn sort_children(&mut self, order: &[&[u8]]) -> bool {
+        let rank = |name: &[u8]| order.iter().position(|n| *n == name).unwrap_or(order.len());
+        let mut groups = vec![];
+        let mut group = vec![];
+        for node in self.children.drain(..) {
+            let el_rank = match node {
+                Node::Element(ref el) => Some(rank(el.name())),
+                Node::Other(_) => None,
+            };
+            group.push(node);
+            if let Some(el_rank) = el_rank {
+                groups.push((el_rank, std::mem::take(&mut group)));
+            }
+        }
+        let sorted = groups.windows(2).all(|pair| pair[0].0 <= pair[1].0);
+        groups.sort_by_key(|&(rank, _)| rank);
+        self.children = groups.into_iter().flat_map(|(_, nodes)| nodes).chain(group).collect();
+        !sorted
+    }
+
+    /// Replace all the child elements with the given name with new ones, which go where the first
+    /// of the old ones was, indented the same way as the existing elements.
+    pub fn replace_children(&mut self, name: &[u8], new: Vec<Element>) {
+        let indent = self.indent();
+        let mut new = Some(new);
+        let mut children = Vec::with_capacity(self.children.len());
+        for node in self.children.drain(..) {
+            match node {
+                Node::Element(ref el) if el.name() == name => {
+                    let space = matches!(children.last(),
+                        Some(Node::Other(Event::Text(t))) if is_whitespace(t));
+                    if space {
+                        children.pop();
+                    }
+                    for el in new.take().into_iter().flatten() {
+                        if let Some(ref indent) = indent {
+                            children.push(Node::Other(Event::Text(indent.clone())));
+                        }
+                        children.push(Node::Element(el));
+                    }
+                }
+                other => children.push(other),
+            }
+        }
+        self.children = children;
+        for el in new.into_iter().flatten() {
+            self.insert_child(el, &[]);
+        }
+    }
+
+    /// Set the text of the first child element with the given name, inserting it according to
+    /// `order` if it isn't already present.
+    pub fn set_child_text(&mut self, name: &str, text: &str, order: &[&[u8]]) {
+        match self.child_mut(name.as_bytes()) {
+            Some(child) => child.set_text(text),
+            None => self.insert_child(Element::with_text(name, text), order),
+        }
+    }
+
+    /// The whitespace used to indent child elements, if any.
+    fn indent(&self) -> Option<BytesText<'static>> {
+        self.children.windows(2).find_map(|pair| match pair {
+            [Node::Other(Event::Text(t)), Node::Element(_)] if is_whitespace(t) => Some(t.clone()),
+            _ => None,
+        })
+    }
+
+    /// Get the unescaped text content of the element, including any CDATA sections.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for node in &self.children {
+            match node {
+                Node::Other(Event::Text(t)) => {
+                    let unescaped = t.unescaped()
+                        .map(|v| v.into_owned())
+                        .unwrap_or_else(|_| t.to_vec());
+                    text.push_str(&String::from_utf8_lossy(&unescaped));
+                }
+                Node::Other(Event::CData(t)) => text.push_str(&String::from_utf8_lossy(t)),
+                Node::Element(el) => text.push_str(&el.text()),
+                _ => (),
+            }
+        }
+        text
+    }
+
+    /// Replace the content of the element with the given text.
+    pub fn set_text(&mut self, text: &str) {
+        self.children = vec![Node::Other(Event::Text(BytesText::from_plain_str(text).into_owned()))];
+        if self.end.is_none() {
+            self.end = Some(BytesEnd::owned(self.name().to_vec()));
+        }
+    }
+
+    /// Replace the content of the element with the given text, in a CDATA section if its content
+    /// was in one before.
+    pub fn replace_text(&mut self, text: &str) {
+        let cdata = self.children.iter().any(|node| matches!(node, Node::Other(Event::CData(_))));
+        self.set_text(text);
+        if cdata {
+            let section = BytesText::from_escaped(text.as_bytes().to_vec());
+            self.children = vec![Node::Other(Event::CData(section))];
+        }
+    }
+
+    /// Remove child elements that don't match the predicate, along with the whitespace preceding
+    /// each one, so that removing them doesn't leave blank lines behind.
+    pub fn retain_elements(&mut self, mut keep: impl FnMut(&Element) -> bool) {
+        let mut kept: Vec<Node> = Vec::with_capacity(self.children.len());
+        for node in self.children.drain(..) {
+            if let Node::Element(ref el) = node {
+                if !keep(el) {
+                    if matches!(kept.last(), Some(Node::Other(Event::Text(t))) if is_whitespace(t)) {
+                        kept.pop();
+                    }
+                    continue;
+                }
+            }
+            kept.push(node);
+        }
+        self.children = kept;
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut Writer<W>) -> quick_xml::Result<()> {
+        match self.end {
+            None => writer.write_event(Event::Empty(self.start.to_borrowed())),
+            Some(ref end) => {
+                writer.write_event(Event::Start(self.start.to_borrowed()))?;
+                for node in &self.children {
+                    match node {
+                        Node::Element(el) => el.write(writer)?,
+                        Node::Other(evt) => writer.write_event(evt)?,
+                    }
+                }
+                writer.write_event(Event::End(end.clone()))
+            }
+        }
+    }
+}
+
+/// Read the next event, like `Reader::read_event`.
+///
+/// quick-xml escapes the content of a CDATA section as it reads it, and then writes that escaped
+/// content inside `<![CDATA[...]]>` as-is, which would turn `<` into `&lt;` and so on. This undoes
+/// the escaping, so CDATA events hold the section's actual content.
+pub(crate) fn read_event<'b, R: BufRead>(reader: &mut Reader<R>, buf: &'b mut Vec<u8>)
+    -> quick_xml::Result<Event<'b>>
+{
+    match reader.read_event(buf)? {
+        Event::CData(text) => {
+            let raw = text.unescaped()?.into_owned();
+            Ok(Event::CData(BytesText::from_escaped(raw)))
+        }
+        evt => Ok(evt),
+    }
+}
+
+/// Turn a CDATA event into a text event with the same content, escaped.
+pub(crate) fn cdata_to_text(evt: Event<'_>) -> Event<'_> {
+    match evt {
+        Event::CData(text) => Event::Text(BytesText::from_plain(&text).into_owned()),
+        evt => evt,
+    }
+}
+
+pub(crate) fn is_whitespace(text: &[u8]) -> bool {
+    text.iter().all(u8::is_ascii_whitespace)
+}
+
+/// Assembles events into Elements.
+#[derive(Default)]
+pub(crate) struct TreeBuilder {
+    stack: Vec<Element>,
+}
+
+impl TreeBuilder {
+    /// Add an event to the element being built. Returns the complete element once its end tag is
+    /// seen.
+    ///
+    /// Events must be well-nested, which the XML reader guarantees, and the first event must be a
+    /// Start or Empty.
+    pub fn push(&mut self, evt: Event<'static>) -> Option<Element> {
+        let done = match evt {
+            Event::Start(start) => {
+                self.stack.push(Element { start, children: vec![], end: None });
+                return None;
+            }
+            Event::Empty(start) => Element { start, children: vec![], end: None },
+            Event::End(end) => {
+                let mut el = self.stack.pop().expect("unbalanced end tag");
+                el.end = Some(end);
+                el
+            }
+            other => {
+                if let Some(parent) = self.stack.last_mut() {
+                    parent.children.push(Node::Other(other));
+                }
+                return None;
+            }
+        };
+        match self.stack.last_mut() {
+            Some(parent) => {
+                parent.children.push(Node::Element(done));
+                None
+            }
+            None => Some(done),
+        }
+    }
+
+    /// Whether an element has been started and not yet finished.
+    pub fn building(&self) -> bool {
+        !self.stack.is_empty()
+    }
+}
+
+/// A whole XML document held in memory.
+pub(crate) struct Document {
+    /// Everything before the root element: the XML declaration, comments, whitespace.
+    pub prolog: Vec<Event<'static>>,
+    pub root: Element,
+    /// Anything after the root element.
+    pub epilog: Vec<Event<'static>>,
+}
+
+impl Document {
+    pub fn read<R: BufRead>(source: R) -> anyhow::Result<Document> {
+        let mut reader = Reader::from_reader(source);
+        let mut buf = vec![];
+        let mut prolog = vec![];
+        let mut epilog = vec![];
+        let mut tree = TreeBuilder::default();
+        let mut root = None;
+        let mut in_root = false;
+        loop {
+            let evt = read_event(&mut reader, &mut buf)?.into_owned();
+            buf.clear();
+            match evt {
+                Event::Eof if in_root && root.is_none() => anyhow::bail!("unexpected end of file"),
+                Event::Eof => break,
+                Event::Start(_) | Event::Empty(_) if root.is_none() => {
+                    in_root = true;
+                    root = tree.push(evt);
+                }
+                _ if in_root && root.is_none() => root = tree.push(evt),
+                _ if root.is_none() => prolog.push(evt),
+                _ => epilog.push(evt),
+            }
+        }
+        match root {
+            Some(root) => Ok(Document { prolog, root, epilog }),
+            None => anyhow::bail!("missing root element"),
+        }
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut Writer<W>) -> quick_xml::Result<()> {
+        for evt in &self.prolog {
+            writer.write_event(evt)?;
+        }
+        self.root.write(writer)?;
+        for evt in &self.epilog {
+            writer.write_event(evt)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,362 @@
+use crate::{schema, Timestamp};
+use crate::tree::read_event;
+use anyhow::{anyhow, bail};
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+use std::rc::Rc;
+
+/// A structural problem found in a GPX file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// 1-based line number.
+    pub line: u64,
+    /// 1-based column number, in bytes.
+    pub column: u64,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Stream through a GPX file, yielding any structural errors found.
+///
+/// This checks the order of the top-level elements, that coordinates are in range, that `<time>`
+/// elements hold ISO 8601 timestamps, and that tags are balanced. Malformed XML stops the
+/// validation, as there's no reliable way to continue parsing past it.
+pub fn validate_gpx(reader: impl BufRead) -> impl Iterator<Item = ValidationError> {
+    let lines = Rc::new(RefCell::new(Lines::default()));
+    let counter = LineCounter { inner: reader, offset: 0, lines: Rc::clone(&lines) };
+    Validator {
+        reader: Reader::from_reader(counter),
+        lines,
+        buf: vec![],
+        path: vec![],
+        seen_root: false,
+        last_top_level: None,
+        time_text: None,
+        pending: VecDeque::new(),
+        done: false,
+    }
+}
+
+/// Check that a document is well-formed XML with a single `<gpx>` root element, without looking
+/// at anything inside the root. This is quicker than [`validate_gpx`], and meant for a simple
+/// check of output that was just written.
+pub fn check_well_formed<R: BufRead>(source: R) -> anyhow::Result<()> {
+    let mut reader = Reader::from_reader(source);
+    let mut buf = vec![];
+    let mut depth = 0;
+    let mut seen_root = false;
+    loop {
+        let pos = reader.buffer_position();
+        let evt = read_event(&mut reader, &mut buf)
+            .map_err(|e| anyhow!("malformed XML at byte {}: {}", pos, e))?;
+        match evt {
+            Event::Eof if depth > 0 => bail!("unexpected end of file: <gpx> is not closed"),
+            Event::Eof => break,
+            Event::Start(ref start) | Event::Empty(ref start) if depth == 0 => {
+                if seen_root {
+                    bail!("more than one root element, the second at byte {}", pos);
+                }
+                if start.name() != b"gpx" {
+                    bail!("root element is <{}>, expected <gpx>",
+                        String::from_utf8_lossy(start.name()));
+                }
+                seen_root = true;
+            }
+            Event::Text(ref text) if depth == 0 && !text.iter().all(u8::is_ascii_whitespace) => {
+                bail!("text outside the root element at byte {}", pos);
+            }
+            _ => (),
+        }
+        match evt {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => (),
+        }
+        buf.clear();
+    }
+    if !seen_root {
+        bail!("missing <gpx> root element");
+    }
+    Ok(())
+}
+
+struct Validator<R: BufRead> {
+    reader: Reader<LineCounter<R>>,
+    lines: Rc<RefCell<Lines>>,
+    buf: Vec<u8>,
+    path: Vec<Vec<u8>>,
+    seen_root: bool,
+    /// Index into schema::GPX of the latest top-level element seen.
+    last_top_level: Option<usize>,
+    /// Position and text content of the `<time>` element being read, if any.
+    time_text: Option<(usize, String)>,
+    pending: VecDeque<ValidationError>,
+    done: bool,
+}
+
+impl<R: BufRead> Validator<R> {
+    fn error(&mut self, pos: usize, message: String) {
+        let (line, column) = self.lines.borrow_mut().locate(pos);
+        self.pending.push_back(ValidationError { line, column, message });
+    }
+
+    fn step(&mut self) {
+        self.buf.clear();
+        let pos = self.reader.buffer_position();
+        let evt = match read_event(&mut self.reader, &mut self.buf) {
+            Ok(evt) => evt.into_owned(),
+            Err(e) => {
+                self.error(pos, format!("malformed XML: {}", e));
+                self.done = true;
+                return;
+            }
+        };
+        match evt {
+            Event::Start(ref start) | Event::Empty(ref start) => {
+                self.check_element(pos, start);
+                if let Event::Start(_) = evt {
+                    if start.name() == b"time" {
+                        self.time_text = Some((pos, String::new()));
+                    }
+                    self.path.push(start.name().to_owned());
+                } else if start.name() == b"time" {
+                    self.error(pos, "empty <time> element".to_owned());
+                }
+            }
+            Event::Text(ref text) | Event::CData(ref text) => {
+                if let Some((_, ref mut time)) = self.time_text {
+                    let unescaped = if let Event::Text(_) = evt {
+                        text.unescaped().map(|t| t.into_owned())
+                    } else {
+                        Ok(text.to_vec())
+                    };
+                    match unescaped {
+                        Ok(t) => time.push_str(&String::from_utf8_lossy(&t)),
+                        Err(e) => {
+                            let msg = format!("malformed XML: {}", e);
+                            self.error(pos, msg);
+                        }
+                    }
+                }
+            }
+            Event::End(ref end) => {
+                if end.name() == b"time" {
+                    if let Some((time_pos, time)) = self.time_text.take() {
+                        if Timestamp::parse(&time).is_none() {
+                            self.error(time_pos, format!("malformed timestamp {:?}", time.trim()));
+                        }
+                    }
+                }
+                self.path.pop();
+            }
+            Event::Eof => {
+                if let Some(open) = self.path.last() {
+                    let msg = format!("unexpected end of file: <{}> is not closed",
+                        String::from_utf8_lossy(open));
+                    self.error(pos, msg);
+                } else if !self.seen_root {
+                    self.error(pos, "missing <gpx> root element".to_owned());
+                }
+                self.done = true;
+            }
+            _ => (),
+        }
+    }
+
+    fn check_element(&mut self, pos: usize, start: &BytesStart) {
+        let name = start.name();
+        if self.path.is_empty() {
+            if name != b"gpx" {
+                self.error(pos, format!("root element is <{}>, expected <gpx>",
+                    String::from_utf8_lossy(name)));
+            }
+            self.seen_root = true;
+        } else if self.path == [b"gpx"] {
+            if let Some(order) = schema::GPX.iter().position(|n| *n == name) {
+                match self.last_top_level {
+                    Some(last) if last > order => {
+                        self.error(pos, format!("<{}> must come before <{}>",
+                            String::from_utf8_lossy(name),
+                            String::from_utf8_lossy(schema::GPX[last])));
+                    }
+                    _ => self.last_top_level = Some(order),
+                }
+            }
+        }
+
+        if matches!(name, b"trkpt" | b"wpt" | b"rtept") {
+            let tag = String::from_utf8_lossy(name).into_owned();
+            for &(attr, limit) in &[(&b"lat"[..], 90.), (&b"lon"[..], 180.)] {
+                let value = start.attributes()
+                    .filter_map(Result::ok)
+                    .find(|a| a.key == attr)
+                    .map(|a| String::from_utf8_lossy(&a.value).into_owned());
+                let attr = String::from_utf8_lossy(attr);
+                match value.as_deref().map(|v| v.trim().parse::<f64>()) {
+                    None => self.error(pos, format!("<{}> is missing the {} attribute", tag, attr)),
+                    Some(Err(_)) => self.error(pos, format!("<{}> has invalid {} {:?}",
+                        tag, attr, value.unwrap())),
+                    Some(Ok(v)) if !(-limit ..= limit).contains(&v) => {
+                        self.error(pos, format!("<{}> {} {} is out of range [-{}, {}]",
+                            tag, attr, v, limit, limit));
+                    }
+                    Some(Ok(_)) => (),
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Validator<R> {
+    type Item = ValidationError;
+
+    fn next(&mut self) -> Option<ValidationError> {
+        loop {
+            if let Some(err) = self.pending.pop_front() {
+                return Some(err);
+            }
+            if self.done {
+                return None;
+            }
+            self.step();
+        }
+    }
+}
+
+/// Tracks where lines start, so byte offsets from the XML reader can be turned into line and
+/// column numbers. Only newlines past the last location asked for are kept.
+#[derive(Default)]
+struct Lines {
+    newlines: VecDeque<usize>,
+    line: u64,
+    line_start: usize,
+}
+
+impl Lines {
+    fn locate(&mut self, pos: usize) -> (u64, u64) {
+        while let Some(&nl) = self.newlines.front() {
+            if nl >= pos {
+                break;
+            }
+            self.newlines.pop_front();
+            self.line += 1;
+            self.line_start = nl + 1;
+        }
+        (self.line + 1, (pos.saturating_sub(self.line_start) + 1) as u64)
+    }
+}
+
+/// Reader wrapper that records the offsets of newlines as they're consumed.
+struct LineCounter<R> {
+    inner: R,
+    offset: usize,
+    lines: Rc<RefCell<Lines>>,
+}
+
+impl<R> LineCounter<R> {
+    fn record(&mut self, bytes: &[u8]) {
+        let mut lines = self.lines.borrow_mut();
+        for (i, _) in bytes.iter().enumerate().filter(|(_, &b)| b == b'\n') {
+            lines.newlines.push_back(self.offset + i);
+        }
+        self.offset += bytes.len();
+    }
+}
+
+impl<R: Read> Read for LineCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.record(&buf[.. n]);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for LineCounter<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // The data being consumed is still in the inner buffer, so this doesn't do any I/O.
+        let consumed = match self.inner.fill_buf() {
+            Ok(buf) => buf[.. amt.min(buf.len())].to_vec(),
+            Err(_) => vec![],
+        };
+        self.record(&consumed);
+        self.inner.consume(amt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(gpx: &str) -> Vec<String> {
+        validate_gpx(gpx.as_bytes()).map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn valid() {
+        assert_eq!(errors(r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1" creator="gpxjoin" xmlns="http://www.topografix.com/GPX/1/1">
+    <metadata><time>2021-08-27T18:59:24Z</time></metadata>
+    <wpt lat="47.5" lon="-121.1"/>
+    <trk><trkseg>
+        <trkpt lat="47.543448" lon="-121.096462"><time>2021-08-27T18:59:24.070Z</time></trkpt>
+    </trkseg></trk>
+</gpx>
+"#), Vec::<String>::new());
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(errors(r#"<gpx>
+    <trk>
+        <trkseg>
+            <trkpt lat="91.5" lon="-121.096462"><time>yesterday</time></trkpt>
+            <trkpt lon="12"/>
+        </trkseg>
+    </trk>
+    <metadata/>
+    <wpt lat="1" lon="2"></trk>
+</gpx>
+"#), vec![
+            "4:13: <trkpt> lat 91.5 is out of range [-90, 90]",
+            "4:49: malformed timestamp \"yesterday\"",
+            "5:13: <trkpt> is missing the lat attribute",
+            "8:5: <metadata> must come before <trk>",
+            "9:5: <wpt> must come before <trk>",
+            "9:26: malformed XML: Expecting </wpt> found </trk>",
+        ]);
+    }
+
+    #[test]
+    fn unclosed() {
+        assert_eq!(errors("<gpx>\n<trk>\n"),
+            vec!["2:6: unexpected end of file: <trk> is not closed"]);
+        assert_eq!(errors("<kml></kml>"),
+            vec!["1:1: root element is <kml>, expected <gpx>"]);
+        assert_eq!(errors(""), vec!["1:1: missing <gpx> root element"]);
+    }
+
+    #[test]
+    fn well_formed() {
+        let gpx = "<?xml version=\"1.0\"?>\n<gpx><trk/></gpx>\n";
+        assert!(check_well_formed(gpx.as_bytes()).is_ok());
+        assert!(check_well_formed("<gpx/>".as_bytes()).is_ok());
+        for bad in &["", "<kml/>", "<gpx><trk></gpx>", "<gpx><trk>", "<gpx/><gpx/>", "<gpx/>x",
+            "<gpx><trk/>"]
+        {
+            assert!(check_well_formed(bad.as_bytes()).is_err(), "{:?}", bad);
+        }
+    }
+}
@@ -0,0 +1,400 @@
+//! Zip archives: reading GPX files out of them, and writing the output into one, with its
+//! entries stored without compression.
+
+use crate::inflate::inflate;
+use crate::time::Timestamp;
+use anyhow::{bail, Context as _};
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+/// Version 2.0 of the format, the first with directories, which is all that's needed.
+const VERSION: u16 = 20;
+/// The flag saying that entry names are UTF-8.
+const UTF8_NAMES: u16 = 1 << 11;
+/// Where the CRC and sizes are in a local header, which are filled in after the data's written.
+const LOCAL_CRC_OFFSET: u64 = 14;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+const ENCRYPTED: u16 = 1;
+/// The size of the end of central directory record, not counting the comment after it.
+const END_LEN: usize = 22;
+
+/// Reads the entries of a zip archive, which can be stored or compressed with DEFLATE. Archives
+/// that need the zip64 extensions, or that span several disks, aren't supported.
+pub struct ZipArchive<R: Read + Seek> {
+    source: R,
+    entries: Vec<ArchiveEntry>,
+}
+
+/// An entry as the archive's central directory describes it.
+struct ArchiveEntry {
+    name: String,
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    offset: u32,
+}
+
+impl<R: Read + Seek> ZipArchive<R> {
+    /// Read the archive's central directory, at its end.
+    pub fn new(mut source: R) -> anyhow::Result<Self> {
+        let len = source.seek(SeekFrom::End(0))?;
+        // The end record is followed by a comment of up to 64 KiB.
+        let tail_len = len.min((END_LEN + usize::from(u16::MAX)) as u64);
+        source.seek(SeekFrom::Start(len - tail_len))?;
+        let mut tail = vec![0; tail_len as usize];
+        source.read_exact(&mut tail)?;
+        let end = (0 ..= tail.len().saturating_sub(END_LEN)).rev()
+            .find(|&pos| u32_at(&tail, pos) == END_OF_CENTRAL_DIRECTORY
+                && pos + END_LEN + usize::from(u16_at(&tail, pos + 20)) == tail.len())
+            .context("not a zip archive: there's no end of central directory record")?;
+        let end = &tail[end ..];
+        if u16_at(end, 4) != 0 || u16_at(end, 6) != 0 {
+            bail!("zip archives that span several disks aren't supported");
+        }
+        let count = u16_at(end, 10);
+        let (size, start) = (u32_at(end, 12), u32_at(end, 16));
+        if count == u16::MAX || size == u32::MAX || start == u32::MAX {
+            bail!("zip archives with zip64 extensions aren't supported");
+        }
+        source.seek(SeekFrom::Start(u64::from(start)))?;
+        let mut directory = vec![0; size as usize];
+        source.read_exact(&mut directory).context("the central directory is cut off")?;
+        let mut entries = vec![];
+        let mut pos = 0;
+        for _ in 0 .. count {
+            if directory.len() < pos + 46 || u32_at(&directory, pos) != CENTRAL_HEADER {
+                bail!("invalid zip central directory");
+            }
+            let header = &directory[pos ..];
+            let name_len = usize::from(u16_at(header, 28));
+            let rest_len = usize::from(u16_at(header, 30)) + usize::from(u16_at(header, 32));
+            let name = header.get(46 .. 46 + name_len).context("invalid zip central directory")?;
+            let entry = ArchiveEntry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                flags: u16_at(header, 8),
+                method: u16_at(header, 10),
+                crc: u32_at(header, 16),
+                compressed_size: u32_at(header, 20),
+                size: u32_at(header, 24),
+                offset: u32_at(header, 42),
+            };
+            if entry.compressed_size == u32::MAX || entry.size == u32::MAX
+                || entry.offset == u32::MAX
+            {
+                bail!("zip archives with zip64 extensions aren't supported");
+            }
+            entries.push(entry);
+            pos += 46 + name_len + rest_len;
+        }
+        Ok(ZipArchive { source, entries })
+    }
+
+    /// The names of the entries, in the order they're in the archive. Directories end with `/`.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.name.as_str())
+    }
+
+    /// Read the contents of an entry, decompressing them if they're compressed.
+    pub fn read(&mut self, name: &str) -> anyhow::Result<Vec<u8>> {
+        let entry = self.entries.iter().find(|entry| entry.name == name)
+            .with_context(|| format!("there's no {:?} in the zip archive", name))?;
+        if entry.flags & ENCRYPTED != 0 {
+            bail!("{:?} in the zip archive is encrypted", name);
+        }
+        self.source.seek(SeekFrom::Start(u64::from(entry.offset)))?;
+        let mut header = [0; 30];
+        self.source.read_exact(&mut header)?;
+        if u32_at(&header, 0) != LOCAL_HEADER {
+            bail!("invalid zip local header for {:?}", name);
+        }
+        // The local header's name and extra fields can differ from the central directory's.
+        let skip = i64::from(u16_at(&header, 26)) + i64::from(u16_at(&header, 28));
+        self.source.seek(SeekFrom::Current(skip))?;
+        let mut data = vec![0; entry.compressed_size as usize];
+        self.source.read_exact(&mut data)
+            .with_context(|| format!("{:?} in the zip archive is cut off", name))?;
+        let data = match entry.method {
+            STORED => data,
+            DEFLATED => inflate(&data).with_context(|| format!("failed to decompress {:?}", name))?,
+            method => bail!("{:?} in the zip archive uses compression method {}, which isn't \
+                supported", name, method),
+        };
+        if data.len() != entry.size as usize || crc32(0, &data) != entry.crc {
+            bail!("{:?} in the zip archive is corrupt: its size or CRC doesn't match", name);
+        }
+        Ok(data)
+    }
+}
+
+fn u16_at(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+/// Writes a zip archive, one entry at a time, through its `Write` impl.
+///
+/// The sizes and CRC of an entry go in its header, before the data, so they're filled in by
+/// seeking back once the data's been written. Archives too big to need the zip64 extensions
+/// aren't supported.
+pub struct ZipWriter<W: Write + Seek> {
+    out: W,
+    /// The entries written so far, for the central directory at the end.
+    entries: Vec<Entry>,
+    /// The entry being written.
+    current: Option<Entry>,
+    /// The modification time and date of the entries, in MS-DOS format.
+    modified: (u16, u16),
+}
+
+struct Entry {
+    name: String,
+    offset: u64,
+    crc: u32,
+    size: u64,
+}
+
+impl<W: Write + Seek> ZipWriter<W> {
+    /// Start an archive whose entries are all marked as modified now.
+    pub fn new(out: W) -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let modified = dos_time(Timestamp(now.as_millis() as i64));
+        ZipWriter { out, entries: vec![], current: None, modified }
+    }
+
+    /// Start a new entry, finishing the one before it. What's written next is its contents.
+    pub fn start_file(&mut self, name: &str) -> io::Result<()> {
+        self.finish_file()?;
+        if self.entries.iter().any(|entry| entry.name == name) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("duplicate zip entry {:?}", name)));
+        }
+        let offset = self.out.stream_position()?;
+        let name_len = u16::try_from(name.len()).map_err(|_| too_big("an entry name"))?;
+        let mut header = vec![];
+        header.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&UTF8_NAMES.to_le_bytes());
+        header.extend_from_slice(&STORED.to_le_bytes());
+        header.extend_from_slice(&self.modified.0.to_le_bytes());
+        header.extend_from_slice(&self.modified.1.to_le_bytes());
+        header.extend_from_slice(&[0; 12]); // the CRC and sizes, filled in later
+        header.extend_from_slice(&name_len.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // no extra fields
+        header.extend_from_slice(name.as_bytes());
+        self.out.write_all(&header)?;
+        self.current = Some(Entry { name: name.to_owned(), offset, crc: 0, size: 0 });
+        Ok(())
+    }
+
+    /// Finish the archive by writing its central directory, returning what it was written to.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_file()?;
+        let start = self.out.stream_position()?;
+        let count = u16::try_from(self.entries.len()).map_err(|_| too_big("the entry count"))?;
+        let mut directory = vec![];
+        for entry in &self.entries {
+            directory.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+            directory.extend_from_slice(&VERSION.to_le_bytes()); // made by
+            directory.extend_from_slice(&VERSION.to_le_bytes()); // needed to extract
+            directory.extend_from_slice(&UTF8_NAMES.to_le_bytes());
+            directory.extend_from_slice(&STORED.to_le_bytes());
+            directory.extend_from_slice(&self.modified.0.to_le_bytes());
+            directory.extend_from_slice(&self.modified.1.to_le_bytes());
+            directory.extend_from_slice(&entry.crc.to_le_bytes());
+            // Both the compressed and uncompressed sizes; they were checked to fit when the
+            // entry was finished.
+            directory.extend_from_slice(&(entry.size as u32).to_le_bytes());
+            directory.extend_from_slice(&(entry.size as u32).to_le_bytes());
+            directory.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            // No extra fields or comment, on disk 0, with no file attributes.
+            directory.extend_from_slice(&[0; 12]);
+            let offset = u32::try_from(entry.offset).map_err(|_| too_big("the archive"))?;
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+        let start = u32::try_from(start).map_err(|_| too_big("the archive"))?;
+        let mut end = vec![];
+        end.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        end.extend_from_slice(&[0; 4]); // on disk 0, with the directory also on disk 0
+        end.extend_from_slice(&count.to_le_bytes()); // on this disk
+        end.extend_from_slice(&count.to_le_bytes()); // in all
+        end.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        end.extend_from_slice(&start.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes()); // no comment
+        self.out.write_all(&directory)?;
+        self.out.write_all(&end)?;
+        Ok(self.out)
+    }
+
+    /// Fill in the header of the entry being written, if there is one.
+    fn finish_file(&mut self) -> io::Result<()> {
+        let entry = match self.current.take() {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        let size = u32::try_from(entry.size).map_err(|_| too_big("an entry"))?;
+        let end = self.out.stream_position()?;
+        self.out.seek(SeekFrom::Start(entry.offset + LOCAL_CRC_OFFSET))?;
+        self.out.write_all(&entry.crc.to_le_bytes())?;
+        self.out.write_all(&size.to_le_bytes())?;
+        self.out.write_all(&size.to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(end))?;
+        self.entries.push(entry);
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Write for ZipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let entry = self.current.as_mut().ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput, "no zip entry has been started"))?;
+        let n = self.out.write(buf)?;
+        entry.crc = crc32(entry.crc, &buf[.. n]);
+        entry.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn too_big(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput,
+        format!("{} is too big for a zip archive without zip64", what))
+}
+
+/// A time as the MS-DOS time and date zip archives use, which can't be before 1980.
+fn dos_time(time: Timestamp) -> (u16, u16) {
+    let time = time.max(Timestamp::from_date(1980, 1, 1));
+    let (year, month, day) = time.date();
+    let secs = time.0.rem_euclid(86_400_000) / 1000;
+    let dos_time = (secs / 3600) << 11 | (secs / 60 % 60) << 5 | (secs % 60 / 2);
+    let dos_date = (year - 1980).min(127) << 9 | month << 5 | day;
+    (dos_time as u16, dos_date as u16)
+}
+
+/// Continue the CRC-32 of some earlier bytes, as zip archives use, over some more bytes.
+pub(crate) fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = crc32_table();
+    let mut crc = !crc;
+    for &byte in bytes {
+        crc = TABLE[usize::from(crc as u8 ^ byte)] ^ (crc >> 8);
+    }
+    !crc
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    /// The names and contents of the entries of an archive.
+    fn entries(data: Vec<u8>) -> Vec<(String, Vec<u8>)> {
+        let mut zip = ZipArchive::new(Cursor::new(data)).unwrap();
+        let names = zip.names().map(str::to_owned).collect::<Vec<_>>();
+        names.into_iter()
+            .map(|name| {
+                let contents = zip.read(&name).unwrap();
+                (name, contents)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn check_crc() {
+        assert_eq!(crc32(0, b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xCBF43926);
+        assert_eq!(crc32(0, b""), 0);
+    }
+
+    #[test]
+    fn write_archive() {
+        let gpx = "<gpx><trk><trkseg><trkpt lat=\"1\" lon=\"2\"/></trkseg></trk></gpx>";
+        let mut joined = vec![];
+        crate::join_gpx(&mut [gpx.as_bytes(), gpx.as_bytes()], &mut joined, &Default::default())
+            .unwrap();
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("merged.gpx").unwrap();
+        // Written in pieces, like the join writes it.
+        for chunk in joined.chunks(7) {
+            zip.write_all(chunk).unwrap();
+        }
+        zip.start_file("dir/empty.gpx").unwrap();
+        assert!(zip.start_file("merged.gpx").is_err());
+        let data = zip.finish().unwrap().into_inner();
+        assert_eq!(entries(data), vec![
+            ("merged.gpx".to_owned(), joined),
+            ("dir/empty.gpx".to_owned(), vec![]),
+        ]);
+    }
+
+    #[test]
+    fn no_entry() {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        assert!(zip.write_all(b"<gpx/>").is_err());
+        assert_eq!(entries(zip.finish().unwrap().into_inner()), vec![]);
+    }
+
+    #[test]
+    fn modified() {
+        let time = Timestamp::parse("2021-08-27T18:30:15Z").unwrap();
+        assert_eq!(dos_time(time), (18 << 11 | 30 << 5 | 7, 41 << 9 | 8 << 5 | 27));
+        assert_eq!(dos_time(Timestamp(0)), (0, 1 << 5 | 1));
+    }
+
+    #[test]
+    fn read_archive() {
+        // Made with Python's zipfile, with some entries stored and some compressed.
+        let mut zip = ZipArchive::new(Cursor::new(&include_bytes!("../testdata/tracks.zip")[..]))
+            .unwrap();
+        assert_eq!(zip.names().collect::<Vec<_>>(),
+            ["b.gpx", "a.gpx", "README.txt", "more/", "more/c.GPX", "more/notes.md"]);
+        for name in &["a.gpx", "b.gpx", "more/c.GPX"] {
+            let data = String::from_utf8(zip.read(name).unwrap()).unwrap();
+            assert!(data.starts_with("<?xml"), "{}", data);
+            assert!(data.ends_with("</gpx>\n"), "{}", data);
+        }
+        assert_eq!(zip.read("more/notes.md").unwrap(), b"# notes\n");
+        assert!(zip.read("c.gpx").is_err());
+    }
+
+    #[test]
+    fn read_corrupt() {
+        assert!(ZipArchive::new(Cursor::new(b"<gpx></gpx>")).is_err());
+        let mut data = include_bytes!("../testdata/tracks.zip").to_vec();
+        // Somewhere in the middle of a.gpx's contents.
+        let pos = data.windows(5).position(|w| w == b"<name").unwrap() + 1000;
+        data[pos] ^= 1;
+        let mut zip = ZipArchive::new(Cursor::new(data)).unwrap();
+        assert!(zip.read("b.gpx").is_ok());
+        assert!(zip.read("a.gpx").is_err());
+    }
+}